@@ -0,0 +1,128 @@
+//! Fluent construction of packets
+use crate::code::{DataType, Magic, Opcode, Status};
+use crate::error::InvalidPacket;
+use crate::frame::FrameInfo;
+use crate::packet::{Extras, Packet, PacketHeader};
+use alloc::vec::Vec;
+use bytes::Bytes;
+
+/// Builder returned by `Packet::builder`, filling the header consistently with the body;
+/// every field but the opcode defaults to empty or zero.
+///
+/// # Examples
+/// ```rust
+/// use memcached_proto::{Extras, Opcode, Packet};
+///
+/// let p = Packet::builder(Opcode::Set)
+///     .key("Hello")
+///     .value("World")
+///     .extras(Extras::Store { flags: 0, expiration: 60 })
+///     .opaque(42)
+///     .request()
+///     .unwrap();
+/// assert_eq!(p.header.body_len, 18);
+/// assert!(Packet::builder(Opcode::Increment).key("n").request().is_err());
+/// ```
+#[derive(Debug)]
+pub struct PacketBuilder {
+    opcode: Opcode,
+    vbucket_id: u16,
+    opaque: u32,
+    cas: u64,
+    data_type: DataType,
+    framing_extras: Vec<FrameInfo>,
+    extras: Extras,
+    key: Bytes,
+    val: Bytes,
+}
+
+impl Packet {
+    pub fn builder(opcode: Opcode) -> PacketBuilder {
+        PacketBuilder {
+            opcode,
+            vbucket_id: 0,
+            opaque: 0,
+            cas: 0,
+            data_type: DataType::RAW,
+            framing_extras: Vec::new(),
+            extras: Extras::None,
+            key: Bytes::new(),
+            val: Bytes::new(),
+        }
+    }
+}
+
+impl PacketBuilder {
+    pub fn key(mut self, key: impl Into<Bytes>) -> Self {
+        self.key = key.into();
+        self
+    }
+
+    pub fn value(mut self, val: impl Into<Bytes>) -> Self {
+        self.val = val.into();
+        self
+    }
+
+    pub fn extras(mut self, extras: Extras) -> Self {
+        self.extras = extras;
+        self
+    }
+
+    pub fn framing_extras(mut self, framing_extras: Vec<FrameInfo>) -> Self {
+        self.framing_extras = framing_extras;
+        self
+    }
+
+    pub fn opaque(mut self, opaque: u32) -> Self {
+        self.opaque = opaque;
+        self
+    }
+
+    pub fn cas(mut self, cas: u64) -> Self {
+        self.cas = cas;
+        self
+    }
+
+    /// Only used by `request`
+    pub fn vbucket_id(mut self, vbucket_id: u16) -> Self {
+        self.vbucket_id = vbucket_id;
+        self
+    }
+
+    pub fn data_type(mut self, data_type: DataType) -> Self {
+        self.data_type = data_type;
+        self
+    }
+
+    /// Build a request, checked with `Packet::validate`
+    pub fn request(self) -> Result<Packet, InvalidPacket> {
+        let vbucket_id = self.vbucket_id;
+        self.build(Magic::Request, vbucket_id)
+    }
+
+    /// Build a response with `status`, checked with `Packet::validate`
+    pub fn response(self, status: Status) -> Result<Packet, InvalidPacket> {
+        self.build(Magic::Response, status.into())
+    }
+
+    fn build(self, magic: Magic, vbucket_id_or_status: u16) -> Result<Packet, InvalidPacket> {
+        let header = PacketHeader {
+            magic,
+            opcode: self.opcode,
+            framing_extras_len: 0,
+            key_len: self.key.len() as u16,
+            extras_len: self.extras.len() as u8,
+            data_type: self.data_type,
+            vbucket_id_or_status,
+            body_len: (self.extras.len() + self.key.len() + self.val.len()) as u32,
+            opaque: self.opaque,
+            cas: self.cas,
+        };
+        let mut packet = Packet::new(header, self.extras, self.key, self.val);
+        if !self.framing_extras.is_empty() {
+            packet = packet.with_framing_extras(self.framing_extras)?;
+        }
+        packet.validate()?;
+        Ok(packet)
+    }
+}
@@ -1,9 +1,20 @@
-use super::AuthResponse;
-use crate::Result;
+use super::{
+    align, dedup_keys, found, is_conflict, not_supported, ok_response, per_key, AuthResponse,
+    DoctorReport, ItemResult, ValueMeta,
+};
+use crate::{Expiration, FromMemcachedValue, Packet, PacketRef, Result, ToMemcachedValue};
 use async_trait::async_trait;
 use bytes::Bytes;
+use futures_lite::{stream, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Stream};
 use semver::Version;
 use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::io;
+use std::pin::Pin;
+use std::time::Instant;
+
+/// Hits of `get_multi_stream` as `(key, value, flags)`
+pub type GetStream<'a> = Pin<Box<dyn Stream<Item = Result<(Bytes, Bytes, u32)>> + Send + 'a>>;
 
 pub trait Proto:
     Operation + MultiOperation + ServerOperation + NoReplyOperation + CasOperation + AuthOperation
@@ -51,6 +62,209 @@ pub trait Operation {
     async fn append(&mut self, key: &[u8], val: &[u8]) -> Result<()>;
     async fn prepend(&mut self, key: &[u8], val: &[u8]) -> Result<()>;
     async fn touch(&mut self, key: &[u8], expiration: u32) -> Result<()>;
+    /// Get into `buf`, which is cleared first so it can be reused across calls;
+    /// a miss returns `None` instead of an error.
+    async fn get_into(&mut self, key: &[u8], buf: &mut Vec<u8>) -> Result<Option<ValueMeta>>;
+    /// Get and set a new expiration (GAT); the default touches then gets in two round trips,
+    /// a client owning the connection sends a single GAT instead.
+    async fn get_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, u32)>
+    where
+        Self: Send,
+    {
+        self.touch(key, expiration).await?;
+        self.get(key).await
+    }
+    /// `get_and_touch` also returning the key (GATK)
+    async fn getk_and_touch(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<(Bytes, Bytes, u32)>
+    where
+        Self: Send,
+    {
+        self.touch(key, expiration).await?;
+        self.getk(key).await
+    }
+
+    /// Whether `key` is stored; the default reads the value with `get`,
+    /// a client owning the connection can probe without transferring it.
+    async fn exists(&mut self, key: &[u8]) -> Result<bool>
+    where
+        Self: Send,
+    {
+        found(self.get(key).await).map(|v| v.is_some())
+    }
+
+    /// `set` of a value of `len` bytes read from `val`, an early end of `val` fails with `UnexpectedEof`;
+    /// the default buffers the value, clients writing to the connection directly stream it
+    /// with `PacketRef::write_streaming` instead.
+    async fn set_from_reader<R>(
+        &mut self,
+        key: &[u8],
+        val: R,
+        len: u32,
+        flags: u32,
+        expiration: u32,
+    ) -> Result<()>
+    where
+        Self: Send + Sized,
+        R: AsyncRead + Unpin + Send,
+    {
+        let mut buf = Vec::with_capacity(len as usize);
+        val.take(len as u64).read_to_end(&mut buf).await?;
+        if buf.len() != len as usize {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        self.set(key, &buf, flags, expiration).await
+    }
+
+    /// `get` copying the value to `w`, returns the flags; the default buffers the value,
+    /// clients reading from the connection directly stream it with `Packet::read_streaming` instead.
+    async fn get_into_writer<W>(&mut self, key: &[u8], w: &mut W) -> Result<u32>
+    where
+        Self: Send + Sized,
+        W: AsyncWrite + Unpin + Send,
+    {
+        let (value, flags) = self.get(key).await?;
+        w.write_all(&value).await?;
+        Ok(flags)
+    }
+
+    /// `set` with an `Expiration`, failing with `InvalidInput` before anything is sent
+    /// if the server would misread it
+    async fn set_exp(&mut self, key: &[u8], val: &[u8], flags: u32, exp: Expiration) -> Result<()>
+    where
+        Self: Send,
+    {
+        self.set(key, val, flags, u32::try_from(exp)?).await
+    }
+
+    /// `add` with an `Expiration`, see `set_exp`
+    async fn add_exp(&mut self, key: &[u8], val: &[u8], flags: u32, exp: Expiration) -> Result<()>
+    where
+        Self: Send,
+    {
+        self.add(key, val, flags, u32::try_from(exp)?).await
+    }
+
+    /// `replace` with an `Expiration`, see `set_exp`
+    async fn replace_exp(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        flags: u32,
+        exp: Expiration,
+    ) -> Result<()>
+    where
+        Self: Send,
+    {
+        self.replace(key, val, flags, u32::try_from(exp)?).await
+    }
+
+    /// `increment` with an `Expiration`, see `set_exp`
+    async fn increment_exp(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        exp: Expiration,
+    ) -> Result<u64>
+    where
+        Self: Send,
+    {
+        self.increment(key, amount, initial, u32::try_from(exp)?)
+            .await
+    }
+
+    /// `decrement` with an `Expiration`, see `set_exp`
+    async fn decrement_exp(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        exp: Expiration,
+    ) -> Result<u64>
+    where
+        Self: Send,
+    {
+        self.decrement(key, amount, initial, u32::try_from(exp)?)
+            .await
+    }
+
+    /// `touch` with an `Expiration`, see `set_exp`
+    async fn touch_exp(&mut self, key: &[u8], exp: Expiration) -> Result<()>
+    where
+        Self: Send,
+    {
+        self.touch(key, u32::try_from(exp)?).await
+    }
+
+    /// `get_and_touch` with an `Expiration`, see `set_exp`
+    async fn get_and_touch_exp(&mut self, key: &[u8], exp: Expiration) -> Result<(Bytes, u32)>
+    where
+        Self: Send,
+    {
+        self.get_and_touch(key, u32::try_from(exp)?).await
+    }
+
+    /// `get` returning `None` on a miss instead of a `KeyNotFound` error
+    async fn get_opt(&mut self, key: &[u8]) -> Result<Option<(Bytes, u32)>>
+    where
+        Self: Send,
+    {
+        found(self.get(key).await)
+    }
+
+    /// `getk` returning `None` on a miss instead of a `KeyNotFound` error
+    async fn getk_opt(&mut self, key: &[u8]) -> Result<Option<(Bytes, Bytes, u32)>>
+    where
+        Self: Send,
+    {
+        found(self.getk(key).await)
+    }
+
+    /// `get` converted to `T`, see `FromMemcachedValue`
+    async fn get_as<T>(&mut self, key: &[u8]) -> Result<T>
+    where
+        Self: Send + Sized,
+        T: FromMemcachedValue,
+    {
+        let (value, flags) = self.get(key).await?;
+        T::from_value(value, flags)
+    }
+
+    /// `set` of `value` with the flags marking its type, see `ToMemcachedValue`
+    async fn set_value<T>(&mut self, key: &[u8], value: &T, expiration: u32) -> Result<()>
+    where
+        Self: Send + Sized,
+        T: ToMemcachedValue + Sync + ?Sized,
+    {
+        self.set(key, &value.to_value(), value.flags(), expiration)
+            .await
+    }
+
+    /// `set` of `value` serialized as JSON, flagged with `JSON_FLAGS`
+    #[cfg(feature = "json")]
+    async fn set_json<T>(&mut self, key: &[u8], value: &T, expiration: u32) -> Result<()>
+    where
+        Self: Send + Sized,
+        T: serde::Serialize + Sync + ?Sized,
+    {
+        let val = crate::value::to_json(value)?;
+        self.set(key, &val, crate::JSON_FLAGS, expiration).await
+    }
+
+    /// `get` of a value stored by `set_json`
+    #[cfg(feature = "json")]
+    async fn get_json<T>(&mut self, key: &[u8]) -> Result<T>
+    where
+        Self: Send + Sized,
+        T: serde::de::DeserializeOwned,
+    {
+        let (value, flags) = self.get(key).await?;
+        crate::value::from_json(&value, flags)
+    }
 }
 
 #[async_trait]
@@ -99,6 +313,74 @@ pub trait CasOperation {
     async fn append_cas(&mut self, key: &[u8], val: &[u8], cas: u64) -> Result<u64>;
     async fn prepend_cas(&mut self, key: &[u8], val: &[u8], cas: u64) -> Result<u64>;
     async fn touch_cas(&mut self, key: &[u8], expiration: u32, cas: u64) -> Result<u64>;
+    /// Delete only if the item's CAS is still `cas`, fails with `KeyExists` otherwise;
+    /// the default fails with `NotSupported`, reading the CAS before a plain delete would race.
+    async fn delete_cas(&mut self, _key: &[u8], _cas: u64) -> Result<()> {
+        Err(not_supported())
+    }
+    /// `get_and_touch` also returning the CAS, the default calls `touch_cas` then `get_cas`
+    async fn get_and_touch_cas(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<(Bytes, u32, u64)>
+    where
+        Self: Send,
+    {
+        self.touch_cas(key, expiration, 0).await?;
+        self.get_cas(key).await
+    }
+    /// `getk_and_touch` also returning the CAS, the default calls `touch_cas` then `getk_cas`
+    async fn getk_and_touch_cas(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<(Bytes, Bytes, u32, u64)>
+    where
+        Self: Send,
+    {
+        self.touch_cas(key, expiration, 0).await?;
+        self.getk_cas(key).await
+    }
+
+    /// Read-modify-write loop: `f` gets the current value (`None` on a miss) and returns the new
+    /// one, stored with `set_cas` (`add_cas` on a miss) and the current flags; if another client
+    /// got there first, reads again and retries up to `max_retries` times.
+    /// Returns the new CAS, or `None` if `f` returned `None` and nothing was stored.
+    async fn update_with<F>(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+        max_retries: u32,
+        mut f: F,
+    ) -> Result<Option<u64>>
+    where
+        Self: Send + Sized,
+        F: FnMut(Option<Bytes>) -> Option<Bytes> + Send,
+    {
+        let mut retries = 0;
+        loop {
+            let current = found(self.get_cas(key).await)?;
+            let (value, flags, cas) = match current {
+                Some((value, flags, cas)) => (Some(value), flags, Some(cas)),
+                None => (None, 0, None),
+            };
+            let new = match f(value) {
+                Some(new) => new,
+                None => return Ok(None),
+            };
+            // a server running without CAS answers hits with a CAS of 0
+            let stored = match cas {
+                Some(cas) => self.set_cas(key, &new, flags, expiration, cas).await,
+                None => self.add_cas(key, &new, flags, expiration).await,
+            };
+            match stored {
+                Ok(cas) => return Ok(Some(cas)),
+                Err(e) if retries < max_retries && is_conflict(&e) => retries += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -108,17 +390,184 @@ pub trait ServerOperation {
     async fn noop(&mut self) -> Result<()>;
     async fn version(&mut self) -> Result<Version>;
     async fn stat(&mut self) -> Result<BTreeMap<String, String>>;
+    /// Set the logging verbosity of the server, the default fails with `NotSupported`
+    async fn verbosity(&mut self, _level: u32) -> Result<()> {
+        Err(not_supported())
+    }
+
+    /// Check connectivity, version and server clock, collecting every failure in the report
+    async fn doctor(&mut self) -> DoctorReport
+    where
+        Self: Send,
+    {
+        let mut report = DoctorReport::default();
+        let start = Instant::now();
+        report.noop(start, self.noop().await);
+        if report.round_trip.is_some() {
+            report.version(self.version().await);
+            report.stat(self.stat().await);
+        }
+        report
+    }
 }
 
 #[async_trait]
 pub trait MultiOperation {
     async fn set_multi(&mut self, kv: BTreeMap<&[u8], (&[u8], u32, u32)>) -> Result<()>;
     async fn delete_multi(&mut self, keys: &[&[u8]]) -> Result<()>;
+    /// Touch every key of `keys`, missing keys are ignored; the default calls `touch`
+    /// for every key, a client owning the connection pipelines the touches behind a `NoOp`
+    /// (Touch has no quiet opcode, the header-only responses are drained).
+    async fn touch_multi(&mut self, keys: &[&[u8]], expiration: u32) -> Result<()>
+    where
+        Self: Operation + Send + Sized,
+    {
+        for key in keys {
+            found(self.touch(key, expiration).await)?;
+        }
+        Ok(())
+    }
     async fn increment_multi<'a>(
         &mut self,
         kv: HashMap<&'a [u8], (u64, u64, u32)>,
     ) -> Result<HashMap<&'a [u8], u64>>;
     async fn get_multi(&mut self, keys: &[&[u8]]) -> Result<HashMap<Bytes, (Bytes, u32)>>;
+    /// `get_multi` also returning the CAS of every item, for batched optimistic updates;
+    /// the default calls `get_cas` for every key, a client owning the connection pipelines GetKQs.
+    async fn get_multi_cas(&mut self, keys: &[&[u8]]) -> Result<HashMap<Bytes, (Bytes, u32, u64)>>
+    where
+        Self: CasOperation + Send + Sized,
+    {
+        let mut items = HashMap::new();
+        for key in dedup_keys(keys) {
+            if let Some(item) = found(self.get_cas(key).await)? {
+                items.insert(Bytes::copy_from_slice(key), item);
+            }
+        }
+        Ok(items)
+    }
+    /// `(key, value, flags)` of every hit of `keys`, misses are left out; the default
+    /// runs `get_multi` first, a client owning the connection pipelines GetKQs followed by
+    /// a `NoOp` and yields every hit as its response arrives.
+    fn get_multi_stream<'a>(&'a mut self, keys: &'a [&'a [u8]]) -> GetStream<'a>
+    where
+        Self: Send,
+    {
+        // the hits once `get_multi` returned
+        let hits: Option<std::vec::IntoIter<Result<(Bytes, Bytes, u32)>>> = None;
+        Box::pin(stream::unfold((self, hits), move |(client, hits)| async move {
+            let mut hits = match hits {
+                Some(hits) => hits,
+                None => match client.get_multi(keys).await {
+                    Ok(found) => found
+                        .into_iter()
+                        .map(|(key, (value, flags))| Ok((key, value, flags)))
+                        .collect::<Vec<_>>()
+                        .into_iter(),
+                    Err(e) => vec![Err(e)].into_iter(),
+                },
+            };
+            let hit = hits.next()?;
+            Some((hit, (client, Some(hits))))
+        }))
+    }
+    /// `get_multi` returning one entry per element of `keys`, in the same order,
+    /// `None` for misses; duplicate keys are fetched once and get the same entry.
+    async fn get_multi_ordered(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<(Bytes, u32)>>>
+    where
+        Self: Send,
+    {
+        let found = self.get_multi(&dedup_keys(keys)).await?;
+        Ok(align(keys, &found))
+    }
+    /// `get_multi` with an outcome for every requested key, hits, misses and per-key errors alike;
+    /// only transport failures fail the whole call. The default calls `get_cas` for every key.
+    async fn get_multi_detailed(&mut self, keys: &[&[u8]]) -> Result<HashMap<Bytes, ItemResult>>
+    where
+        Self: CasOperation + Send + Sized,
+    {
+        let mut items = HashMap::new();
+        for key in dedup_keys(keys) {
+            let item = match self.get_cas(key).await {
+                Ok((value, flags, cas)) => ItemResult::Hit { value, flags, cas },
+                Err(crate::Error::Proto(e)) => match e.status() {
+                    crate::Status::KeyNotFound => ItemResult::Miss,
+                    _ => ItemResult::Error(e),
+                },
+                Err(e) => return Err(e),
+            };
+            items.insert(Bytes::copy_from_slice(key), item);
+        }
+        Ok(items)
+    }
+    /// Get and touch every key of `keys`, missing keys are left out; the default calls
+    /// `get_and_touch` for every key, a client owning the connection pipelines quiet GATKQs.
+    async fn get_and_touch_multi(
+        &mut self,
+        keys: &[&[u8]],
+        expiration: u32,
+    ) -> Result<HashMap<Bytes, (Bytes, u32)>>
+    where
+        Self: Operation + Send + Sized,
+    {
+        let mut items = HashMap::new();
+        for key in dedup_keys(keys) {
+            if let Some(item) = found(self.get_and_touch(key, expiration).await)? {
+                items.insert(Bytes::copy_from_slice(key), item);
+            }
+        }
+        Ok(items)
+    }
+    /// Store `(val, flags, expiration, cas)` of every key, a `cas` of 0 always stores;
+    /// returns the new CAS, or the error response, of every key, only I/O errors fail the call.
+    /// The default stores the keys one by one with `set_cas`, a client owning the connection
+    /// can pipeline plain Sets (only their responses carry the new CAS) behind a `NoOp` instead.
+    async fn set_multi_cas<'a>(
+        &mut self,
+        kv: BTreeMap<&'a [u8], (&[u8], u32, u32, u64)>,
+    ) -> Result<BTreeMap<&'a [u8], Result<u64>>>
+    where
+        Self: CasOperation + Send + Sized,
+    {
+        let mut stored = BTreeMap::new();
+        for (key, (val, flags, expiration, cas)) in kv {
+            let result = self.set_cas(key, val, flags, expiration, cas).await;
+            stored.insert(key, per_key(result)?);
+        }
+        Ok(stored)
+    }
+    /// `set_multi_cas` with Replaces, the default calls `replace_cas` for every key
+    async fn replace_multi<'a>(
+        &mut self,
+        kv: BTreeMap<&'a [u8], (&[u8], u32, u32, u64)>,
+    ) -> Result<BTreeMap<&'a [u8], Result<u64>>>
+    where
+        Self: CasOperation + Send + Sized,
+    {
+        let mut stored = BTreeMap::new();
+        for (key, (val, flags, expiration, cas)) in kv {
+            let result = self.replace_cas(key, val, flags, expiration, cas).await;
+            stored.insert(key, per_key(result)?);
+        }
+        Ok(stored)
+    }
+    /// Delete every key guarded by its CAS, a `cas` of 0 always deletes; returns the outcome
+    /// of every key, only I/O errors fail the call. The default calls `delete_cas` for every key,
+    /// a client owning the connection pipelines plain Deletes behind a `NoOp` instead.
+    async fn delete_multi_cas<'a>(
+        &mut self,
+        kv: BTreeMap<&'a [u8], u64>,
+    ) -> Result<BTreeMap<&'a [u8], Result<()>>>
+    where
+        Self: CasOperation + Send + Sized,
+    {
+        let mut deleted = BTreeMap::new();
+        for (key, cas) in kv {
+            let result = self.delete_cas(key, cas).await;
+            deleted.insert(key, per_key(result)?);
+        }
+        Ok(deleted)
+    }
 }
 
 #[async_trait]
@@ -161,6 +610,31 @@ pub trait NoReplyOperation {
     ) -> Result<()>;
     async fn append_noreply(&mut self, key: &[u8], val: &[u8]) -> Result<()>;
     async fn prepend_noreply(&mut self, key: &[u8], val: &[u8]) -> Result<()>;
+    /// Touch has no quiet opcode: this sends a Touch without waiting, and its header-only
+    /// response is discarded when a later command reads its own, so a stream of touches
+    /// followed by `noop` costs a single round trip.
+    /// The default waits for the response of a plain `touch`, ignoring an error status.
+    async fn touch_noreply(&mut self, key: &[u8], expiration: u32) -> Result<()>
+    where
+        Self: Operation + Send + Sized,
+    {
+        per_key(self.touch(key, expiration).await).map(drop)
+    }
+    /// FlushQ, the default waits for the response of a plain `flush`
+    async fn flush_noreply(&mut self, expiration: u32) -> Result<()>
+    where
+        Self: ServerOperation + Send + Sized,
+    {
+        self.flush(expiration).await
+    }
+    /// QuitQ, the server closes the connection without answering;
+    /// the default waits for the response of a plain `quit`
+    async fn quit_noreply(&mut self) -> Result<()>
+    where
+        Self: ServerOperation + Send + Sized,
+    {
+        self.quit().await
+    }
 }
 
 #[async_trait]
@@ -169,3 +643,33 @@ pub trait AuthOperation {
     async fn auth_start(&mut self, mech: &str, init: &[u8]) -> Result<AuthResponse>;
     async fn auth_continue(&mut self, mech: &str, data: &[u8]) -> Result<AuthResponse>;
 }
+
+#[async_trait]
+pub trait RawOperation {
+    /// Send `packet` as is, with an opaque of the connection's choosing, and return the response;
+    /// for vendor-specific or newer opcodes, errors are reported in the response status.
+    async fn execute_raw(&mut self, packet: Packet) -> Result<Packet>;
+    /// Pipelined `execute_raw` followed by a `NoOp`; returns the responses in wire order,
+    /// quiet commands that succeeded have none.
+    async fn execute_raw_multi(&mut self, packets: Vec<Packet>) -> Result<Vec<Packet>>;
+    /// Send `packet` with the connection's opaque and return its response,
+    /// unlike `execute_raw` a status other than `NoError` is returned as an error;
+    /// the default copies `packet` into `execute_raw`.
+    async fn send_packet(&mut self, packet: PacketRef<'_>) -> Result<Packet>
+    where
+        Self: Send,
+    {
+        let request = packet.to_packet();
+        ok_response(self.execute_raw(request).await?)
+    }
+    /// Send `packet` with the connection's opaque without waiting for a response;
+    /// any response is discarded, as with the noreply operations. The default waits for
+    /// the response of `execute_raw`, a client owning the connection only writes `packet`.
+    async fn send_packet_nowait(&mut self, packet: PacketRef<'_>) -> Result<()>
+    where
+        Self: Send,
+    {
+        let request = packet.to_packet();
+        self.execute_raw(request).await.map(drop)
+    }
+}
@@ -0,0 +1,498 @@
+use super::{r#async, sync, ValueMeta};
+use crate::error::ProtoError;
+use crate::{Result, Status};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+/// Flag bit marking a manifest of `Chunked`, not to be used by the application
+pub const CHUNKED: u32 = 1 << 6;
+
+const MANIFEST_LEN: usize = 8 + 4 + 8;
+/// Longest key the server accepts
+const MAX_KEY_LEN: usize = 250;
+
+/// Chunks of one stored value: `count` chunks under `<key>:<generation>:<n>`, `len` bytes in all;
+/// every store draws a new generation so concurrent writers never mix their chunks.
+struct Manifest {
+    generation: u64,
+    count: u32,
+    len: u64,
+}
+
+impl Manifest {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(MANIFEST_LEN);
+        buf.extend_from_slice(&self.generation.to_be_bytes());
+        buf.extend_from_slice(&self.count.to_be_bytes());
+        buf.extend_from_slice(&self.len.to_be_bytes());
+        buf
+    }
+
+    fn decode(value: &[u8]) -> Result<Self> {
+        if value.len() != MANIFEST_LEN {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidData, "corrupt chunk manifest").into(),
+            );
+        }
+        let mut generation = [0u8; 8];
+        let mut count = [0u8; 4];
+        let mut len = [0u8; 8];
+        generation.copy_from_slice(&value[..8]);
+        count.copy_from_slice(&value[8..12]);
+        len.copy_from_slice(&value[12..]);
+        Ok(Self {
+            generation: u64::from_be_bytes(generation),
+            count: u32::from_be_bytes(count),
+            len: u64::from_be_bytes(len),
+        })
+    }
+
+    fn keys(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        (0..self.count)
+            .map(|n| {
+                let mut k = key.to_vec();
+                k.extend_from_slice(format!(":{:016x}:{}", self.generation, n).as_bytes());
+                k
+            })
+            .collect()
+    }
+
+    /// `keys` of a new value, failing with `InvalidInput` if one is longer than the server accepts
+    fn checked_keys(&self, key: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let keys = self.keys(key);
+        // the last key has the most digits
+        if keys.last().map_or(0, Vec::len) > MAX_KEY_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "key too long to derive the keys of its chunks",
+            )
+            .into());
+        }
+        Ok(keys)
+    }
+
+    /// Concatenation of the chunks, a missing chunk (evicted or expired) makes the whole value a miss
+    fn assemble(
+        &self,
+        keys: &[Vec<u8>],
+        mut chunks: HashMap<Bytes, (Bytes, u32)>,
+    ) -> Result<Bytes> {
+        let mut value = BytesMut::with_capacity(self.len as usize);
+        for k in keys {
+            let (chunk, _) = chunks.remove(&k[..]).ok_or_else(|| {
+                ProtoError::from_status(
+                    Status::KeyNotFound,
+                    Some(format!("missing chunk {}", String::from_utf8_lossy(k))),
+                )
+            })?;
+            value.extend_from_slice(&chunk);
+        }
+        if value.len() as u64 != self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunks don't match their manifest",
+            )
+            .into());
+        }
+        Ok(value.freeze())
+    }
+}
+
+fn refs(keys: &[Vec<u8>]) -> Vec<&[u8]> {
+    keys.iter().map(Vec::as_slice).collect()
+}
+
+/// Client storing the values longer than `chunk_size` as chunks under derived keys,
+/// written before a manifest stored under the key itself with the `CHUNKED` flag bit,
+/// so that values above the server item size limit can be stored; reads reassemble them
+/// and fail with `KeyNotFound` if any chunk is gone.
+///
+/// Chunks of a value that is overwritten or deleted are left to expire or be evicted.
+/// `append`/`prepend`/`increment`/`decrement` are passed on as is, they would corrupt a manifest.
+#[derive(Debug)]
+pub struct Chunked<C> {
+    inner: C,
+    chunk_size: usize,
+}
+
+impl<C> Chunked<C> {
+    /// # Panics
+    /// if `chunk_size` is 0
+    pub fn new(inner: C, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunks need at least one byte");
+        Self { inner, chunk_size }
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn split<'a>(&self, value: &'a [u8]) -> Option<(Manifest, Vec<&'a [u8]>)> {
+        if value.len() <= self.chunk_size {
+            return None;
+        }
+        let chunks: Vec<&[u8]> = value.chunks(self.chunk_size).collect();
+        let manifest = Manifest {
+            generation: fastrand::u64(..),
+            count: chunks.len() as u32,
+            len: value.len() as u64,
+        };
+        Some((manifest, chunks))
+    }
+}
+
+impl<C: r#async::Operation + r#async::MultiOperation + Send> Chunked<C> {
+    /// Value to store under `key` itself: `value`, or the manifest of its chunks once they are stored
+    async fn store_chunks<'a>(
+        &mut self,
+        key: &[u8],
+        value: &'a [u8],
+        flags: u32,
+        expiration: u32,
+    ) -> Result<(Cow<'a, [u8]>, u32)> {
+        let (manifest, chunks) = match self.split(value) {
+            Some(split) => split,
+            None => return Ok((Cow::Borrowed(value), flags)),
+        };
+        let keys = manifest.checked_keys(key)?;
+        let kv: BTreeMap<&[u8], (&[u8], u32, u32)> = keys
+            .iter()
+            .zip(chunks)
+            .map(|(k, chunk)| (&k[..], (chunk, 0, expiration)))
+            .collect();
+        self.inner.set_multi(kv).await?;
+        Ok((Cow::Owned(manifest.encode()), flags | CHUNKED))
+    }
+
+    /// `value` read from `key`, reassembled if it is a manifest; chunks are touched with `expiration`
+    async fn fetch_chunks(
+        &mut self,
+        key: &[u8],
+        value: Bytes,
+        flags: u32,
+        expiration: Option<u32>,
+    ) -> Result<(Bytes, u32)> {
+        if flags & CHUNKED == 0 {
+            return Ok((value, flags));
+        }
+        let manifest = Manifest::decode(&value)?;
+        let keys = manifest.keys(key);
+        let chunks = match expiration {
+            Some(expiration) => {
+                self.inner
+                    .get_and_touch_multi(&refs(&keys), expiration)
+                    .await?
+            }
+            None => self.inner.get_multi(&refs(&keys)).await?,
+        };
+        Ok((manifest.assemble(&keys, chunks)?, flags & !CHUNKED))
+    }
+}
+
+impl<C: sync::Operation + sync::MultiOperation> Chunked<C> {
+    /// Synchronous `store_chunks`
+    fn store_chunks_sync<'a>(
+        &mut self,
+        key: &[u8],
+        value: &'a [u8],
+        flags: u32,
+        expiration: u32,
+    ) -> Result<(Cow<'a, [u8]>, u32)> {
+        let (manifest, chunks) = match self.split(value) {
+            Some(split) => split,
+            None => return Ok((Cow::Borrowed(value), flags)),
+        };
+        let keys = manifest.checked_keys(key)?;
+        let kv: BTreeMap<&[u8], (&[u8], u32, u32)> = keys
+            .iter()
+            .zip(chunks)
+            .map(|(k, chunk)| (&k[..], (chunk, 0, expiration)))
+            .collect();
+        self.inner.set_multi(kv)?;
+        Ok((Cow::Owned(manifest.encode()), flags | CHUNKED))
+    }
+
+    /// Synchronous `fetch_chunks`
+    fn fetch_chunks_sync(
+        &mut self,
+        key: &[u8],
+        value: Bytes,
+        flags: u32,
+        expiration: Option<u32>,
+    ) -> Result<(Bytes, u32)> {
+        if flags & CHUNKED == 0 {
+            return Ok((value, flags));
+        }
+        let manifest = Manifest::decode(&value)?;
+        let keys = manifest.keys(key);
+        let chunks = match expiration {
+            Some(expiration) => self.inner.get_and_touch_multi(&refs(&keys), expiration)?,
+            None => self.inner.get_multi(&refs(&keys))?,
+        };
+        Ok((manifest.assemble(&keys, chunks)?, flags & !CHUNKED))
+    }
+}
+
+#[async_trait]
+impl<C: r#async::Operation + r#async::MultiOperation + Send> r#async::Operation for Chunked<C> {
+    async fn set(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (val, flags) = self.store_chunks(key, val, flags, expiration).await?;
+        self.inner.set(key, &val, flags, expiration).await
+    }
+    async fn add(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (val, flags) = self.store_chunks(key, val, flags, expiration).await?;
+        self.inner.add(key, &val, flags, expiration).await
+    }
+    async fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.inner.delete(key).await
+    }
+    async fn replace(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (val, flags) = self.store_chunks(key, val, flags, expiration).await?;
+        self.inner.replace(key, &val, flags, expiration).await
+    }
+    async fn get(&mut self, key: &[u8]) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get(key).await?;
+        self.fetch_chunks(key, value, flags, None).await
+    }
+    async fn getk(&mut self, key: &[u8]) -> Result<(Bytes, Bytes, u32)> {
+        let (key, value, flags) = self.inner.getk(key).await?;
+        let (value, flags) = self.fetch_chunks(&key, value, flags, None).await?;
+        Ok((key, value, flags))
+    }
+    async fn increment(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.inner.increment(key, amount, initial, expiration).await
+    }
+    async fn decrement(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.inner.decrement(key, amount, initial, expiration).await
+    }
+    async fn append(&mut self, key: &[u8], val: &[u8]) -> Result<()> {
+        self.inner.append(key, val).await
+    }
+    async fn prepend(&mut self, key: &[u8], val: &[u8]) -> Result<()> {
+        self.inner.prepend(key, val).await
+    }
+    /// Also touches the chunks: once the key is touched, its value is read to find a manifest
+    async fn touch(&mut self, key: &[u8], expiration: u32) -> Result<()> {
+        self.inner.touch(key, expiration).await?;
+        let mut buf = Vec::new();
+        match self.inner.get_into(key, &mut buf).await? {
+            Some(meta) if meta.flags & CHUNKED != 0 => {
+                let keys = Manifest::decode(&buf)?.keys(key);
+                self.inner.touch_multi(&refs(&keys), expiration).await
+            }
+            _ => Ok(()),
+        }
+    }
+    async fn get_into(&mut self, key: &[u8], buf: &mut Vec<u8>) -> Result<Option<ValueMeta>> {
+        let meta = match self.inner.get_into(key, buf).await? {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+        if meta.flags & CHUNKED == 0 {
+            return Ok(Some(meta));
+        }
+        let manifest = Bytes::copy_from_slice(buf);
+        let (value, flags) = self.fetch_chunks(key, manifest, meta.flags, None).await?;
+        buf.clear();
+        buf.extend_from_slice(&value);
+        Ok(Some(ValueMeta { flags, ..meta }))
+    }
+    async fn get_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get_and_touch(key, expiration).await?;
+        self.fetch_chunks(key, value, flags, Some(expiration)).await
+    }
+    async fn getk_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, Bytes, u32)> {
+        let (key, value, flags) = self.inner.getk_and_touch(key, expiration).await?;
+        let (value, flags) = self
+            .fetch_chunks(&key, value, flags, Some(expiration))
+            .await?;
+        Ok((key, value, flags))
+    }
+    async fn exists(&mut self, key: &[u8]) -> Result<bool> {
+        self.inner.exists(key).await
+    }
+}
+
+impl<C: sync::Operation + sync::MultiOperation> sync::Operation for Chunked<C> {
+    fn set(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (value, flags) = self.store_chunks_sync(key, value, flags, expiration)?;
+        self.inner.set(key, &value, flags, expiration)
+    }
+    fn add(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (value, flags) = self.store_chunks_sync(key, value, flags, expiration)?;
+        self.inner.add(key, &value, flags, expiration)
+    }
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.inner.delete(key)
+    }
+    fn replace(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (value, flags) = self.store_chunks_sync(key, value, flags, expiration)?;
+        self.inner.replace(key, &value, flags, expiration)
+    }
+    fn get(&mut self, key: &[u8]) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get(key)?;
+        self.fetch_chunks_sync(key, value, flags, None)
+    }
+    fn getk(&mut self, key: &[u8]) -> Result<(Bytes, Bytes, u32)> {
+        let (key, value, flags) = self.inner.getk(key)?;
+        let (value, flags) = self.fetch_chunks_sync(&key, value, flags, None)?;
+        Ok((key, value, flags))
+    }
+    fn increment(&mut self, key: &[u8], amount: u64, initial: u64, expiration: u32) -> Result<u64> {
+        self.inner.increment(key, amount, initial, expiration)
+    }
+    fn decrement(&mut self, key: &[u8], amount: u64, initial: u64, expiration: u32) -> Result<u64> {
+        self.inner.decrement(key, amount, initial, expiration)
+    }
+    fn append(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.inner.append(key, value)
+    }
+    fn prepend(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.inner.prepend(key, value)
+    }
+    /// Also touches the chunks: once the key is touched, its value is read to find a manifest
+    fn touch(&mut self, key: &[u8], expiration: u32) -> Result<()> {
+        self.inner.touch(key, expiration)?;
+        let mut buf = Vec::new();
+        match self.inner.get_into(key, &mut buf)? {
+            Some(meta) if meta.flags & CHUNKED != 0 => {
+                let keys = Manifest::decode(&buf)?.keys(key);
+                self.inner.touch_multi(&refs(&keys), expiration)
+            }
+            _ => Ok(()),
+        }
+    }
+    fn get_into(&mut self, key: &[u8], buf: &mut Vec<u8>) -> Result<Option<ValueMeta>> {
+        let meta = match self.inner.get_into(key, buf)? {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+        if meta.flags & CHUNKED == 0 {
+            return Ok(Some(meta));
+        }
+        let manifest = Bytes::copy_from_slice(buf);
+        let (value, flags) = self.fetch_chunks_sync(key, manifest, meta.flags, None)?;
+        buf.clear();
+        buf.extend_from_slice(&value);
+        Ok(Some(ValueMeta { flags, ..meta }))
+    }
+    fn get_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get_and_touch(key, expiration)?;
+        self.fetch_chunks_sync(key, value, flags, Some(expiration))
+    }
+    fn getk_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, Bytes, u32)> {
+        let (key, value, flags) = self.inner.getk_and_touch(key, expiration)?;
+        let (value, flags) = self.fetch_chunks_sync(&key, value, flags, Some(expiration))?;
+        Ok((key, value, flags))
+    }
+    fn exists(&mut self, key: &[u8]) -> Result<bool> {
+        self.inner.exists(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::Memory;
+    use crate::client::sync::Operation;
+
+    #[test]
+    fn test_manifest() {
+        let manifest = Manifest {
+            generation: 0xab,
+            count: 3,
+            len: 25,
+        };
+        let decoded = Manifest::decode(&manifest.encode()).unwrap();
+        let keys = decoded.keys(b"k");
+        assert_eq!(keys[2], b"k:00000000000000ab:2");
+
+        let mut chunks = HashMap::new();
+        for (k, v) in keys
+            .iter()
+            .zip(&[&b"0123456789"[..], b"0123456789", b"01234"])
+        {
+            chunks.insert(Bytes::copy_from_slice(k), (Bytes::copy_from_slice(v), 0));
+        }
+        assert_eq!(decoded.assemble(&keys, chunks.clone()).unwrap().len(), 25);
+
+        chunks.remove(&keys[1][..]);
+        match decoded.assemble(&keys, chunks).unwrap_err() {
+            crate::Error::Proto(e) => assert_eq!(e.status(), Status::KeyNotFound),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut client = Chunked::new(Memory::default(), 10);
+        let value = b"0123456789".repeat(3);
+        client.set(b"k", &value, 1, 0).unwrap();
+        assert_eq!(client.get_ref().items.len(), 4);
+        assert_eq!(client.get(b"k").unwrap(), (value.into(), 1));
+    }
+
+    #[test]
+    fn test_key_too_long() {
+        let mut client = Chunked::new(Memory::default(), 10);
+        // room for the 16 hex digits of the generation and one digit of chunk number
+        let key = vec![b'k'; MAX_KEY_LEN - 19];
+        client.set(&key, &[0; 100], 0, 0).unwrap();
+        let key = vec![b'k'; MAX_KEY_LEN - 18];
+        match client.set(&key, &[0; 100], 0, 0).unwrap_err() {
+            crate::Error::Io(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+            e => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(client.get_ref().items.len(), 11);
+        // short values aren't chunked
+        client.set(&key, &[0; 10], 0, 0).unwrap();
+    }
+
+    #[test]
+    fn test_touch() {
+        let mut client = Chunked::new(Memory::default(), 10);
+        client.set(b"big", &[0; 30], 0, 0).unwrap();
+        client.set(b"small", &[0; 3], 0, 0).unwrap();
+        client.get_mut().calls.clear();
+
+        client.touch(b"big", 60).unwrap();
+        // the manifest, then its 3 chunks
+        assert_eq!(
+            client.get_ref().calls,
+            ["touch", "get_into", "touch", "touch", "touch"]
+        );
+        client.get_mut().calls.clear();
+        client.touch(b"small", 60).unwrap();
+        assert_eq!(client.get_ref().calls, ["touch", "get_into"]);
+        client.get_mut().calls.clear();
+        // a miss reads nothing
+        match client.touch(b"missing", 60).unwrap_err() {
+            crate::Error::Proto(e) => assert_eq!(e.status(), Status::KeyNotFound),
+            e => panic!("unexpected error {:?}", e),
+        }
+        assert_eq!(client.get_ref().calls, ["touch"]);
+    }
+}
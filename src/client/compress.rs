@@ -0,0 +1,307 @@
+use super::{r#async, sync, ValueMeta};
+use crate::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::borrow::Cow;
+use std::io;
+
+/// Flag bit marking a value compressed by `Compressed`, not to be used by the application;
+/// other clients' compression bits (zlib for pymemcache's `COMPRESSED`) are left alone.
+pub const LZ4_COMPRESSED: u32 = 1 << 7;
+
+/// Default of the limit on the length of decompressed values, memcached's default item size
+pub const DEFAULT_MAX_DECOMPRESSED_LEN: usize = 1024 * 1024;
+
+/// Client compressing (lz4) the values of set/add/replace longer than a threshold,
+/// marked with `LZ4_COMPRESSED` in their flags;
+/// the reads decompress such values and clear the flag, other values pass through.
+///
+/// `append`/`prepend` are passed on as is, they would corrupt a compressed value.
+/// Reads fail with `InvalidData` rather than decompress a value announcing a length above
+/// the limit, `DEFAULT_MAX_DECOMPRESSED_LEN` unless changed with `set_max_len`.
+#[derive(Debug)]
+pub struct Compressed<C> {
+    inner: C,
+    threshold: usize,
+    max_len: usize,
+}
+
+impl<C> Compressed<C> {
+    /// Compress values longer than `threshold` bytes
+    pub fn new(inner: C, threshold: usize) -> Self {
+        Self {
+            inner,
+            threshold,
+            max_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+        }
+    }
+
+    /// Limit on the length of decompressed values, e.g. above the item size over `Chunked`
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len;
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn compress<'a>(&self, value: &'a [u8], flags: u32) -> (Cow<'a, [u8]>, u32) {
+        if value.len() > self.threshold {
+            let compressed = lz4_flex::compress_prepend_size(value);
+            if compressed.len() < value.len() {
+                return (Cow::Owned(compressed), flags | LZ4_COMPRESSED);
+            }
+        }
+        (Cow::Borrowed(value), flags)
+    }
+
+    fn decompress(&self, value: Bytes, flags: u32) -> Result<(Bytes, u32)> {
+        if flags & LZ4_COMPRESSED == 0 {
+            return Ok((value, flags));
+        }
+        // the little-endian length prefix of `compress_prepend_size`, checked before allocating
+        if value.len() < 4 {
+            return Err(io::Error::from(io::ErrorKind::InvalidData).into());
+        }
+        let len = u32::from_le_bytes([value[0], value[1], value[2], value[3]]) as usize;
+        if len > self.max_len {
+            let msg = format!("decompressed length {} above {}", len, self.max_len);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg).into());
+        }
+        let value = lz4_flex::decompress(&value[4..], len)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        Ok((value.into(), flags & !LZ4_COMPRESSED))
+    }
+
+    fn decompress_into(&self, buf: &mut Vec<u8>, meta: ValueMeta) -> Result<ValueMeta> {
+        if meta.flags & LZ4_COMPRESSED == 0 {
+            return Ok(meta);
+        }
+        let (value, flags) = self.decompress(Bytes::copy_from_slice(buf), meta.flags)?;
+        buf.clear();
+        buf.extend_from_slice(&value);
+        Ok(ValueMeta { flags, ..meta })
+    }
+}
+
+#[async_trait]
+impl<C: r#async::Operation + Send> r#async::Operation for Compressed<C> {
+    async fn set(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (val, flags) = self.compress(val, flags);
+        self.inner.set(key, &val, flags, expiration).await
+    }
+    async fn add(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (val, flags) = self.compress(val, flags);
+        self.inner.add(key, &val, flags, expiration).await
+    }
+    async fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.inner.delete(key).await
+    }
+    async fn replace(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (val, flags) = self.compress(val, flags);
+        self.inner.replace(key, &val, flags, expiration).await
+    }
+    async fn get(&mut self, key: &[u8]) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get(key).await?;
+        self.decompress(value, flags)
+    }
+    async fn getk(&mut self, key: &[u8]) -> Result<(Bytes, Bytes, u32)> {
+        let (key, value, flags) = self.inner.getk(key).await?;
+        let (value, flags) = self.decompress(value, flags)?;
+        Ok((key, value, flags))
+    }
+    async fn increment(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.inner.increment(key, amount, initial, expiration).await
+    }
+    async fn decrement(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        self.inner.decrement(key, amount, initial, expiration).await
+    }
+    async fn append(&mut self, key: &[u8], val: &[u8]) -> Result<()> {
+        self.inner.append(key, val).await
+    }
+    async fn prepend(&mut self, key: &[u8], val: &[u8]) -> Result<()> {
+        self.inner.prepend(key, val).await
+    }
+    async fn touch(&mut self, key: &[u8], expiration: u32) -> Result<()> {
+        self.inner.touch(key, expiration).await
+    }
+    async fn get_into(&mut self, key: &[u8], buf: &mut Vec<u8>) -> Result<Option<ValueMeta>> {
+        match self.inner.get_into(key, buf).await? {
+            Some(meta) => self.decompress_into(buf, meta).map(Some),
+            None => Ok(None),
+        }
+    }
+    async fn get_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get_and_touch(key, expiration).await?;
+        self.decompress(value, flags)
+    }
+    async fn getk_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, Bytes, u32)> {
+        let (key, value, flags) = self.inner.getk_and_touch(key, expiration).await?;
+        let (value, flags) = self.decompress(value, flags)?;
+        Ok((key, value, flags))
+    }
+    async fn exists(&mut self, key: &[u8]) -> Result<bool> {
+        self.inner.exists(key).await
+    }
+}
+
+impl<C: sync::Operation> sync::Operation for Compressed<C> {
+    fn set(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (value, flags) = self.compress(value, flags);
+        self.inner.set(key, &value, flags, expiration)
+    }
+    fn add(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (value, flags) = self.compress(value, flags);
+        self.inner.add(key, &value, flags, expiration)
+    }
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.inner.delete(key)
+    }
+    fn replace(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let (value, flags) = self.compress(value, flags);
+        self.inner.replace(key, &value, flags, expiration)
+    }
+    fn get(&mut self, key: &[u8]) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get(key)?;
+        self.decompress(value, flags)
+    }
+    fn getk(&mut self, key: &[u8]) -> Result<(Bytes, Bytes, u32)> {
+        let (key, value, flags) = self.inner.getk(key)?;
+        let (value, flags) = self.decompress(value, flags)?;
+        Ok((key, value, flags))
+    }
+    fn increment(&mut self, key: &[u8], amount: u64, initial: u64, expiration: u32) -> Result<u64> {
+        self.inner.increment(key, amount, initial, expiration)
+    }
+    fn decrement(&mut self, key: &[u8], amount: u64, initial: u64, expiration: u32) -> Result<u64> {
+        self.inner.decrement(key, amount, initial, expiration)
+    }
+    fn append(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.inner.append(key, value)
+    }
+    fn prepend(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.inner.prepend(key, value)
+    }
+    fn touch(&mut self, key: &[u8], expiration: u32) -> Result<()> {
+        self.inner.touch(key, expiration)
+    }
+    fn get_into(&mut self, key: &[u8], buf: &mut Vec<u8>) -> Result<Option<ValueMeta>> {
+        match self.inner.get_into(key, buf)? {
+            Some(meta) => self.decompress_into(buf, meta).map(Some),
+            None => Ok(None),
+        }
+    }
+    fn get_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get_and_touch(key, expiration)?;
+        self.decompress(value, flags)
+    }
+    fn getk_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, Bytes, u32)> {
+        let (key, value, flags) = self.inner.getk_and_touch(key, expiration)?;
+        let (value, flags) = self.decompress(value, flags)?;
+        Ok((key, value, flags))
+    }
+    fn exists(&mut self, key: &[u8]) -> Result<bool> {
+        self.inner.exists(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compressed, LZ4_COMPRESSED};
+    use crate::client::mock::Memory;
+    use crate::client::sync::Operation;
+    use crate::interop::pymemcache;
+    use crate::Error;
+    use std::io;
+
+    fn stored(client: &Compressed<Memory>, key: &[u8]) -> (usize, u32) {
+        let (value, flags, _) = &client.get_ref().items[key];
+        (value.len(), *flags)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut client = Compressed::new(Memory::default(), 64);
+        let value = b"abcd".repeat(1024);
+        client.set(b"k", &value, 3, 0).unwrap();
+        let (len, flags) = stored(&client, b"k");
+        assert!(len < value.len() / 4);
+        assert_eq!(flags, 3 | LZ4_COMPRESSED);
+        assert_eq!(client.get(b"k").unwrap(), (value.clone().into(), 3));
+
+        let mut buf = Vec::new();
+        let meta = client.get_into(b"k", &mut buf).unwrap().unwrap();
+        assert_eq!((buf, meta.flags), (value, 3));
+    }
+
+    #[test]
+    fn test_below_threshold() {
+        let mut client = Compressed::new(Memory::default(), 64);
+        let value = b"a".repeat(64);
+        client.add(b"k", &value, 0, 0).unwrap();
+        assert_eq!(stored(&client, b"k"), (64, 0));
+        assert_eq!(client.get(b"k").unwrap(), (value.into(), 0));
+    }
+
+    #[test]
+    fn test_incompressible() {
+        let mut client = Compressed::new(Memory::default(), 64);
+        let rng = fastrand::Rng::with_seed(1);
+        let value: Vec<u8> = (0..4096).map(|_| rng.u8(..)).collect();
+        client.set(b"k", &value, 0, 0).unwrap();
+        assert_eq!(stored(&client, b"k"), (4096, 0));
+        assert_eq!(client.get(b"k").unwrap(), (value.into(), 0));
+    }
+
+    #[test]
+    fn test_max_len() {
+        let mut client = Compressed::new(Memory::default(), 64);
+        let value = b"a".repeat(4096);
+        client.set(b"k", &value, 0, 0).unwrap();
+        client.set_max_len(4095);
+        match client.get(b"k") {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            other => panic!("unexpected {:?}", other),
+        }
+        client.set_max_len(4096);
+        assert_eq!(client.get(b"k").unwrap(), (value.into(), 0));
+
+        // a forged prefix is rejected before anything is allocated
+        let mut forged = u32::MAX.to_le_bytes().to_vec();
+        forged.extend_from_slice(b"\x10a");
+        client
+            .get_mut()
+            .set(b"f", &forged, LZ4_COMPRESSED, 0)
+            .unwrap();
+        assert!(client.get(b"f").is_err());
+    }
+
+    #[test]
+    fn test_foreign_compression() {
+        // zlib compressed by pymemcache, not ours to decompress
+        let mut client = Compressed::new(Memory::default(), 64);
+        let flags = pymemcache::TEXT | pymemcache::COMPRESSED;
+        client.get_mut().set(b"k", b"x\x9c", flags, 0).unwrap();
+        assert_eq!(client.get(b"k").unwrap(), (b"x\x9c"[..].into(), flags));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::error::ProtoError;
+use crate::{Result, Status};
+use bytes::Bytes;
+use std::collections::HashMap;
+
+/// A logical counter spread across `shards` sub-keys (`<key>:<n>`) to relieve contention on one hot key;
+/// each increment hits one shard at random and reading sums all of them.
+#[derive(Debug, Clone)]
+pub struct ShardedCounter {
+    keys: Vec<Vec<u8>>,
+}
+
+impl ShardedCounter {
+    /// # Panics
+    /// if `shards` is 0
+    pub fn new(key: &[u8], shards: u32) -> Self {
+        assert!(shards > 0, "a sharded counter needs at least one shard");
+        let keys = (0..shards)
+            .map(|i| {
+                let mut k = key.to_vec();
+                k.extend_from_slice(format!(":{}", i).as_bytes());
+                k
+            })
+            .collect();
+        Self { keys }
+    }
+
+    /// Keys of all the shards
+    pub fn shard_keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.keys.iter().map(Vec::as_slice)
+    }
+
+    fn pick(&self) -> &[u8] {
+        &self.keys[fastrand::usize(..self.keys.len())]
+    }
+
+    fn sum(values: HashMap<Bytes, (Bytes, u32)>) -> Result<u64> {
+        values.values().try_fold(0u64, |acc, (val, _)| {
+            let n = std::str::from_utf8(val)
+                .ok()
+                .and_then(|s| s.trim().parse::<u64>().ok())
+                .ok_or_else(|| {
+                    ProtoError::from_status(
+                        Status::IncrOrDecrOnNonNumericValue,
+                        Some(String::from_utf8_lossy(val).into_owned()),
+                    )
+                })?;
+            Ok(acc.wrapping_add(n))
+        })
+    }
+
+    /// Add `amount` to one shard, creating it with `amount` if missing
+    pub async fn incr<C>(&self, client: &mut C, amount: u64, expiration: u32) -> Result<()>
+    where
+        C: super::r#async::MultiOperation + Send,
+    {
+        let mut kv = HashMap::with_capacity(1);
+        kv.insert(self.pick(), (amount, amount, expiration));
+        client.increment_multi(kv).await?;
+        Ok(())
+    }
+
+    /// Sum of all the shards, missing shards count as 0
+    pub async fn get<C>(&self, client: &mut C) -> Result<u64>
+    where
+        C: super::r#async::MultiOperation + Send,
+    {
+        let keys: Vec<&[u8]> = self.shard_keys().collect();
+        Self::sum(client.get_multi(&keys).await?)
+    }
+
+    /// Synchronous `incr`
+    pub fn incr_sync<C>(&self, client: &mut C, amount: u64, expiration: u32) -> Result<()>
+    where
+        C: super::sync::MultiOperation,
+    {
+        let mut kv = HashMap::with_capacity(1);
+        kv.insert(self.pick(), (amount, amount, expiration));
+        client.increment_multi(kv)?;
+        Ok(())
+    }
+
+    /// Synchronous `get`
+    pub fn get_sync<C>(&self, client: &mut C) -> Result<u64>
+    where
+        C: super::sync::MultiOperation,
+    {
+        let keys: Vec<&[u8]> = self.shard_keys().collect();
+        Self::sum(client.get_multi(&keys)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::Memory;
+
+    #[test]
+    fn test_shard_keys() {
+        let counter = ShardedCounter::new(b"hits", 3);
+        let keys: Vec<&[u8]> = counter.shard_keys().collect();
+        assert_eq!(keys, [&b"hits:0"[..], b"hits:1", b"hits:2"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_no_shard() {
+        ShardedCounter::new(b"hits", 0);
+    }
+
+    #[test]
+    fn test_incr_get() {
+        let counter = ShardedCounter::new(b"hits", 4);
+        let mut client = Memory::default();
+        assert_eq!(counter.get_sync(&mut client).unwrap(), 0);
+        for _ in 0..10 {
+            counter.incr_sync(&mut client, 2, 0).unwrap();
+        }
+        assert_eq!(counter.get_sync(&mut client).unwrap(), 20);
+        assert!(client
+            .items
+            .keys()
+            .all(|k| counter.shard_keys().any(|s| s == &k[..])));
+    }
+
+    #[test]
+    fn test_non_numeric_shard() {
+        let counter = ShardedCounter::new(b"hits", 2);
+        let mut client = Memory::default();
+        client
+            .items
+            .insert(b"hits:1".to_vec(), (Bytes::from_static(b"x"), 0, 1));
+        match counter.get_sync(&mut client).unwrap_err() {
+            crate::Error::Proto(e) => assert_eq!(e.status(), Status::IncrOrDecrOnNonNumericValue),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::Result;
+use semver::Version;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Result of `ServerOperation::doctor`, meant to be exposed by health endpoints
+#[derive(Debug, Clone, Default)]
+pub struct DoctorReport {
+    /// Round trip of a `noop`, `None` if the server couldn't be reached
+    pub round_trip: Option<Duration>,
+    pub version: Option<Version>,
+    /// Server clock minus local clock in seconds, from the `time` statistic
+    pub clock_skew: Option<i64>,
+    /// Name and error of every check that failed
+    pub failures: Vec<(&'static str, String)>,
+}
+
+impl DoctorReport {
+    /// Skew above which `is_healthy` reports the server clock as wrong
+    pub const MAX_CLOCK_SKEW: i64 = 5;
+
+    #[inline]
+    pub fn is_healthy(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    fn fail(&mut self, check: &'static str, e: impl ToString) {
+        self.failures.push((check, e.to_string()));
+    }
+
+    pub(super) fn noop(&mut self, start: Instant, res: Result<()>) {
+        match res {
+            Ok(()) => self.round_trip = Some(start.elapsed()),
+            // authentication problems surface here as `AuthenticationRequired`
+            Err(e) => self.fail("noop", e),
+        }
+    }
+
+    pub(super) fn version(&mut self, res: Result<Version>) {
+        match res {
+            Ok(version) => self.version = Some(version),
+            Err(e) => self.fail("version", e),
+        }
+    }
+
+    pub(super) fn stat(&mut self, res: Result<BTreeMap<String, String>>) {
+        let stats = match res {
+            Ok(stats) => stats,
+            Err(e) => return self.fail("stat", e),
+        };
+        let server_time = match stats.get("time").and_then(|t| t.parse::<i64>().ok()) {
+            Some(t) => t,
+            None => return self.fail("clock", "no time statistic"),
+        };
+        let local_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let skew = server_time - local_time;
+        self.clock_skew = Some(skew);
+        if skew.abs() > Self::MAX_CLOCK_SKEW {
+            self.fail("clock", format!("server clock is {}s off", skew));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::sync::ServerOperation;
+    use crate::error::ProtoError;
+    use crate::Status;
+
+    /// Server answering `noop` as told, with its clock `skew` seconds off
+    struct Server {
+        reachable: bool,
+        skew: Option<i64>,
+    }
+
+    impl ServerOperation for Server {
+        fn quit(&mut self) -> Result<()> {
+            Ok(())
+        }
+        fn flush(&mut self, _expiration: u32) -> Result<()> {
+            Ok(())
+        }
+        fn noop(&mut self) -> Result<()> {
+            if self.reachable {
+                Ok(())
+            } else {
+                Err(ProtoError::from_status(Status::AuthenticationRequired, None).into())
+            }
+        }
+        fn version(&mut self) -> Result<Version> {
+            Ok(Version::new(1, 6, 21))
+        }
+        fn stat(&mut self) -> Result<BTreeMap<String, String>> {
+            let mut stats = BTreeMap::new();
+            if let Some(skew) = self.skew {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                stats.insert("time".into(), (now.as_secs() as i64 + skew).to_string());
+            }
+            Ok(stats)
+        }
+    }
+
+    #[test]
+    fn test_healthy() {
+        let report = Server {
+            reachable: true,
+            skew: Some(0),
+        }
+        .doctor();
+        assert!(report.is_healthy(), "{:?}", report);
+        assert!(report.round_trip.is_some());
+        assert_eq!(report.version, Some(Version::new(1, 6, 21)));
+        assert!(report.clock_skew.unwrap().abs() <= 1);
+    }
+
+    #[test]
+    fn test_failures() {
+        let report = Server {
+            reachable: false,
+            skew: Some(0),
+        }
+        .doctor();
+        assert!(!report.is_healthy());
+        assert_eq!(report.round_trip, None);
+        // nothing else is checked on an unreachable server
+        assert_eq!(report.version, None);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].0, "noop");
+
+        let report = Server {
+            reachable: true,
+            skew: Some(60),
+        }
+        .doctor();
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].0, "clock");
+        assert!((59..=61).contains(&report.clock_skew.unwrap()));
+
+        let report = Server {
+            reachable: true,
+            skew: None,
+        }
+        .doctor();
+        assert_eq!(report.clock_skew, None);
+        assert_eq!(report.failures[0], ("clock", "no time statistic".into()));
+    }
+
+    #[test]
+    fn test_verbosity_default() {
+        let mut server = Server {
+            reachable: true,
+            skew: None,
+        };
+        match server.verbosity(1) {
+            Err(crate::Error::Proto(e)) => assert_eq!(e.status(), Status::NotSupported),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use super::{r#async, sync, ValueMeta};
+use crate::Result;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use async_trait::async_trait;
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::io;
+
+const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 12;
+
+/// AES-256 keys of `EncryptedClient` by id, so that they can be rotated:
+/// values are encrypted with the current key and decrypted with the key whose id they carry.
+pub trait KeyProvider: Send + Sync {
+    /// Id and key to encrypt with
+    fn current(&self) -> (u32, [u8; 32]);
+    /// Key of `id`, `None` if it was retired
+    fn get(&self, id: u32) -> Option<[u8; 32]>;
+}
+
+/// A single key with id 0
+impl KeyProvider for [u8; 32] {
+    fn current(&self) -> (u32, [u8; 32]) {
+        (0, *self)
+    }
+    fn get(&self, id: u32) -> Option<[u8; 32]> {
+        if id == 0 {
+            Some(*self)
+        } else {
+            None
+        }
+    }
+}
+
+fn invalid(msg: &'static str) -> crate::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg).into()
+}
+
+/// Client encrypting values with AES-256-GCM before they reach the server,
+/// stored as the key id, the nonce then the ciphertext; the item key is authenticated with the value,
+/// so a value copied under another key fails to decrypt. Flags are stored in the clear.
+///
+/// With `with_key_hmac`, item keys are replaced by their hex HMAC-SHA256 so they don't leak either.
+/// Counters are stored in the clear, the server has to read them;
+/// `append`/`prepend` fail with `Unsupported` as they would corrupt the ciphertext.
+pub struct EncryptedClient<C> {
+    inner: C,
+    keys: Box<dyn KeyProvider>,
+    key_hmac: Option<Hmac<Sha256>>,
+}
+
+impl<C> EncryptedClient<C> {
+    pub fn new(inner: C, keys: impl KeyProvider + 'static) -> Self {
+        Self {
+            inner,
+            keys: Box::new(keys),
+            key_hmac: None,
+        }
+    }
+
+    /// Send the HMAC-SHA256 of every item key under `secret` instead of the key
+    pub fn with_key_hmac(mut self, secret: &[u8]) -> Self {
+        let mac = <Hmac<Sha256> as Mac>::new_from_slice(secret);
+        self.key_hmac = Some(mac.expect("HMAC accepts keys of any length"));
+        self
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn key<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+        match &self.key_hmac {
+            Some(mac) => {
+                let mut mac = mac.clone();
+                mac.update(key);
+                let digest = mac.finalize().into_bytes();
+                let mut hex = String::with_capacity(digest.len() * 2);
+                for b in digest {
+                    let _ = write!(hex, "{:02x}", b);
+                }
+                Cow::Owned(hex.into_bytes())
+            }
+            None => Cow::Borrowed(key),
+        }
+    }
+
+    fn encrypt(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+        let (id, secret) = self.keys.current();
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&secret));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: value,
+            aad: key,
+        };
+        let ciphertext = cipher
+            .encrypt(&nonce, payload)
+            .map_err(|_| invalid("encryption failed"))?;
+        let mut out = Vec::with_capacity(KEY_ID_LEN + NONCE_LEN + ciphertext.len());
+        out.extend_from_slice(&id.to_be_bytes());
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    fn decrypt(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+        if value.len() < KEY_ID_LEN + NONCE_LEN {
+            return Err(invalid("value too short to be encrypted"));
+        }
+        let (id, rest) = value.split_at(KEY_ID_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let mut id_bytes = [0u8; KEY_ID_LEN];
+        id_bytes.copy_from_slice(id);
+        let secret = self
+            .keys
+            .get(u32::from_be_bytes(id_bytes))
+            .ok_or_else(|| invalid("value encrypted with an unknown key"))?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&secret));
+        let payload = Payload {
+            msg: ciphertext,
+            aad: key,
+        };
+        cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| invalid("value failed to decrypt"))
+    }
+
+    fn decrypt_into(&self, key: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+        let plaintext = self.decrypt(key, buf)?;
+        buf.clear();
+        buf.extend_from_slice(&plaintext);
+        Ok(())
+    }
+}
+
+impl<C> std::fmt::Debug for EncryptedClient<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedClient")
+            .field("key_hmac", &self.key_hmac.is_some())
+            .finish()
+    }
+}
+
+fn unsupported() -> crate::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "append/prepend to an encrypted value",
+    )
+    .into()
+}
+
+#[async_trait]
+impl<C: r#async::Operation + Send> r#async::Operation for EncryptedClient<C> {
+    async fn set(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let val = self.encrypt(key, val)?;
+        let key = self.key(key);
+        self.inner.set(&key, &val, flags, expiration).await
+    }
+    async fn add(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let val = self.encrypt(key, val)?;
+        let key = self.key(key);
+        self.inner.add(&key, &val, flags, expiration).await
+    }
+    async fn delete(&mut self, key: &[u8]) -> Result<()> {
+        let key = self.key(key);
+        self.inner.delete(&key).await
+    }
+    async fn replace(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let val = self.encrypt(key, val)?;
+        let key = self.key(key);
+        self.inner.replace(&key, &val, flags, expiration).await
+    }
+    async fn get(&mut self, key: &[u8]) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get(&self.key(key)).await?;
+        Ok((self.decrypt(key, &value)?.into(), flags))
+    }
+    async fn getk(&mut self, key: &[u8]) -> Result<(Bytes, Bytes, u32)> {
+        let (value, flags) = self.get(key).await?;
+        Ok((Bytes::copy_from_slice(key), value, flags))
+    }
+    async fn increment(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        let key = self.key(key);
+        self.inner
+            .increment(&key, amount, initial, expiration)
+            .await
+    }
+    async fn decrement(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<u64> {
+        let key = self.key(key);
+        self.inner
+            .decrement(&key, amount, initial, expiration)
+            .await
+    }
+    async fn append(&mut self, _key: &[u8], _val: &[u8]) -> Result<()> {
+        Err(unsupported())
+    }
+    async fn prepend(&mut self, _key: &[u8], _val: &[u8]) -> Result<()> {
+        Err(unsupported())
+    }
+    async fn touch(&mut self, key: &[u8], expiration: u32) -> Result<()> {
+        let key = self.key(key);
+        self.inner.touch(&key, expiration).await
+    }
+    async fn get_into(&mut self, key: &[u8], buf: &mut Vec<u8>) -> Result<Option<ValueMeta>> {
+        let meta = self.inner.get_into(&self.key(key), buf).await?;
+        if meta.is_some() {
+            self.decrypt_into(key, buf)?;
+        }
+        Ok(meta)
+    }
+    async fn get_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get_and_touch(&self.key(key), expiration).await?;
+        Ok((self.decrypt(key, &value)?.into(), flags))
+    }
+    async fn getk_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, Bytes, u32)> {
+        let (value, flags) = self.get_and_touch(key, expiration).await?;
+        Ok((Bytes::copy_from_slice(key), value, flags))
+    }
+    async fn exists(&mut self, key: &[u8]) -> Result<bool> {
+        let key = self.key(key);
+        self.inner.exists(&key).await
+    }
+}
+
+impl<C: sync::Operation> sync::Operation for EncryptedClient<C> {
+    fn set(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let value = self.encrypt(key, value)?;
+        self.inner.set(&self.key(key), &value, flags, expiration)
+    }
+    fn add(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let value = self.encrypt(key, value)?;
+        self.inner.add(&self.key(key), &value, flags, expiration)
+    }
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.inner.delete(&self.key(key))
+    }
+    fn replace(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let value = self.encrypt(key, value)?;
+        self.inner
+            .replace(&self.key(key), &value, flags, expiration)
+    }
+    fn get(&mut self, key: &[u8]) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get(&self.key(key))?;
+        Ok((self.decrypt(key, &value)?.into(), flags))
+    }
+    fn getk(&mut self, key: &[u8]) -> Result<(Bytes, Bytes, u32)> {
+        let (value, flags) = self.get(key)?;
+        Ok((Bytes::copy_from_slice(key), value, flags))
+    }
+    fn increment(&mut self, key: &[u8], amount: u64, initial: u64, expiration: u32) -> Result<u64> {
+        self.inner
+            .increment(&self.key(key), amount, initial, expiration)
+    }
+    fn decrement(&mut self, key: &[u8], amount: u64, initial: u64, expiration: u32) -> Result<u64> {
+        self.inner
+            .decrement(&self.key(key), amount, initial, expiration)
+    }
+    fn append(&mut self, _key: &[u8], _value: &[u8]) -> Result<()> {
+        Err(unsupported())
+    }
+    fn prepend(&mut self, _key: &[u8], _value: &[u8]) -> Result<()> {
+        Err(unsupported())
+    }
+    fn touch(&mut self, key: &[u8], expiration: u32) -> Result<()> {
+        self.inner.touch(&self.key(key), expiration)
+    }
+    fn get_into(&mut self, key: &[u8], buf: &mut Vec<u8>) -> Result<Option<ValueMeta>> {
+        let meta = self.inner.get_into(&self.key(key), buf)?;
+        if meta.is_some() {
+            self.decrypt_into(key, buf)?;
+        }
+        Ok(meta)
+    }
+    fn get_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, u32)> {
+        let (value, flags) = self.inner.get_and_touch(&self.key(key), expiration)?;
+        Ok((self.decrypt(key, &value)?.into(), flags))
+    }
+    fn getk_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, Bytes, u32)> {
+        let (value, flags) = self.get_and_touch(key, expiration)?;
+        Ok((Bytes::copy_from_slice(key), value, flags))
+    }
+    fn exists(&mut self, key: &[u8]) -> Result<bool> {
+        self.inner.exists(&self.key(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EncryptedClient, KeyProvider, KEY_ID_LEN, NONCE_LEN};
+    use crate::client::mock::Memory;
+    use crate::client::sync::Operation;
+    use crate::Error;
+    use std::io;
+
+    /// Key 0 retired, values now encrypted with key 1
+    struct Rotated;
+
+    impl KeyProvider for Rotated {
+        fn current(&self) -> (u32, [u8; 32]) {
+            (1, [2; 32])
+        }
+        fn get(&self, id: u32) -> Option<[u8; 32]> {
+            if id == 1 {
+                Some([2; 32])
+            } else {
+                None
+            }
+        }
+    }
+
+    fn invalid_data<T: std::fmt::Debug>(result: crate::Result<T>) -> String {
+        match result {
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::InvalidData => e.to_string(),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut client = EncryptedClient::new(Memory::default(), [1; 32]);
+        client.set(b"k", b"secret", 5, 0).unwrap();
+        let (stored, flags, _) = &client.get_ref().items[&b"k"[..]];
+        assert_eq!(stored.len(), KEY_ID_LEN + NONCE_LEN + 6 + 16);
+        assert_eq!(&stored[..KEY_ID_LEN], &[0; 4]);
+        assert_eq!(*flags, 5);
+        assert_eq!(client.get(b"k").unwrap(), ("secret".into(), 5));
+
+        let mut buf = Vec::new();
+        let meta = client.get_into(b"k", &mut buf).unwrap().unwrap();
+        assert_eq!((&buf[..], meta.flags), (&b"secret"[..], 5));
+        assert!(client.append(b"k", b"x").is_err());
+    }
+
+    #[test]
+    fn test_key_is_authenticated() {
+        let mut client = EncryptedClient::new(Memory::default(), [1; 32]);
+        client.set(b"a", b"secret", 0, 0).unwrap();
+        let copied = client.get_ref().items[&b"a"[..]].clone();
+        client.get_mut().items.insert(b"b".to_vec(), copied);
+        assert_eq!(invalid_data(client.get(b"b")), "value failed to decrypt");
+    }
+
+    #[test]
+    fn test_unknown_key_id() {
+        let mut client = EncryptedClient::new(Memory::default(), [1; 32]);
+        client.set(b"old", b"v", 0, 0).unwrap();
+        let mut client = EncryptedClient::new(client.into_inner(), Rotated);
+        client.set(b"new", b"w", 0, 0).unwrap();
+        assert_eq!(client.get(b"new").unwrap().0, "w");
+        assert_eq!(
+            invalid_data(client.get(b"old")),
+            "value encrypted with an unknown key"
+        );
+    }
+
+    #[test]
+    fn test_key_hmac() {
+        let mut client = EncryptedClient::new(Memory::default(), [1; 32]).with_key_hmac(b"pepper");
+        client.set(b"user:1", b"v", 0, 0).unwrap();
+        let keys: Vec<_> = client.get_ref().items.keys().cloned().collect();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].len(), 64);
+        assert!(keys[0].iter().all(u8::is_ascii_hexdigit));
+        assert_eq!(client.get(b"user:1").unwrap().0, "v");
+        assert!(client.exists(b"user:1").unwrap());
+        client.delete(b"user:1").unwrap();
+        assert!(client.get_ref().items.is_empty());
+
+        // another secret gives other keys
+        let mut other = EncryptedClient::new(client.into_inner(), [1; 32]).with_key_hmac(b"salt");
+        other.set(b"user:1", b"v", 0, 0).unwrap();
+        assert_ne!(other.get_ref().items.keys().next().unwrap(), &keys[0]);
+    }
+}
@@ -0,0 +1,334 @@
+//! In-memory `sync` client for the unit tests of the wrappers and helpers
+use super::sync::{self, CasOperation, Operation};
+use super::{AuthResponse, ValueMeta};
+use crate::error::ProtoError;
+use crate::{Result, Status};
+use bytes::{Bytes, BytesMut};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+fn status(status: Status) -> crate::Error {
+    ProtoError::from_status(status, None).into()
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Memory {
+    /// Value, flags and CAS of every key
+    pub items: HashMap<Vec<u8>, (Bytes, u32, u64)>,
+    /// Statuses the next CAS-guarded stores fail with before looking at the items,
+    /// to simulate other clients winning races
+    pub conflicts: VecDeque<Status>,
+    /// Name of every operation called, in order
+    pub calls: Vec<&'static str>,
+    last_cas: u64,
+}
+
+impl Memory {
+    fn store(&mut self, key: &[u8], value: Bytes, flags: u32) -> u64 {
+        self.last_cas += 1;
+        self.items
+            .insert(key.to_vec(), (value, flags, self.last_cas));
+        self.last_cas
+    }
+
+    fn item(&self, key: &[u8]) -> Result<(Bytes, u32, u64)> {
+        self.items
+            .get(key)
+            .cloned()
+            .ok_or_else(|| status(Status::KeyNotFound))
+    }
+
+    /// Fails like the server if `cas` is set and doesn't match the item's
+    fn check_cas(&mut self, key: &[u8], cas: u64) -> Result<()> {
+        if let Some(s) = self.conflicts.pop_front() {
+            return Err(status(s));
+        }
+        if cas != 0 && self.item(key)?.2 != cas {
+            return Err(status(Status::KeyExits));
+        }
+        Ok(())
+    }
+
+    fn counter(&mut self, key: &[u8], delta: i128, initial: u64) -> Result<(u64, u64)> {
+        let value = match self.items.get(key) {
+            None => initial,
+            Some((v, _, _)) => {
+                let n: u64 = std::str::from_utf8(v)
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| status(Status::IncrOrDecrOnNonNumericValue))?;
+                (n as i128 + delta).max(0) as u64
+            }
+        };
+        let cas = self.store(key, value.to_string().into(), 0);
+        Ok((value, cas))
+    }
+
+    fn concat(&mut self, key: &[u8], value: &[u8], cas: u64, append: bool) -> Result<u64> {
+        self.check_cas(key, cas)?;
+        let (old, flags, _) = self.item(key).map_err(|_| status(Status::ItemNotStored))?;
+        let mut new = BytesMut::with_capacity(old.len() + value.len());
+        if append {
+            new.extend_from_slice(&old);
+            new.extend_from_slice(value);
+        } else {
+            new.extend_from_slice(value);
+            new.extend_from_slice(&old);
+        }
+        Ok(self.store(key, new.freeze(), flags))
+    }
+}
+
+impl Operation for Memory {
+    fn set(&mut self, key: &[u8], value: &[u8], flags: u32, _expiration: u32) -> Result<()> {
+        self.calls.push("set");
+        self.store(key, Bytes::copy_from_slice(value), flags);
+        Ok(())
+    }
+    fn add(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        self.add_cas(key, value, flags, expiration).map(drop)
+    }
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.delete_cas(key, 0)
+    }
+    fn replace(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        self.replace_cas(key, value, flags, expiration, 0).map(drop)
+    }
+    fn get(&mut self, key: &[u8]) -> Result<(Bytes, u32)> {
+        self.calls.push("get");
+        let (value, flags, _) = self.item(key)?;
+        Ok((value, flags))
+    }
+    fn getk(&mut self, key: &[u8]) -> Result<(Bytes, Bytes, u32)> {
+        let (value, flags) = self.get(key)?;
+        Ok((Bytes::copy_from_slice(key), value, flags))
+    }
+    fn increment(&mut self, key: &[u8], amount: u64, initial: u64, expiration: u32) -> Result<u64> {
+        Ok(self.increment_cas(key, amount, initial, expiration, 0)?.0)
+    }
+    fn decrement(&mut self, key: &[u8], amount: u64, initial: u64, expiration: u32) -> Result<u64> {
+        Ok(self.decrement_cas(key, amount, initial, expiration, 0)?.0)
+    }
+    fn append(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.append_cas(key, value, 0).map(drop)
+    }
+    fn prepend(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.prepend_cas(key, value, 0).map(drop)
+    }
+    fn touch(&mut self, key: &[u8], expiration: u32) -> Result<()> {
+        self.touch_cas(key, expiration, 0).map(drop)
+    }
+    fn get_into(&mut self, key: &[u8], buf: &mut Vec<u8>) -> Result<Option<ValueMeta>> {
+        self.calls.push("get_into");
+        buf.clear();
+        Ok(self.items.get(key).map(|(value, flags, cas)| {
+            buf.extend_from_slice(value);
+            ValueMeta {
+                flags: *flags,
+                cas: *cas,
+            }
+        }))
+    }
+}
+
+impl CasOperation for Memory {
+    fn set_cas(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        _expiration: u32,
+        cas: u64,
+    ) -> Result<u64> {
+        self.calls.push("set_cas");
+        self.check_cas(key, cas)?;
+        Ok(self.store(key, Bytes::copy_from_slice(value), flags))
+    }
+    fn add_cas(&mut self, key: &[u8], value: &[u8], flags: u32, _expiration: u32) -> Result<u64> {
+        self.calls.push("add_cas");
+        self.check_cas(key, 0)?;
+        if self.items.contains_key(key) {
+            return Err(status(Status::KeyExits));
+        }
+        Ok(self.store(key, Bytes::copy_from_slice(value), flags))
+    }
+    fn replace_cas(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        _expiration: u32,
+        cas: u64,
+    ) -> Result<u64> {
+        self.calls.push("replace_cas");
+        self.item(key)?;
+        self.check_cas(key, cas)?;
+        Ok(self.store(key, Bytes::copy_from_slice(value), flags))
+    }
+    fn get_cas(&mut self, key: &[u8]) -> Result<(Bytes, u32, u64)> {
+        self.calls.push("get_cas");
+        self.item(key)
+    }
+    fn getk_cas(&mut self, key: &[u8]) -> Result<(Bytes, Bytes, u32, u64)> {
+        let (value, flags, cas) = self.get_cas(key)?;
+        Ok((Bytes::copy_from_slice(key), value, flags, cas))
+    }
+    fn increment_cas(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        _expiration: u32,
+        cas: u64,
+    ) -> Result<(u64, u64)> {
+        self.calls.push("increment");
+        self.check_cas(key, cas)?;
+        self.counter(key, amount as i128, initial)
+    }
+    fn decrement_cas(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        _expiration: u32,
+        cas: u64,
+    ) -> Result<(u64, u64)> {
+        self.calls.push("decrement");
+        self.check_cas(key, cas)?;
+        self.counter(key, -(amount as i128), initial)
+    }
+    fn append_cas(&mut self, key: &[u8], value: &[u8], cas: u64) -> Result<u64> {
+        self.calls.push("append");
+        self.concat(key, value, cas, true)
+    }
+    fn prepend_cas(&mut self, key: &[u8], value: &[u8], cas: u64) -> Result<u64> {
+        self.calls.push("prepend");
+        self.concat(key, value, cas, false)
+    }
+    fn touch_cas(&mut self, key: &[u8], _expiration: u32, cas: u64) -> Result<u64> {
+        self.calls.push("touch");
+        self.item(key)?;
+        self.check_cas(key, cas)?;
+        Ok(self.item(key)?.2)
+    }
+    fn delete_cas(&mut self, key: &[u8], cas: u64) -> Result<()> {
+        self.calls.push("delete");
+        self.item(key)?;
+        self.check_cas(key, cas)?;
+        self.items.remove(key);
+        Ok(())
+    }
+}
+
+impl sync::MultiOperation for Memory {
+    fn set_multi(&mut self, kv: BTreeMap<&[u8], (&[u8], u32, u32)>) -> Result<()> {
+        for (key, (value, flags, expiration)) in kv {
+            self.set(key, value, flags, expiration)?;
+        }
+        Ok(())
+    }
+    fn delete_multi(&mut self, keys: &[&[u8]]) -> Result<()> {
+        for key in keys {
+            self.items.remove(*key);
+        }
+        Ok(())
+    }
+    fn increment_multi<'a>(
+        &mut self,
+        kv: HashMap<&'a [u8], (u64, u64, u32)>,
+    ) -> Result<HashMap<&'a [u8], u64>> {
+        kv.into_iter()
+            .map(|(key, (amount, initial, expiration))| {
+                Ok((key, self.increment(key, amount, initial, expiration)?))
+            })
+            .collect()
+    }
+    fn get_multi(&mut self, keys: &[&[u8]]) -> Result<HashMap<Bytes, (Bytes, u32)>> {
+        self.calls.push("get_multi");
+        Ok(keys
+            .iter()
+            .filter_map(|key| {
+                let (value, flags, _) = self.items.get(*key)?;
+                Some((Bytes::copy_from_slice(key), (value.clone(), *flags)))
+            })
+            .collect())
+    }
+}
+
+/// Quiet commands report nothing, failures included
+impl sync::NoReplyOperation for Memory {
+    fn set_noreply(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let _ = self.set(key, value, flags, expiration);
+        Ok(())
+    }
+    fn add_noreply(&mut self, key: &[u8], value: &[u8], flags: u32, expiration: u32) -> Result<()> {
+        let _ = self.add(key, value, flags, expiration);
+        Ok(())
+    }
+    fn delete_noreply(&mut self, key: &[u8]) -> Result<()> {
+        let _ = self.delete(key);
+        Ok(())
+    }
+    fn replace_noreply(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        expiration: u32,
+    ) -> Result<()> {
+        let _ = self.replace(key, value, flags, expiration);
+        Ok(())
+    }
+    fn increment_noreply(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<()> {
+        let _ = self.increment(key, amount, initial, expiration);
+        Ok(())
+    }
+    fn decrement_noreply(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> Result<()> {
+        let _ = self.decrement(key, amount, initial, expiration);
+        Ok(())
+    }
+    fn append_noreply(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let _ = self.append(key, value);
+        Ok(())
+    }
+    fn prepend_noreply(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let _ = self.prepend(key, value);
+        Ok(())
+    }
+    fn flush_noreply(&mut self, _expiration: u32) -> Result<()> {
+        self.items.clear();
+        Ok(())
+    }
+    fn quit_noreply(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Accepts any PLAIN credentials in one step
+impl sync::AuthOperation for Memory {
+    fn list_mechanisms(&mut self) -> Result<Vec<String>> {
+        Ok(vec!["PLAIN".to_string()])
+    }
+    fn auth_start(&mut self, mech: &str, _init: &[u8]) -> Result<AuthResponse> {
+        self.calls.push("auth_start");
+        if mech == "PLAIN" {
+            Ok(AuthResponse::Succeeded)
+        } else {
+            Err(status(Status::AuthenticationError))
+        }
+    }
+    fn auth_continue(&mut self, _mech: &str, _data: &[u8]) -> Result<AuthResponse> {
+        Err(status(Status::AuthenticationError))
+    }
+}
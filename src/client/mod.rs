@@ -2,6 +2,16 @@ use bytes::Bytes;
 
 pub mod r#async;
 mod async_impl;
+mod chunk;
+#[cfg(feature = "lz4")]
+mod compress;
+mod counter;
+mod doctor;
+#[cfg(test)]
+pub(crate) mod mock;
+#[cfg(feature = "encryption")]
+mod encrypt;
+mod pipeline;
 pub mod sync;
 
 #[derive(Debug)]
@@ -11,6 +21,64 @@ pub enum AuthResponse {
     Failed,
 }
 
+/// Outcome of one key of `get_multi_detailed`
+#[derive(Debug)]
+pub enum ItemResult {
+    Hit {
+        value: Bytes,
+        flags: u32,
+        cas: u64,
+    },
+    Miss,
+    /// The server answered this key with an error status other than `KeyNotFound`
+    Error(crate::error::ProtoError),
+}
+
+/// Metadata of a value read into a caller buffer by `get_into`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueMeta {
+    pub flags: u32,
+    pub cas: u64,
+}
+
+/// Keys from `start` to `end` of a range request (RGet, RSet, ...), for building it by hand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyRange<'a> {
+    pub start: &'a [u8],
+    pub end: &'a [u8],
+    /// Backend specific, e.g. whether `start`/`end` are excluded
+    pub flags: u8,
+    /// 0 for no limit
+    pub max_results: u32,
+}
+
+impl<'a> KeyRange<'a> {
+    pub fn new(start: &'a [u8], end: &'a [u8]) -> Self {
+        Self {
+            start,
+            end,
+            flags: 0,
+            max_results: 0,
+        }
+    }
+
+    pub fn extras(&self) -> crate::Extras {
+        crate::Extras::Range {
+            start_key_len: self.start.len() as u16,
+            flags: self.flags,
+            max_results: self.max_results,
+        }
+    }
+
+    /// Key field of the request: `start` followed by `end`
+    pub fn key(&self) -> Bytes {
+        let mut key = Vec::with_capacity(self.start.len() + self.end.len());
+        key.extend_from_slice(self.start);
+        key.extend_from_slice(self.end);
+        key.into()
+    }
+}
+
 #[inline]
 fn gen_opaque() -> u32 {
     fastrand::u32(..)
@@ -18,5 +86,107 @@ fn gen_opaque() -> u32 {
 #[inline]
 fn discard_packet(_: crate::Packet) {}
 
+/// A `KeyNotFound` error as `None`
+fn found<T>(result: crate::Result<T>) -> crate::Result<Option<T>> {
+    match result {
+        Ok(v) => Ok(Some(v)),
+        Err(crate::Error::Proto(e)) if e.status() == crate::Status::KeyNotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// `keys` without duplicates, first occurrence first
+fn dedup_keys<'a>(keys: &[&'a [u8]]) -> Vec<&'a [u8]> {
+    let mut seen = std::collections::HashSet::with_capacity(keys.len());
+    keys.iter().copied().filter(|k| seen.insert(*k)).collect()
+}
+
+/// The entry of every key of `keys` in `found`, in order
+fn align<V: Clone>(keys: &[&[u8]], found: &std::collections::HashMap<Bytes, V>) -> Vec<Option<V>> {
+    keys.iter().map(|k| found.get(*k).cloned()).collect()
+}
+
+/// Error of the defaults for operations the other ones can't emulate
+fn not_supported() -> crate::Error {
+    crate::error::ProtoError::from_status(crate::Status::NotSupported, None).into()
+}
+
+/// `response` if its status is `NoError`, otherwise the status as an error,
+/// with the value of the response as the detail
+fn ok_response(response: crate::Packet) -> crate::Result<crate::Packet> {
+    let detail = if response.val.is_empty() {
+        None
+    } else {
+        Some(String::from_utf8_lossy(&response.val).into_owned())
+    };
+    response.status().ok_or(detail)?;
+    Ok(response)
+}
+
+/// An error response as the outcome of one key of a batch, I/O errors fail the whole batch
+fn per_key<T>(result: crate::Result<T>) -> crate::Result<crate::Result<T>> {
+    match result {
+        Err(crate::Error::Io(e)) => Err(e.into()),
+        result => Ok(result),
+    }
+}
+
+/// Whether a CAS-guarded store lost a race: the item changed (`KeyExists`) or went away
+fn is_conflict(e: &crate::Error) -> bool {
+    match e {
+        crate::Error::Proto(e) => matches!(
+            e.status(),
+            crate::Status::KeyExits | crate::Status::KeyNotFound
+        ),
+        _ => false,
+    }
+}
+
 pub use async_impl::BinaryProto;
+pub use chunk::{Chunked, CHUNKED};
+#[cfg(feature = "lz4")]
+pub use compress::{Compressed, DEFAULT_MAX_DECOMPRESSED_LEN, LZ4_COMPRESSED};
+pub use counter::ShardedCounter;
+pub use doctor::DoctorReport;
+#[cfg(feature = "encryption")]
+pub use encrypt::{EncryptedClient, KeyProvider};
+pub use pipeline::Pipeline;
 pub use r#async::Proto;
+
+#[cfg(test)]
+mod tests {
+    use super::{found, is_conflict, per_key};
+    use crate::error::ProtoError;
+    use crate::{Error, Status};
+    use std::io;
+
+    fn proto(status: Status) -> Error {
+        ProtoError::from_status(status, None).into()
+    }
+
+    #[test]
+    fn test_found() {
+        assert_eq!(found(Ok(1)).unwrap(), Some(1));
+        assert_eq!(found::<u8>(Err(proto(Status::KeyNotFound))).unwrap(), None);
+        assert!(found::<u8>(Err(proto(Status::KeyExits))).is_err());
+        assert!(found::<u8>(Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())).is_err());
+    }
+
+    #[test]
+    fn test_per_key() {
+        assert_eq!(per_key(Ok(1)).unwrap().unwrap(), 1);
+        let result = per_key::<u8>(Err(proto(Status::KeyExits))).unwrap();
+        assert!(is_conflict(&result.unwrap_err()));
+        assert!(per_key::<u8>(Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())).is_err());
+    }
+
+    #[test]
+    fn test_is_conflict() {
+        assert!(is_conflict(&proto(Status::KeyExits)));
+        assert!(is_conflict(&proto(Status::KeyNotFound)));
+        assert!(!is_conflict(&proto(Status::ItemNotStored)));
+        assert!(!is_conflict(
+            &io::Error::from(io::ErrorKind::InvalidData).into()
+        ));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{Packet, Request, Response, Result};
+use bytes::Bytes;
+use std::io;
+
+/// Operations queued to be sent in one write with `RawOperation::execute_raw_multi`,
+/// one typed `Response` per operation in the order they were queued;
+/// a failed operation is a `Response::Error` and doesn't stop the following ones.
+///
+/// # Examples
+/// ```rust,no_run
+/// use memcached_proto::client::{r#async::RawOperation, Pipeline};
+/// use memcached_proto::Response;
+///
+/// async fn run<C: RawOperation + Send>(client: &mut C) -> memcached_proto::Result<()> {
+///     let mut p = Pipeline::new();
+///     p.get(b"k1").set(b"k2", b"v", 0, 0).increment(b"k3", 1, 0, 0);
+///     let results = p.execute(client).await?;
+///     if let Response::CounterValue(n) = results[2] {
+///         println!("k3 = {}", n);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    requests: Vec<Request>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    fn push(&mut self, request: Request) -> &mut Self {
+        self.requests.push(request);
+        self
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> &mut Self {
+        self.push(Request::Get {
+            key: Bytes::copy_from_slice(key),
+        })
+    }
+
+    pub fn getk(&mut self, key: &[u8]) -> &mut Self {
+        self.push(Request::GetK {
+            key: Bytes::copy_from_slice(key),
+        })
+    }
+
+    pub fn get_and_touch(&mut self, key: &[u8], expiration: u32) -> &mut Self {
+        self.push(Request::GAT {
+            key: Bytes::copy_from_slice(key),
+            expiration,
+        })
+    }
+
+    pub fn set(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> &mut Self {
+        self.push(Request::Set {
+            key: Bytes::copy_from_slice(key),
+            value: Bytes::copy_from_slice(val),
+            flags,
+            expiration,
+            cas: 0,
+        })
+    }
+
+    pub fn add(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> &mut Self {
+        self.push(Request::Add {
+            key: Bytes::copy_from_slice(key),
+            value: Bytes::copy_from_slice(val),
+            flags,
+            expiration,
+        })
+    }
+
+    pub fn replace(&mut self, key: &[u8], val: &[u8], flags: u32, expiration: u32) -> &mut Self {
+        self.push(Request::Replace {
+            key: Bytes::copy_from_slice(key),
+            value: Bytes::copy_from_slice(val),
+            flags,
+            expiration,
+            cas: 0,
+        })
+    }
+
+    pub fn append(&mut self, key: &[u8], val: &[u8]) -> &mut Self {
+        self.push(Request::Append {
+            key: Bytes::copy_from_slice(key),
+            value: Bytes::copy_from_slice(val),
+            cas: 0,
+        })
+    }
+
+    pub fn prepend(&mut self, key: &[u8], val: &[u8]) -> &mut Self {
+        self.push(Request::Prepend {
+            key: Bytes::copy_from_slice(key),
+            value: Bytes::copy_from_slice(val),
+            cas: 0,
+        })
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> &mut Self {
+        self.push(Request::Delete {
+            key: Bytes::copy_from_slice(key),
+            cas: 0,
+        })
+    }
+
+    pub fn increment(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> &mut Self {
+        self.push(Request::Increment {
+            key: Bytes::copy_from_slice(key),
+            amount,
+            initial,
+            expiration,
+            cas: 0,
+        })
+    }
+
+    pub fn decrement(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+    ) -> &mut Self {
+        self.push(Request::Decrement {
+            key: Bytes::copy_from_slice(key),
+            amount,
+            initial,
+            expiration,
+            cas: 0,
+        })
+    }
+
+    pub fn touch(&mut self, key: &[u8], expiration: u32) -> &mut Self {
+        self.push(Request::Touch {
+            key: Bytes::copy_from_slice(key),
+            expiration,
+        })
+    }
+
+    pub fn noop(&mut self) -> &mut Self {
+        self.push(Request::NoOp)
+    }
+
+    pub fn version(&mut self) -> &mut Self {
+        self.push(Request::Version)
+    }
+
+    fn packets(self) -> Vec<Packet> {
+        self.requests
+            .into_iter()
+            .enumerate()
+            .map(|(i, request)| request.into_packet(0, i as u32))
+            .collect()
+    }
+
+    // every queued command is non-quiet, so each has exactly one response,
+    // carrying the opaque of its request: its index
+    fn responses(len: usize, packets: Vec<Packet>) -> Result<Vec<Response>> {
+        if packets.len() != len {
+            return Err(io::Error::from(io::ErrorKind::InvalidData).into());
+        }
+        Ok(packets
+            .into_iter()
+            .enumerate()
+            .map(|(i, packet)| {
+                if packet.header.opaque != i as u32 {
+                    return Err(io::ErrorKind::InvalidData.into());
+                }
+                Response::from_packet(packet)
+            })
+            .collect::<io::Result<_>>()?)
+    }
+
+    /// Send all the queued operations and wait for their responses
+    pub async fn execute<C>(self, client: &mut C) -> Result<Vec<Response>>
+    where
+        C: super::r#async::RawOperation + Send + ?Sized,
+    {
+        let len = self.len();
+        let responses = client.execute_raw_multi(self.packets()).await?;
+        Self::responses(len, responses)
+    }
+
+    /// Synchronous `execute`
+    pub fn execute_sync<C>(self, client: &mut C) -> Result<Vec<Response>>
+    where
+        C: super::sync::RawOperation + ?Sized,
+    {
+        let len = self.len();
+        let responses = client.execute_raw_multi(self.packets())?;
+        Self::responses(len, responses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pipeline;
+    use crate::client::sync::RawOperation;
+    use crate::{Error, Extras, Opcode, Packet, Response, Result, Status};
+    use bytes::Bytes;
+    use std::io;
+
+    /// Answers a get with its key as the value, a counter with the opaque of the request
+    /// and a missing key with `KeyNotFound`; with `short`, loses the last response,
+    /// with `swapped`, answers the first two requests in reverse order.
+    #[derive(Default)]
+    struct Server {
+        opaques: Vec<u32>,
+        short: bool,
+        swapped: bool,
+    }
+
+    impl RawOperation for Server {
+        fn execute_raw(&mut self, _packet: Packet) -> Result<Packet> {
+            unreachable!()
+        }
+        fn execute_raw_multi(&mut self, packets: Vec<Packet>) -> Result<Vec<Packet>> {
+            let mut responses: Vec<Packet> = packets
+                .into_iter()
+                .map(|request| {
+                    let header = &request.header;
+                    self.opaques.push(header.opaque);
+                    let builder = Packet::builder(header.opcode).opaque(header.opaque);
+                    let builder = match header.opcode {
+                        Opcode::Get if request.key.is_empty() => {
+                            return builder.response(Status::KeyNotFound).unwrap()
+                        }
+                        Opcode::Get => builder
+                            .extras(Extras::Get { flags: 1 })
+                            .value(request.key.clone()),
+                        Opcode::Increment => builder.value(Bytes::copy_from_slice(
+                            &u64::from(header.opaque).to_be_bytes(),
+                        )),
+                        _ => builder,
+                    };
+                    builder.response(Status::NoError).unwrap()
+                })
+                .collect();
+            if self.short {
+                responses.pop();
+            }
+            if self.swapped {
+                responses.swap(0, 1);
+            }
+            Ok(responses)
+        }
+    }
+
+    fn pipeline() -> Pipeline {
+        let mut p = Pipeline::new();
+        p.get(b"k1")
+            .set(b"k2", b"v", 0, 0)
+            .get(b"")
+            .increment(b"k3", 1, 0, 0)
+            .get(b"k4");
+        p
+    }
+
+    #[test]
+    fn test_response_order() {
+        let mut server = Server::default();
+        let responses = pipeline().execute_sync(&mut server).unwrap();
+        assert_eq!(server.opaques, [0, 1, 2, 3, 4]);
+        let hit = |value: &'static str| Response::GetHit {
+            key: Bytes::new(),
+            value: value.into(),
+            flags: 1,
+            cas: 0,
+        };
+        assert_eq!(
+            responses,
+            [
+                hit("k1"),
+                Response::Stored { cas: 0 },
+                Response::Miss,
+                Response::CounterValue(3),
+                hit("k4"),
+            ]
+        );
+
+        // a response out of order is caught by its opaque
+        let mut server = Server {
+            swapped: true,
+            ..Server::default()
+        };
+        match pipeline().execute_sync(&mut server) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_missing_response() {
+        let mut server = Server {
+            short: true,
+            ..Server::default()
+        };
+        match pipeline().execute_sync(&mut server) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -1,8 +1,13 @@
-use crate::Result;
+use crate::{Expiration, FromMemcachedValue, Packet, PacketRef, Result, ToMemcachedValue};
 use bytes::Bytes;
 use semver::Version;
 use std::collections::{BTreeMap, HashMap};
-use super::AuthResponse;
+use std::convert::TryFrom;
+use std::time::Instant;
+use super::{
+    align, dedup_keys, found, is_conflict, not_supported, ok_response, per_key, AuthResponse,
+    DoctorReport, ItemResult, ValueMeta,
+};
 
 pub trait Proto:
 Operation + MultiOperation + ServerOperation + NoReplyOperation + CasOperation + AuthOperation
@@ -31,6 +36,125 @@ pub trait Operation {
     fn append(&mut self, key: &[u8], value: &[u8]) -> Result<()>;
     fn prepend(&mut self, key: &[u8], value: &[u8]) -> Result<()>;
     fn touch(&mut self, key: &[u8], expiration: u32) -> Result<()>;
+    /// Get into `buf`, which is cleared first so it can be reused across calls;
+    /// a miss returns `None` instead of an error.
+    fn get_into(&mut self, key: &[u8], buf: &mut Vec<u8>) -> Result<Option<ValueMeta>>;
+    /// Get and set a new expiration (GAT); the default touches then gets in two round trips,
+    /// a client owning the connection sends a single GAT instead.
+    fn get_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, u32)> {
+        self.touch(key, expiration)?;
+        self.get(key)
+    }
+    /// `get_and_touch` also returning the key (GATK)
+    fn getk_and_touch(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, Bytes, u32)> {
+        self.touch(key, expiration)?;
+        self.getk(key)
+    }
+
+    /// Whether `key` is stored; the default reads the value with `get`,
+    /// a client owning the connection can probe without transferring it.
+    fn exists(&mut self, key: &[u8]) -> Result<bool> {
+        found(self.get(key)).map(|v| v.is_some())
+    }
+
+    /// `set` with an `Expiration`, failing with `InvalidInput` before anything is sent
+    /// if the server would misread it
+    fn set_exp(&mut self, key: &[u8], value: &[u8], flags: u32, exp: Expiration) -> Result<()> {
+        self.set(key, value, flags, u32::try_from(exp)?)
+    }
+
+    /// `add` with an `Expiration`, see `set_exp`
+    fn add_exp(&mut self, key: &[u8], value: &[u8], flags: u32, exp: Expiration) -> Result<()> {
+        self.add(key, value, flags, u32::try_from(exp)?)
+    }
+
+    /// `replace` with an `Expiration`, see `set_exp`
+    fn replace_exp(&mut self, key: &[u8], value: &[u8], flags: u32, exp: Expiration) -> Result<()> {
+        self.replace(key, value, flags, u32::try_from(exp)?)
+    }
+
+    /// `increment` with an `Expiration`, see `set_exp`
+    fn increment_exp(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        exp: Expiration,
+    ) -> Result<u64> {
+        self.increment(key, amount, initial, u32::try_from(exp)?)
+    }
+
+    /// `decrement` with an `Expiration`, see `set_exp`
+    fn decrement_exp(
+        &mut self,
+        key: &[u8],
+        amount: u64,
+        initial: u64,
+        exp: Expiration,
+    ) -> Result<u64> {
+        self.decrement(key, amount, initial, u32::try_from(exp)?)
+    }
+
+    /// `touch` with an `Expiration`, see `set_exp`
+    fn touch_exp(&mut self, key: &[u8], exp: Expiration) -> Result<()> {
+        self.touch(key, u32::try_from(exp)?)
+    }
+
+    /// `get_and_touch` with an `Expiration`, see `set_exp`
+    fn get_and_touch_exp(&mut self, key: &[u8], exp: Expiration) -> Result<(Bytes, u32)> {
+        self.get_and_touch(key, u32::try_from(exp)?)
+    }
+
+    /// `get` returning `None` on a miss instead of a `KeyNotFound` error
+    fn get_opt(&mut self, key: &[u8]) -> Result<Option<(Bytes, u32)>> {
+        found(self.get(key))
+    }
+
+    /// `getk` returning `None` on a miss instead of a `KeyNotFound` error
+    fn getk_opt(&mut self, key: &[u8]) -> Result<Option<(Bytes, Bytes, u32)>> {
+        found(self.getk(key))
+    }
+
+    /// `get` converted to `T`, see `FromMemcachedValue`
+    fn get_as<T>(&mut self, key: &[u8]) -> Result<T>
+    where
+        Self: Sized,
+        T: FromMemcachedValue,
+    {
+        let (value, flags) = self.get(key)?;
+        T::from_value(value, flags)
+    }
+
+    /// `set` of `value` with the flags marking its type, see `ToMemcachedValue`
+    fn set_value<T>(&mut self, key: &[u8], value: &T, expiration: u32) -> Result<()>
+    where
+        Self: Sized,
+        T: ToMemcachedValue + ?Sized,
+    {
+        self.set(key, &value.to_value(), value.flags(), expiration)
+    }
+
+    /// `set` of `value` serialized as JSON, flagged with `JSON_FLAGS`
+    #[cfg(feature = "json")]
+    fn set_json<T>(&mut self, key: &[u8], value: &T, expiration: u32) -> Result<()>
+    where
+        Self: Sized,
+        T: serde::Serialize + ?Sized,
+    {
+        let val = crate::value::to_json(value)?;
+        self.set(key, &val, crate::JSON_FLAGS, expiration)
+    }
+
+    /// `get` of a value stored by `set_json`
+    #[cfg(feature = "json")]
+    fn get_json<T>(&mut self, key: &[u8]) -> Result<T>
+    where
+        Self: Sized,
+        T: serde::de::DeserializeOwned,
+    {
+        let (value, flags) = self.get(key)?;
+        crate::value::from_json(&value, flags)
+    }
 }
 
 pub trait CasOperation {
@@ -72,6 +196,64 @@ pub trait CasOperation {
     fn append_cas(&mut self, key: &[u8], value: &[u8], cas: u64) -> Result<u64>;
     fn prepend_cas(&mut self, key: &[u8], value: &[u8], cas: u64) -> Result<u64>;
     fn touch_cas(&mut self, key: &[u8], expiration: u32, cas: u64) -> Result<u64>;
+    /// Delete only if the item's CAS is still `cas`, fails with `KeyExists` otherwise;
+    /// the default fails with `NotSupported`, reading the CAS before a plain delete would race.
+    fn delete_cas(&mut self, _key: &[u8], _cas: u64) -> Result<()> {
+        Err(not_supported())
+    }
+    /// `get_and_touch` also returning the CAS, the default calls `touch_cas` then `get_cas`
+    fn get_and_touch_cas(&mut self, key: &[u8], expiration: u32) -> Result<(Bytes, u32, u64)> {
+        self.touch_cas(key, expiration, 0)?;
+        self.get_cas(key)
+    }
+    /// `getk_and_touch` also returning the CAS, the default calls `touch_cas` then `getk_cas`
+    fn getk_and_touch_cas(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+    ) -> Result<(Bytes, Bytes, u32, u64)> {
+        self.touch_cas(key, expiration, 0)?;
+        self.getk_cas(key)
+    }
+
+    /// Read-modify-write loop: `f` gets the current value (`None` on a miss) and returns the new
+    /// one, stored with `set_cas` (`add_cas` on a miss) and the current flags; if another client
+    /// got there first, reads again and retries up to `max_retries` times.
+    /// Returns the new CAS, or `None` if `f` returned `None` and nothing was stored.
+    fn update_with<F>(
+        &mut self,
+        key: &[u8],
+        expiration: u32,
+        max_retries: u32,
+        mut f: F,
+    ) -> Result<Option<u64>>
+    where
+        Self: Sized,
+        F: FnMut(Option<Bytes>) -> Option<Bytes>,
+    {
+        let mut retries = 0;
+        loop {
+            let current = found(self.get_cas(key))?;
+            let (value, flags, cas) = match current {
+                Some((value, flags, cas)) => (Some(value), flags, Some(cas)),
+                None => (None, 0, None),
+            };
+            let new = match f(value) {
+                Some(new) => new,
+                None => return Ok(None),
+            };
+            // a server running without CAS answers hits with a CAS of 0
+            let stored = match cas {
+                Some(cas) => self.set_cas(key, &new, flags, expiration, cas),
+                None => self.add_cas(key, &new, flags, expiration),
+            };
+            match stored {
+                Ok(cas) => return Ok(Some(cas)),
+                Err(e) if retries < max_retries && is_conflict(&e) => retries += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
 }
 
 pub trait ServerOperation {
@@ -80,16 +262,162 @@ pub trait ServerOperation {
     fn noop(&mut self) -> Result<()>;
     fn version(&mut self) -> Result<Version>;
     fn stat(&mut self) -> Result<BTreeMap<String, String>>;
+    /// Set the logging verbosity of the server, the default fails with `NotSupported`
+    fn verbosity(&mut self, _level: u32) -> Result<()> {
+        Err(not_supported())
+    }
+
+    /// Check connectivity, version and server clock, collecting every failure in the report
+    fn doctor(&mut self) -> DoctorReport {
+        let mut report = DoctorReport::default();
+        let start = Instant::now();
+        report.noop(start, self.noop());
+        if report.round_trip.is_some() {
+            report.version(self.version());
+            report.stat(self.stat());
+        }
+        report
+    }
 }
 
 pub trait MultiOperation {
     fn set_multi(&mut self, kv: BTreeMap<&[u8], (&[u8], u32, u32)>) -> Result<()>;
     fn delete_multi(&mut self, keys: &[&[u8]]) -> Result<()>;
+    /// Touch every key of `keys`, missing keys are ignored; the default calls `touch`
+    /// for every key, a client owning the connection pipelines the touches behind a `NoOp`
+    /// (Touch has no quiet opcode, the header-only responses are drained).
+    fn touch_multi(&mut self, keys: &[&[u8]], expiration: u32) -> Result<()>
+    where
+        Self: Operation + Sized,
+    {
+        for key in keys {
+            found(self.touch(key, expiration))?;
+        }
+        Ok(())
+    }
     fn increment_multi<'a>(
         &mut self,
         kv: HashMap<&'a [u8], (u64, u64, u32)>,
     ) -> Result<HashMap<&'a [u8], u64>>;
     fn get_multi(&mut self, keys: &[&[u8]]) -> Result<HashMap<Bytes, (Bytes, u32)>>;
+    /// `get_multi` also returning the CAS of every item, for batched optimistic updates;
+    /// the default calls `get_cas` for every key, a client owning the connection pipelines GetKQs.
+    fn get_multi_cas(&mut self, keys: &[&[u8]]) -> Result<HashMap<Bytes, (Bytes, u32, u64)>>
+    where
+        Self: CasOperation + Sized,
+    {
+        let mut items = HashMap::new();
+        for key in dedup_keys(keys) {
+            if let Some(item) = found(self.get_cas(key))? {
+                items.insert(Bytes::copy_from_slice(key), item);
+            }
+        }
+        Ok(items)
+    }
+    /// `(key, value, flags)` of every hit of `keys`, misses are left out; the default
+    /// runs `get_multi` first, a client owning the connection pipelines GetKQs followed by
+    /// a `NoOp` and yields every hit as its response is read.
+    fn get_multi_iter<'a>(
+        &'a mut self,
+        keys: &'a [&'a [u8]],
+    ) -> Box<dyn Iterator<Item = Result<(Bytes, Bytes, u32)>> + 'a> {
+        match self.get_multi(keys) {
+            Ok(hits) => Box::new(
+                hits.into_iter()
+                    .map(|(key, (value, flags))| Ok((key, value, flags))),
+            ),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        }
+    }
+    /// `get_multi` returning one entry per element of `keys`, in the same order,
+    /// `None` for misses; duplicate keys are fetched once and get the same entry.
+    fn get_multi_ordered(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<(Bytes, u32)>>> {
+        let found = self.get_multi(&dedup_keys(keys))?;
+        Ok(align(keys, &found))
+    }
+    /// `get_multi` with an outcome for every requested key, hits, misses and per-key errors alike;
+    /// only transport failures fail the whole call. The default calls `get_cas` for every key.
+    fn get_multi_detailed(&mut self, keys: &[&[u8]]) -> Result<HashMap<Bytes, ItemResult>>
+    where
+        Self: CasOperation + Sized,
+    {
+        let mut items = HashMap::new();
+        for key in dedup_keys(keys) {
+            let item = match self.get_cas(key) {
+                Ok((value, flags, cas)) => ItemResult::Hit { value, flags, cas },
+                Err(crate::Error::Proto(e)) => match e.status() {
+                    crate::Status::KeyNotFound => ItemResult::Miss,
+                    _ => ItemResult::Error(e),
+                },
+                Err(e) => return Err(e),
+            };
+            items.insert(Bytes::copy_from_slice(key), item);
+        }
+        Ok(items)
+    }
+    /// Get and touch every key of `keys`, missing keys are left out; the default calls
+    /// `get_and_touch` for every key, a client owning the connection pipelines quiet GATKQs.
+    fn get_and_touch_multi(
+        &mut self,
+        keys: &[&[u8]],
+        expiration: u32,
+    ) -> Result<HashMap<Bytes, (Bytes, u32)>>
+    where
+        Self: Operation + Sized,
+    {
+        let mut items = HashMap::new();
+        for key in dedup_keys(keys) {
+            if let Some(item) = found(self.get_and_touch(key, expiration))? {
+                items.insert(Bytes::copy_from_slice(key), item);
+            }
+        }
+        Ok(items)
+    }
+    /// Store `(val, flags, expiration, cas)` of every key, a `cas` of 0 always stores;
+    /// returns the new CAS, or the error response, of every key, only I/O errors fail the call.
+    /// The default stores the keys one by one with `set_cas`, a client owning the connection
+    /// can pipeline plain Sets (only their responses carry the new CAS) behind a `NoOp` instead.
+    fn set_multi_cas<'a>(
+        &mut self,
+        kv: BTreeMap<&'a [u8], (&[u8], u32, u32, u64)>,
+    ) -> Result<BTreeMap<&'a [u8], Result<u64>>>
+    where
+        Self: CasOperation + Sized,
+    {
+        kv.into_iter()
+            .map(|(key, (val, flags, expiration, cas))| {
+                Ok((key, per_key(self.set_cas(key, val, flags, expiration, cas))?))
+            })
+            .collect()
+    }
+    /// `set_multi_cas` with Replaces, the default calls `replace_cas` for every key
+    fn replace_multi<'a>(
+        &mut self,
+        kv: BTreeMap<&'a [u8], (&[u8], u32, u32, u64)>,
+    ) -> Result<BTreeMap<&'a [u8], Result<u64>>>
+    where
+        Self: CasOperation + Sized,
+    {
+        kv.into_iter()
+            .map(|(key, (val, flags, expiration, cas))| {
+                Ok((key, per_key(self.replace_cas(key, val, flags, expiration, cas))?))
+            })
+            .collect()
+    }
+    /// Delete every key guarded by its CAS, a `cas` of 0 always deletes; returns the outcome
+    /// of every key, only I/O errors fail the call. The default calls `delete_cas` for every key,
+    /// a client owning the connection pipelines plain Deletes behind a `NoOp` instead.
+    fn delete_multi_cas<'a>(
+        &mut self,
+        kv: BTreeMap<&'a [u8], u64>,
+    ) -> Result<BTreeMap<&'a [u8], Result<()>>>
+    where
+        Self: CasOperation + Sized,
+    {
+        kv.into_iter()
+            .map(|(key, cas)| Ok((key, per_key(self.delete_cas(key, cas))?)))
+            .collect()
+    }
 }
 
 pub trait NoReplyOperation {
@@ -119,6 +447,31 @@ pub trait NoReplyOperation {
     ) -> Result<()>;
     fn append_noreply(&mut self, key: &[u8], value: &[u8]) -> Result<()>;
     fn prepend_noreply(&mut self, key: &[u8], value: &[u8]) -> Result<()>;
+    /// Touch has no quiet opcode: this sends a Touch without waiting, and its header-only
+    /// response is discarded when a later command reads its own, so a stream of touches
+    /// followed by `noop` costs a single round trip.
+    /// The default waits for the response of a plain `touch`, ignoring an error status.
+    fn touch_noreply(&mut self, key: &[u8], expiration: u32) -> Result<()>
+    where
+        Self: Operation + Sized,
+    {
+        per_key(self.touch(key, expiration)).map(drop)
+    }
+    /// FlushQ, the default waits for the response of a plain `flush`
+    fn flush_noreply(&mut self, expiration: u32) -> Result<()>
+    where
+        Self: ServerOperation + Sized,
+    {
+        self.flush(expiration)
+    }
+    /// QuitQ, the server closes the connection without answering;
+    /// the default waits for the response of a plain `quit`
+    fn quit_noreply(&mut self) -> Result<()>
+    where
+        Self: ServerOperation + Sized,
+    {
+        self.quit()
+    }
 }
 
 
@@ -127,3 +480,266 @@ pub trait AuthOperation {
     fn auth_start(&mut self, mech: &str, init: &[u8]) -> Result<AuthResponse>;
     fn auth_continue(&mut self, mech: &str, data: &[u8]) -> Result<AuthResponse>;
 }
+
+pub trait RawOperation {
+    /// Send `packet` as is, with an opaque of the connection's choosing, and return the response;
+    /// for vendor-specific or newer opcodes, errors are reported in the response status.
+    fn execute_raw(&mut self, packet: Packet) -> Result<Packet>;
+    /// Pipelined `execute_raw` followed by a `NoOp`; returns the responses in wire order,
+    /// quiet commands that succeeded have none.
+    fn execute_raw_multi(&mut self, packets: Vec<Packet>) -> Result<Vec<Packet>>;
+    /// Send `packet` with the connection's opaque and return its response,
+    /// unlike `execute_raw` a status other than `NoError` is returned as an error;
+    /// the default copies `packet` into `execute_raw`.
+    fn send_packet(&mut self, packet: PacketRef<'_>) -> Result<Packet>
+    {
+        let request = packet.to_packet();
+        ok_response(self.execute_raw(request)?)
+    }
+    /// Send `packet` with the connection's opaque without waiting for a response;
+    /// any response is discarded, as with the noreply operations. The default waits for
+    /// the response of `execute_raw`, a client owning the connection only writes `packet`.
+    fn send_packet_nowait(&mut self, packet: PacketRef<'_>) -> Result<()>
+    {
+        let request = packet.to_packet();
+        self.execute_raw(request).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mock::Memory;
+    use super::*;
+    use crate::{Error, Status};
+
+    fn status<T: std::fmt::Debug>(result: Result<T>) -> Status {
+        match result {
+            Err(Error::Proto(e)) => e.status(),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn append_x(value: Option<Bytes>) -> Option<Bytes> {
+        let mut new = value.map(|v| v.to_vec()).unwrap_or_default();
+        new.push(b'x');
+        Some(new.into())
+    }
+
+    #[test]
+    fn test_update_with_add_retry() {
+        let mut client = Memory::default();
+        client.conflicts.push_back(Status::KeyExits);
+        let cas = client.update_with(b"k", 0, 1, append_x).unwrap();
+        assert_eq!(client.get_cas(b"k").unwrap(), ("x".into(), 0, cas.unwrap()));
+        assert_eq!(
+            client.calls,
+            ["get_cas", "add_cas", "get_cas", "add_cas", "get_cas"]
+        );
+    }
+
+    #[test]
+    fn test_update_with_set_cas_retry() {
+        let mut client = Memory::default();
+        client.set(b"k", b"a", 7, 0).unwrap();
+        // deleted by another client between the read and the store
+        client.conflicts.push_back(Status::KeyNotFound);
+        let cas = client.update_with(b"k", 0, 1, append_x).unwrap();
+        assert_eq!(
+            client.get_cas(b"k").unwrap(),
+            ("ax".into(), 7, cas.unwrap())
+        );
+        assert_eq!(
+            &client.calls[1..],
+            ["get_cas", "set_cas", "get_cas", "set_cas", "get_cas"]
+        );
+    }
+
+    #[test]
+    fn test_update_with_cas_disabled() {
+        let mut client = Memory::default();
+        client.items.insert(b"k".to_vec(), ("a".into(), 7, 0));
+        client.update_with(b"k", 0, 0, append_x).unwrap();
+        assert_eq!(client.get(b"k").unwrap(), ("ax".into(), 7));
+        assert_eq!(client.calls, ["get_cas", "set_cas", "get"]);
+    }
+
+    #[test]
+    fn test_update_with_gives_up() {
+        let mut client = Memory::default();
+        client.set(b"k", b"a", 0, 0).unwrap();
+        client.conflicts.extend(vec![Status::KeyExits; 3]);
+        assert_eq!(
+            status(client.update_with(b"k", 0, 2, append_x)),
+            Status::KeyExits
+        );
+        let stores = client.calls.iter().filter(|c| **c == "set_cas").count();
+        assert_eq!(stores, 3);
+        assert_eq!(client.get(b"k").unwrap().0, "a");
+
+        // other errors aren't retried
+        client
+            .conflicts
+            .extend(vec![Status::OutOfMemory, Status::OutOfMemory]);
+        assert_eq!(
+            status(client.update_with(b"k", 0, 2, append_x)),
+            Status::OutOfMemory
+        );
+        assert_eq!(client.conflicts.len(), 1);
+    }
+
+    #[test]
+    fn test_update_with_nothing_to_store() {
+        let mut client = Memory::default();
+        assert_eq!(client.update_with(b"k", 0, 1, |_| None).unwrap(), None);
+        assert!(client.items.is_empty());
+        assert_eq!(client.calls, ["get_cas"]);
+    }
+
+    #[test]
+    fn test_exp() {
+        let mut client = Memory::default();
+        client
+            .set_exp(b"k", b"v", 0, Expiration::Seconds(60))
+            .unwrap();
+        client.touch_exp(b"k", Expiration::Never).unwrap();
+        assert_eq!(client.calls, ["set", "touch"]);
+
+        let too_long = Expiration::Seconds(crate::MAX_RELATIVE_SECS + 1);
+        match client.add_exp(b"other", b"v", 0, too_long) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(client.get_and_touch_exp(b"k", too_long).is_err());
+        // nothing sent
+        assert_eq!(client.calls, ["set", "touch"]);
+    }
+
+    #[test]
+    fn test_set_multi_cas() {
+        let mut client = Memory::default();
+        let cas = client.set_cas(b"a", b"1", 0, 0, 0).unwrap();
+        let mut kv = BTreeMap::new();
+        kv.insert(&b"a"[..], (&b"2"[..], 0, 0, cas + 1));
+        kv.insert(&b"b"[..], (&b"2"[..], 0, 0, 0));
+        let stored = client.set_multi_cas(kv.clone()).unwrap();
+        assert!(matches!(&stored[&b"a"[..]], Err(e) if is_conflict(e)));
+        assert!(stored[&b"b"[..]].is_ok());
+
+        let replaced = client.replace_multi(kv).unwrap();
+        assert!(matches!(&replaced[&b"a"[..]], Err(e) if is_conflict(e)));
+        assert!(replaced[&b"b"[..]].is_ok());
+    }
+
+    #[test]
+    fn test_get_and_touch() {
+        let mut client = Memory::default();
+        client.set(b"a", b"1", 3, 0).unwrap();
+        client.calls.clear();
+        assert_eq!(client.get_and_touch(b"a", 60).unwrap(), ("1".into(), 3));
+        assert_eq!(client.calls, ["touch", "get"]);
+        assert_eq!(status(client.getk_and_touch(b"b", 60)), Status::KeyNotFound);
+        assert_eq!(client.get_and_touch_cas(b"a", 60).unwrap().0, "1");
+
+        let items = client.get_and_touch_multi(&[b"a", b"b", b"a"], 60).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[&b"a"[..]], ("1".into(), 3));
+    }
+
+    #[test]
+    fn test_touch_noreply() {
+        let mut client = Memory::default();
+        client.touch_noreply(b"missing", 60).unwrap();
+        assert_eq!(client.calls, ["touch"]);
+    }
+
+    #[test]
+    fn test_delete_multi_cas() {
+        let mut client = Memory::default();
+        let a = client.set_cas(b"a", b"1", 0, 0, 0).unwrap();
+        let b = client.set_cas(b"b", b"1", 0, 0, 0).unwrap();
+        let mut kv = BTreeMap::new();
+        kv.insert(&b"a"[..], a);
+        kv.insert(&b"b"[..], b + 1);
+        kv.insert(&b"c"[..], 0);
+        let deleted = client.delete_multi_cas(kv).unwrap();
+        assert!(deleted[&b"a"[..]].is_ok());
+        assert!(matches!(&deleted[&b"b"[..]], Err(e) if is_conflict(e)));
+        assert!(matches!(&deleted[&b"c"[..]], Err(e) if is_conflict(e)));
+        assert!(client.items.contains_key(&b"b"[..]));
+    }
+
+    #[test]
+    fn test_exists() {
+        let mut client = Memory::default();
+        client.set(b"a", b"1", 0, 0).unwrap();
+        assert!(client.exists(b"a").unwrap());
+        assert!(!client.exists(b"b").unwrap());
+    }
+
+    #[test]
+    fn test_touch_multi() {
+        let mut client = Memory::default();
+        client.set(b"a", b"1", 0, 0).unwrap();
+        client.touch_multi(&[b"a", b"missing"], 60).unwrap();
+        assert_eq!(&client.calls[1..], ["touch", "touch"]);
+    }
+
+    #[test]
+    fn test_get_multi_detailed() {
+        let mut client = Memory::default();
+        client.set(b"a", b"1", 3, 0).unwrap();
+        let items = client.get_multi_detailed(&[b"a", b"b"]).unwrap();
+        assert!(matches!(&items[&b"a"[..]], ItemResult::Hit { flags: 3, .. }));
+        assert!(matches!(&items[&b"b"[..]], ItemResult::Miss));
+    }
+
+    #[test]
+    fn test_get_multi_iter() {
+        let mut client = Memory::default();
+        client.set(b"a", b"1", 3, 0).unwrap();
+        let hits: Vec<_> = client
+            .get_multi_iter(&[b"a", b"b"])
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(hits, vec![(Bytes::from("a"), Bytes::from("1"), 3)]);
+    }
+
+    #[test]
+    fn test_get_multi_cas() {
+        let mut client = Memory::default();
+        let cas = client.set_cas(b"a", b"1", 3, 0, 0).unwrap();
+        let items = client.get_multi_cas(&[b"a", b"b", b"a"]).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[&b"a"[..]], (Bytes::from("1"), 3, cas));
+    }
+
+    /// Answers every request with its status and the request key as the value
+    struct Raw(Status);
+
+    impl RawOperation for Raw {
+        fn execute_raw(&mut self, packet: Packet) -> Result<Packet> {
+            let builder = Packet::builder(packet.header.opcode).value(packet.key);
+            Ok(builder.response(self.0).unwrap())
+        }
+        fn execute_raw_multi(&mut self, _packets: Vec<Packet>) -> Result<Vec<Packet>> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_send_packet() {
+        let request = Packet::builder(crate::Opcode::Get).key("k").request().unwrap();
+        let response = Raw(Status::NoError).send_packet(request.as_ref()).unwrap();
+        assert_eq!(response.val, "k");
+        match Raw(Status::KeyNotFound).send_packet(request.as_ref()) {
+            Err(Error::Proto(e)) => {
+                assert_eq!(e.status(), Status::KeyNotFound);
+                assert_eq!(e.detail().as_deref(), Some("k"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // the response is discarded, errors included
+        let mut raw = Raw(Status::KeyNotFound);
+        raw.send_packet_nowait(request.as_ref()).unwrap();
+    }
+}
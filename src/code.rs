@@ -1,10 +1,50 @@
+#[cfg(feature = "std")]
 use crate::error::ProtoError;
+#[cfg(feature = "std")]
 use crate::Result;
 use num_derive::FromPrimitive;
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone, FromPrimitive)]
+/// Declares a C-like enum over `$raw` plus an `$other($raw)` fallback variant,
+/// so values this crate doesn't know about still convert both ways.
+macro_rules! raw_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident($raw:ty, $other:ident) {
+            $($(#[$vmeta:meta])* $variant:ident = $value:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        pub enum $name {
+            $($(#[$vmeta])* $variant,)*
+            /// Any value not listed above, never one that is
+            $other($raw),
+        }
+
+        impl From<$raw> for $name {
+            fn from(v: $raw) -> Self {
+                match v {
+                    $($value => Self::$variant,)*
+                    v => Self::$other(v),
+                }
+            }
+        }
+
+        impl From<$name> for $raw {
+            fn from(v: $name) -> Self {
+                match v {
+                    $($name::$variant => $value,)*
+                    $name::$other(v) => v,
+                }
+            }
+        }
+    };
+}
+
+raw_enum! {
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
-pub enum Opcode {
+pub enum Opcode(u8, Other) {
     Get = 0x00,
     Set = 0x01,
     Add = 0x02,
@@ -40,6 +80,9 @@ pub enum Opcode {
     SASLListMechs = 0x20,
     SASLAuth = 0x21,
     SASLStep = 0x22,
+    /// Get and touch, returning the key
+    GATK = 0x23,
+    GATKQ = 0x24,
     // These commands are used for range operations and exist within
     // this header for use in other projects.  Range operations are
     // not expected to be implemented in the memcached server itself.
@@ -69,16 +112,338 @@ pub enum Opcode {
     TapCheckPointStart = 0x46,
     TabCheckPointEnd = 0x47,
 }
+}
+
+// (noisy, quiet)
+const QUIET_PAIRS: &[(Opcode, Opcode)] = &[
+    (Opcode::Get, Opcode::GetQ),
+    (Opcode::GetK, Opcode::GetKQ),
+    (Opcode::Set, Opcode::SetQ),
+    (Opcode::Add, Opcode::AddQ),
+    (Opcode::Replace, Opcode::ReplaceQ),
+    (Opcode::Delete, Opcode::DeleteQ),
+    (Opcode::Increment, Opcode::IncrementQ),
+    (Opcode::Decrement, Opcode::DecrementQ),
+    (Opcode::Quit, Opcode::QuitQ),
+    (Opcode::Flush, Opcode::FlushQ),
+    (Opcode::Append, Opcode::AppendQ),
+    (Opcode::Prepend, Opcode::PrependQ),
+    (Opcode::GAT, Opcode::GATQ),
+    (Opcode::GATK, Opcode::GATKQ),
+    (Opcode::RSet, Opcode::RSetQ),
+    (Opcode::RAppend, Opcode::RAppendQ),
+    (Opcode::RPrepend, Opcode::RPrependQ),
+    (Opcode::RDelete, Opcode::RDeleteQ),
+    (Opcode::RIncr, Opcode::RIncrQ),
+    (Opcode::RDecr, Opcode::RDecrQ),
+];
+
+impl Opcode {
+    /// Whether the server only answers this command on failure (or a hit, for GetQ/GetKQ/GATQ/GATKQ)
+    #[inline]
+    pub fn is_quiet(self) -> bool {
+        QUIET_PAIRS.iter().any(|&(_, quiet)| quiet == self)
+    }
+
+    /// Whether a request may carry a key, `true` for unknown opcodes
+    pub fn allows_key(self) -> bool {
+        !matches!(
+            self,
+            Self::Quit
+                | Self::QuitQ
+                | Self::Flush
+                | Self::FlushQ
+                | Self::NoOp
+                | Self::Version
+                | Self::Verbosity
+                | Self::SASLListMechs
+        )
+    }
+
+    /// Whether a request must carry a zero CAS, checked by `Packet::validate`
+    pub(crate) fn forbids_cas(self) -> bool {
+        matches!(
+            self,
+            Self::Add
+                | Self::AddQ
+                | Self::Get
+                | Self::GetQ
+                | Self::GetK
+                | Self::GetKQ
+                | Self::GAT
+                | Self::GATQ
+                | Self::GATK
+                | Self::GATKQ
+                | Self::Flush
+                | Self::FlushQ
+                | Self::Quit
+                | Self::QuitQ
+                | Self::NoOp
+                | Self::Version
+                | Self::Stat
+                | Self::Verbosity
+        )
+    }
+
+    /// Whether a request may carry a value, `true` for unknown opcodes
+    pub fn allows_value(self) -> bool {
+        !matches!(
+            self,
+            Self::Get
+                | Self::GetQ
+                | Self::GetK
+                | Self::GetKQ
+                | Self::Delete
+                | Self::DeleteQ
+                | Self::Increment
+                | Self::IncrementQ
+                | Self::Decrement
+                | Self::DecrementQ
+                | Self::Quit
+                | Self::QuitQ
+                | Self::Flush
+                | Self::FlushQ
+                | Self::NoOp
+                | Self::Version
+                | Self::Stat
+                | Self::Verbosity
+                | Self::Touch
+                | Self::GAT
+                | Self::GATQ
+                | Self::GATK
+                | Self::GATKQ
+                | Self::SASLListMechs
+        )
+    }
+
+    /// Whether a request may carry extras, `true` for unknown opcodes
+    pub fn allows_extras_on_request(self) -> bool {
+        !matches!(
+            self,
+            Self::Get
+                | Self::GetQ
+                | Self::GetK
+                | Self::GetKQ
+                | Self::Delete
+                | Self::DeleteQ
+                | Self::Quit
+                | Self::QuitQ
+                | Self::NoOp
+                | Self::Version
+                | Self::Append
+                | Self::AppendQ
+                | Self::Prepend
+                | Self::PrependQ
+                | Self::Stat
+                | Self::SASLListMechs
+                | Self::SASLAuth
+                | Self::SASLStep
+        )
+    }
+
+    /// Whether the command changes stored items, their expiration included
+    pub fn is_mutation(self) -> bool {
+        matches!(
+            self,
+            Self::Set
+                | Self::SetQ
+                | Self::Add
+                | Self::AddQ
+                | Self::Replace
+                | Self::ReplaceQ
+                | Self::Delete
+                | Self::DeleteQ
+                | Self::Increment
+                | Self::IncrementQ
+                | Self::Decrement
+                | Self::DecrementQ
+                | Self::Flush
+                | Self::FlushQ
+                | Self::Append
+                | Self::AppendQ
+                | Self::Prepend
+                | Self::PrependQ
+                | Self::Touch
+                | Self::GAT
+                | Self::GATQ
+                | Self::GATK
+                | Self::GATKQ
+                | Self::RSet
+                | Self::RSetQ
+                | Self::RAppend
+                | Self::RAppendQ
+                | Self::RPrepend
+                | Self::RPrependQ
+                | Self::RDelete
+                | Self::RDeleteQ
+                | Self::RIncr
+                | Self::RIncrQ
+                | Self::RDecr
+                | Self::RDecrQ
+        )
+    }
+
+    /// Whether the command returns stored values; the get-and-touch commands are both this and a mutation
+    pub fn is_retrieval(self) -> bool {
+        matches!(
+            self,
+            Self::Get
+                | Self::GetQ
+                | Self::GetK
+                | Self::GetKQ
+                | Self::GAT
+                | Self::GATQ
+                | Self::GATK
+                | Self::GATKQ
+                | Self::RGet
+        )
+    }
+
+    /// Whether the command is one of the range commands, operating on the keys between a start and an end key
+    pub fn is_range(self) -> bool {
+        matches!(
+            self,
+            Self::RGet
+                | Self::RSet
+                | Self::RSetQ
+                | Self::RAppend
+                | Self::RAppendQ
+                | Self::RPrepend
+                | Self::RPrependQ
+                | Self::RDelete
+                | Self::RDeleteQ
+                | Self::RIncr
+                | Self::RIncrQ
+                | Self::RDecr
+                | Self::RDecrQ
+        )
+    }
+
+    /// Quiet variant of a command that has one (`self` if already quiet), `None` otherwise
+    ///
+    /// # Examples
+    /// ```rust
+    /// use memcached_proto::Opcode;
+    ///
+    /// assert_eq!(Opcode::Set.quiet(), Some(Opcode::SetQ));
+    /// assert_eq!(Opcode::SetQ.noisy(), Some(Opcode::Set));
+    /// assert_eq!(Opcode::NoOp.quiet(), None);
+    /// ```
+    pub fn quiet(self) -> Option<Self> {
+        QUIET_PAIRS
+            .iter()
+            .find(|&&(noisy, quiet)| noisy == self || quiet == self)
+            .map(|&(_, quiet)| quiet)
+    }
+
+    /// Non-quiet variant of a command that has a quiet one (`self` if not quiet), `None` otherwise
+    pub fn noisy(self) -> Option<Self> {
+        QUIET_PAIRS
+            .iter()
+            .find(|&&(noisy, quiet)| noisy == self || quiet == self)
+            .map(|&(noisy, _)| noisy)
+    }
+}
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Magic {
     Request = 0x80,
     Response = 0x81,
+    /// Request with framing extras
+    AltRequest = 0x08,
+    /// Response with framing extras
+    AltResponse = 0x18,
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone, FromPrimitive)]
+impl Magic {
+    #[inline]
+    pub fn is_request(self) -> bool {
+        matches!(self, Self::Request | Self::AltRequest)
+    }
+    #[inline]
+    pub fn is_response(self) -> bool {
+        matches!(self, Self::Response | Self::AltResponse)
+    }
+    /// Whether the header carries a framing extras length (alternative magics)
+    #[inline]
+    pub fn is_alt(self) -> bool {
+        matches!(self, Self::AltRequest | Self::AltResponse)
+    }
+}
+
+/// Data type bits of the header, `DataType::RAW` (0x00) unless negotiated with the server
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DataType(u8);
+
+impl DataType {
+    pub const RAW: Self = Self(0x00);
+    /// The value is a JSON document
+    pub const JSON: Self = Self(0x01);
+    /// The value is compressed with snappy
+    pub const SNAPPY: Self = Self(0x02);
+    /// The value starts with extended attributes
+    pub const XATTR: Self = Self(0x04);
+
+    #[inline]
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+    #[inline]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+    /// Whether all the bits of `other` are set
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+    #[inline]
+    pub const fn is_raw(self) -> bool {
+        self.0 == 0
+    }
+    #[inline]
+    pub const fn is_json(self) -> bool {
+        self.contains(Self::JSON)
+    }
+    #[inline]
+    pub const fn is_snappy(self) -> bool {
+        self.contains(Self::SNAPPY)
+    }
+}
+
+impl core::ops::BitOr for DataType {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for DataType {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0
+    }
+}
+
+impl From<u8> for DataType {
+    fn from(bits: u8) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<DataType> for u8 {
+    fn from(v: DataType) -> Self {
+        v.0
+    }
+}
+
+raw_enum! {
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
-pub enum Status {
+pub enum Status(u16, Unknown) {
     NoError = 0x0000,
     KeyNotFound = 0x0001,
     KeyExits = 0x0002,
@@ -98,6 +463,7 @@ pub enum Status {
     AuthenticationRequired = 0x0020,
     AuthenticationFurtherStepRequired = 0x0021,
 }
+}
 impl Status {
     pub fn desc(&self) -> &'static str {
         match self {
@@ -119,8 +485,10 @@ impl Status {
             Self::TemporaryFailure => "temporary failure",
             Self::AuthenticationRequired => "authentication required/not successful",
             Self::AuthenticationFurtherStepRequired => "further authentication steps required",
+            Self::Unknown(_) => "unknown status",
         }
     }
+    #[cfg(feature = "std")]
     pub fn ok_or(self, detail: Option<String>) -> Result<()> {
         match self {
             Self::NoError => Ok(()),
@@ -133,3 +501,15 @@ impl Default for Status {
         Self::NoError
     }
 }
+
+raw_enum! {
+/// State of a vbucket, as carried by the SetVBucket extras and the GetVBucket response
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VBucketState(u32, Other) {
+    Active = 1,
+    Replica = 2,
+    Pending = 3,
+    Dead = 4,
+}
+}
@@ -0,0 +1,131 @@
+//! `tokio_util::codec` support, for `Framed` pipelines and tokio server scaffolding
+use crate::packet::{Packet, PacketHeader};
+use crate::ParseError;
+use bytes::BytesMut;
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Codec of binary protocol packets, in both directions;
+/// decoding rejects bodies longer than `max_body_len` like `Packet::read_from_limited`.
+#[derive(Debug, Clone)]
+pub struct BinaryCodec {
+    max_body_len: u32,
+}
+
+impl BinaryCodec {
+    /// Codec limited to `Packet::default_max_body_len`
+    pub fn new() -> Self {
+        Self::with_max_body_len(Packet::default_max_body_len())
+    }
+
+    pub fn with_max_body_len(max_body_len: u32) -> Self {
+        Self { max_body_len }
+    }
+}
+
+impl Default for BinaryCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder<Packet> for BinaryCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: Packet, dst: &mut BytesMut) -> io::Result<()> {
+        dst.reserve(packet.encoded_len());
+        packet.encode_to(dst);
+        Ok(())
+    }
+}
+
+impl Decoder for BinaryCodec {
+    type Item = Packet;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Packet>> {
+        let header = match PacketHeader::try_parse(src) {
+            Ok(header) => header,
+            Err(ParseError::Incomplete { needed }) => {
+                src.reserve(needed);
+                return Ok(None);
+            }
+            Err(ParseError::Invalid) => return Err(io::ErrorKind::InvalidData.into()),
+        };
+        header.check_body_len(self.max_body_len)?;
+        let frame_len = PacketHeader::size() + header.body_len as usize;
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+        let mut frame = src.split_to(frame_len);
+        let body = frame.split_off(PacketHeader::size());
+        Packet::parse_body(header, body).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BinaryCodec;
+    use crate::{BodyTooLarge, Extras, Opcode, Packet, Status};
+    use bytes::BytesMut;
+    use std::io;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    fn get_hit() -> Packet {
+        Packet::builder(Opcode::Get)
+            .key("key")
+            .value("value")
+            .response(Status::NoError)
+            .unwrap()
+    }
+
+    fn set() -> Packet {
+        Packet::builder(Opcode::Set)
+            .extras(Extras::Store {
+                flags: 0,
+                expiration: 0,
+            })
+            .key("key")
+            .value(vec![0u8; 64])
+            .request()
+            .unwrap()
+    }
+
+    fn encoded(packet: Packet) -> BytesMut {
+        let mut buf = BytesMut::new();
+        BinaryCodec::new().encode(packet, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_split_frame() {
+        let bytes = encoded(get_hit());
+        let mut codec = BinaryCodec::new();
+        let mut src = BytesMut::new();
+        // cut in the header, then in the body
+        for end in &[10, 30] {
+            src.extend_from_slice(&bytes[src.len()..*end]);
+            assert_eq!(codec.decode(&mut src).unwrap(), None);
+        }
+        src.extend_from_slice(&bytes[30..]);
+        src.extend_from_slice(&bytes[..5]);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(get_hit()));
+        // the start of the next packet stays buffered
+        assert_eq!(&src[..], &bytes[..5]);
+    }
+
+    #[test]
+    fn test_max_body_len() {
+        let mut src = encoded(set());
+        let err = BinaryCodec::with_max_body_len(32)
+            .decode(&mut src)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.into_inner().unwrap().is::<BodyTooLarge>());
+
+        let mut src = encoded(set());
+        let decoded = BinaryCodec::with_max_body_len(256).decode(&mut src);
+        assert_eq!(decoded.unwrap(), Some(set()));
+    }
+}
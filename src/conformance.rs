@@ -0,0 +1,596 @@
+//! Scripted protocol conformance checks, for servers and client implementations claiming
+//! compatibility with the binary protocol; behind the `conformance` feature.
+//!
+//! The checks only touch keys prefixed with `conformance:`.
+use crate::client::sync::{AuthOperation, NoReplyOperation, Operation};
+use crate::client::AuthResponse;
+use crate::{Error, Extras, Opcode, Packet, Status, SyncOps};
+use bytes::Bytes;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+const KEY: &[u8] = b"conformance:key";
+const COUNTER: &[u8] = b"conformance:counter";
+
+/// Checks that passed and, for every other one, a description of the violation
+#[derive(Debug, Default)]
+pub struct Report {
+    pub passed: Vec<&'static str>,
+    pub violations: Vec<(&'static str, String)>,
+}
+
+impl Report {
+    pub fn is_conformant(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    fn record(&mut self, name: &'static str, outcome: Result<(), String>) {
+        match outcome {
+            Ok(()) => self.passed.push(name),
+            Err(violation) => self.violations.push((name, violation)),
+        }
+    }
+}
+
+/// One line per violation, then the count of passed checks
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, violation) in &self.violations {
+            writeln!(f, "{}: {}", name, violation)?;
+        }
+        write!(
+            f,
+            "{} of {} checks passed",
+            self.passed.len(),
+            self.passed.len() + self.violations.len()
+        )
+    }
+}
+
+trait Transport: Read + Write {}
+
+impl<T: Read + Write> Transport for T {}
+
+/// A connection to the server under test
+struct Session {
+    stream: Box<dyn Transport>,
+    opaque: u32,
+}
+
+type Outcome = Result<(), String>;
+
+impl Session {
+    fn send(&mut self, opcode: Opcode, cas: u64, extras: Extras, key: &[u8], val: &[u8]) -> u32 {
+        self.opaque = self.opaque.wrapping_add(1);
+        let packet = Packet::request(
+            opcode,
+            0,
+            self.opaque,
+            cas,
+            extras,
+            Bytes::copy_from_slice(key),
+            Bytes::copy_from_slice(val),
+        );
+        // a failed write shows up as a failed read
+        let _ = packet.write_to(&mut self.stream);
+        self.opaque
+    }
+
+    fn recv(&mut self) -> Result<Packet, String> {
+        let resp = Packet::read_from(&mut self.stream).map_err(|e| e.to_string())?;
+        if !resp.is_response() {
+            return Err(format!("magic {:?} in a response", resp.header.magic));
+        }
+        Ok(resp)
+    }
+
+    /// Round trip, checking the response echoes the opcode and opaque
+    fn call(
+        &mut self,
+        opcode: Opcode,
+        cas: u64,
+        extras: Extras,
+        key: &[u8],
+        val: &[u8],
+    ) -> Result<Packet, String> {
+        let opaque = self.send(opcode, cas, extras, key, val);
+        let resp = self.recv()?;
+        if resp.header.opcode != opcode || resp.header.opaque != opaque {
+            return Err(format!(
+                "response {} doesn't echo {:?} opaque={:#x}",
+                resp.header, opcode, opaque
+            ));
+        }
+        Ok(resp)
+    }
+
+    /// Send a quiet command then a `NoOp`, the next response must be the `NoOp`'s
+    fn quiet(&mut self, opcode: Opcode, extras: Extras, key: &[u8], val: &[u8]) -> Outcome {
+        self.send(opcode, 0, extras, key, val);
+        let opaque = self.send(Opcode::NoOp, 0, Extras::None, b"", b"");
+        let resp = self.recv()?;
+        if resp.header.opaque != opaque {
+            return Err(format!("{:?} answered with {}", opcode, resp.header));
+        }
+        Ok(())
+    }
+}
+
+fn store(flags: u32) -> Extras {
+    Extras::Store {
+        flags,
+        expiration: 0,
+    }
+}
+
+fn counter(amount: u64, initial: u64) -> Extras {
+    Extras::Counter {
+        amount,
+        initial,
+        expiration: 0,
+    }
+}
+
+fn expect(resp: &Packet, status: Status) -> Outcome {
+    if resp.status() == status {
+        Ok(())
+    } else {
+        Err(format!("expected {:?}, got {:?}", status, resp.status()))
+    }
+}
+
+fn expect_val(resp: &Packet, val: &[u8]) -> Outcome {
+    expect(resp, Status::NoError)?;
+    if resp.val == val {
+        Ok(())
+    } else {
+        Err(format!("expected value {:?}, got {:?}", val, resp.val))
+    }
+}
+
+type EndpointCheck = fn(&mut Session) -> Outcome;
+
+const ENDPOINT_CHECKS: &[(&str, EndpointCheck)] = &[
+    ("set then get returns the value and flags", |s| {
+        expect(
+            &s.call(Opcode::Set, 0, store(7), KEY, b"v")?,
+            Status::NoError,
+        )?;
+        let resp = s.call(Opcode::Get, 0, Extras::None, KEY, b"")?;
+        expect_val(&resp, b"v")?;
+        match resp.extras {
+            Extras::Get { flags: 7 } => Ok(()),
+            ref e => Err(format!("unexpected extras {:?}", e)),
+        }
+    }),
+    ("getk echoes the key", |s| {
+        s.call(Opcode::Set, 0, store(0), KEY, b"v")?;
+        let resp = s.call(Opcode::GetK, 0, Extras::None, KEY, b"")?;
+        expect_val(&resp, b"v")?;
+        if resp.key == KEY {
+            Ok(())
+        } else {
+            Err(format!("unexpected key {:?}", resp.key))
+        }
+    }),
+    ("get of a missing key is KeyNotFound", |s| {
+        s.call(Opcode::Delete, 0, Extras::None, KEY, b"")?;
+        expect(
+            &s.call(Opcode::Get, 0, Extras::None, KEY, b"")?,
+            Status::KeyNotFound,
+        )
+    }),
+    ("add of an existing key is KeyExists", |s| {
+        s.call(Opcode::Set, 0, store(0), KEY, b"v")?;
+        expect(
+            &s.call(Opcode::Add, 0, store(0), KEY, b"v")?,
+            Status::KeyExits,
+        )
+    }),
+    ("replace of a missing key is KeyNotFound", |s| {
+        s.call(Opcode::Delete, 0, Extras::None, KEY, b"")?;
+        expect(
+            &s.call(Opcode::Replace, 0, store(0), KEY, b"v")?,
+            Status::KeyNotFound,
+        )
+    }),
+    ("append and prepend", |s| {
+        s.call(Opcode::Set, 0, store(0), KEY, b"b")?;
+        expect(
+            &s.call(Opcode::Append, 0, Extras::None, KEY, b"c")?,
+            Status::NoError,
+        )?;
+        expect(
+            &s.call(Opcode::Prepend, 0, Extras::None, KEY, b"a")?,
+            Status::NoError,
+        )?;
+        expect_val(&s.call(Opcode::Get, 0, Extras::None, KEY, b"")?, b"abc")
+    }),
+    ("set with a stale CAS is KeyExists", |s| {
+        let cas = s.call(Opcode::Set, 0, store(0), KEY, b"v")?.header.cas;
+        if cas == 0 {
+            return Err("set returned a zero CAS".to_string());
+        }
+        expect(
+            &s.call(Opcode::Set, cas.wrapping_add(1), store(0), KEY, b"w")?,
+            Status::KeyExits,
+        )?;
+        expect(
+            &s.call(Opcode::Set, cas, store(0), KEY, b"w")?,
+            Status::NoError,
+        )
+    }),
+    ("delete of a missing key is KeyNotFound", |s| {
+        s.call(Opcode::Set, 0, store(0), KEY, b"v")?;
+        expect(
+            &s.call(Opcode::Delete, 0, Extras::None, KEY, b"")?,
+            Status::NoError,
+        )?;
+        expect(
+            &s.call(Opcode::Delete, 0, Extras::None, KEY, b"")?,
+            Status::KeyNotFound,
+        )
+    }),
+    ("increment creates with the initial value", |s| {
+        s.call(Opcode::Delete, 0, Extras::None, COUNTER, b"")?;
+        let resp = s.call(Opcode::Increment, 0, counter(5, 10), COUNTER, b"")?;
+        expect_val(&resp, &10u64.to_be_bytes())?;
+        let resp = s.call(Opcode::Increment, 0, counter(5, 10), COUNTER, b"")?;
+        expect_val(&resp, &15u64.to_be_bytes())
+    }),
+    ("decrement stops at 0", |s| {
+        s.call(Opcode::Delete, 0, Extras::None, COUNTER, b"")?;
+        s.call(Opcode::Increment, 0, counter(0, 3), COUNTER, b"")?;
+        let resp = s.call(Opcode::Decrement, 0, counter(5, 0), COUNTER, b"")?;
+        expect_val(&resp, &0u64.to_be_bytes())
+    }),
+    ("increment of a non-numeric value", |s| {
+        s.call(Opcode::Set, 0, store(0), KEY, b"v")?;
+        expect(
+            &s.call(Opcode::Increment, 0, counter(1, 0), KEY, b"")?,
+            Status::IncrOrDecrOnNonNumericValue,
+        )
+    }),
+    ("successful quiet set is silent", |s| {
+        s.quiet(Opcode::SetQ, store(0), KEY, b"q")?;
+        expect_val(&s.call(Opcode::Get, 0, Extras::None, KEY, b"")?, b"q")
+    }),
+    ("quiet get miss is silent", |s| {
+        s.call(Opcode::Delete, 0, Extras::None, KEY, b"")?;
+        s.quiet(Opcode::GetQ, Extras::None, KEY, b"")
+    }),
+    ("quiet get hit is answered", |s| {
+        s.call(Opcode::Set, 0, store(0), KEY, b"v")?;
+        let opaque = s.send(Opcode::GetQ, 0, Extras::None, KEY, b"");
+        s.send(Opcode::NoOp, 0, Extras::None, b"", b"");
+        let resp = s.recv()?;
+        if resp.header.opaque != opaque {
+            return Err(format!("expected the GetQ response, got {}", resp.header));
+        }
+        expect_val(&resp, b"v")?;
+        expect(&s.recv()?, Status::NoError)
+    }),
+    ("failed quiet add is answered", |s| {
+        s.call(Opcode::Set, 0, store(0), KEY, b"v")?;
+        let opaque = s.send(Opcode::AddQ, 0, store(0), KEY, b"v");
+        s.send(Opcode::NoOp, 0, Extras::None, b"", b"");
+        let resp = s.recv()?;
+        if resp.header.opaque != opaque {
+            return Err(format!("expected the AddQ response, got {}", resp.header));
+        }
+        expect(&resp, Status::KeyExits)?;
+        expect(&s.recv()?, Status::NoError)
+    }),
+    ("version is text", |s| {
+        let resp = s.call(Opcode::Version, 0, Extras::None, b"", b"")?;
+        expect(&resp, Status::NoError)?;
+        std::str::from_utf8(&resp.val)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }),
+    ("unknown opcode is UnknownCommand", |s| {
+        expect(
+            &s.call(Opcode::Other(0xfd), 0, Extras::None, b"", b"")?,
+            Status::UnknownCommand,
+        )
+    }),
+    ("SASL mechanisms are text, or SASL is unsupported", |s| {
+        let resp = s.call(Opcode::SASLListMechs, 0, Extras::None, b"", b"")?;
+        match resp.status() {
+            Status::NoError => std::str::from_utf8(&resp.val)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Status::UnknownCommand => Ok(()),
+            status => Err(format!("unexpected {:?}", status)),
+        }
+    }),
+];
+
+/// Run the scripted checks against the server `connect` opens a connection to;
+/// it is called again after every violation, as the connection may be out of sync.
+/// Fails only if `connect` does.
+pub fn run_endpoint<S, F>(mut connect: F) -> io::Result<Report>
+where
+    S: Read + Write + 'static,
+    F: FnMut() -> io::Result<S>,
+{
+    let mut session = Session {
+        stream: Box::new(connect()?),
+        opaque: 0,
+    };
+    let mut report = Report::default();
+    for (name, check) in ENDPOINT_CHECKS {
+        let outcome = check(&mut session);
+        if outcome.is_err() {
+            session.stream = Box::new(connect()?);
+        }
+        report.record(name, outcome);
+    }
+    Ok(report)
+}
+
+fn status_of<T>(result: crate::Result<T>) -> Result<Option<Status>, String> {
+    match result {
+        Ok(_) => Ok(None),
+        Err(Error::Proto(e)) => Ok(Some(e.status())),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn expect_status<T>(result: crate::Result<T>, status: Status) -> Outcome {
+    match status_of(result)? {
+        Some(s) if s == status => Ok(()),
+        other => Err(format!("expected {:?}, got {:?}", status, other)),
+    }
+}
+
+fn checked<T>(result: crate::Result<T>) -> Result<T, String> {
+    result.map_err(|e| e.to_string())
+}
+
+type ClientCheck<C> = fn(&mut C) -> Outcome;
+
+fn client_checks<C>() -> Vec<(&'static str, ClientCheck<C>)>
+where
+    C: Operation + NoReplyOperation + AuthOperation,
+{
+    vec![
+        ("set then get returns the value and flags", |c| {
+            checked(c.set(KEY, b"v", 7, 0))?;
+            match checked(c.get(KEY))? {
+                (ref val, 7) if val == &b"v"[..] => Ok(()),
+                other => Err(format!("unexpected {:?}", other)),
+            }
+        }),
+        ("getk returns the key", |c| {
+            checked(c.set(KEY, b"v", 0, 0))?;
+            let (key, _, _) = checked(c.getk(KEY))?;
+            if key == KEY {
+                Ok(())
+            } else {
+                Err(format!("unexpected key {:?}", key))
+            }
+        }),
+        ("get of a missing key is KeyNotFound", |c| {
+            let _ = c.delete(KEY);
+            expect_status(c.get(KEY), Status::KeyNotFound)?;
+            let mut buf = Vec::new();
+            match checked(c.get_into(KEY, &mut buf))? {
+                None => Ok(()),
+                Some(meta) => Err(format!("get_into of a missing key returned {:?}", meta)),
+            }
+        }),
+        ("add of an existing key is KeyExists", |c| {
+            checked(c.set(KEY, b"v", 0, 0))?;
+            expect_status(c.add(KEY, b"v", 0, 0), Status::KeyExits)
+        }),
+        ("replace of a missing key is KeyNotFound", |c| {
+            let _ = c.delete(KEY);
+            expect_status(c.replace(KEY, b"v", 0, 0), Status::KeyNotFound)
+        }),
+        ("append and prepend", |c| {
+            checked(c.set(KEY, b"b", 0, 0))?;
+            checked(c.append(KEY, b"c"))?;
+            checked(c.prepend(KEY, b"a"))?;
+            let (val, _) = checked(c.get(KEY))?;
+            if val == b"abc"[..] {
+                Ok(())
+            } else {
+                Err(format!("expected value \"abc\", got {:?}", val))
+            }
+        }),
+        ("delete of a missing key is KeyNotFound", |c| {
+            checked(c.set(KEY, b"v", 0, 0))?;
+            checked(c.delete(KEY))?;
+            expect_status(c.delete(KEY), Status::KeyNotFound)
+        }),
+        ("increment creates with the initial value", |c| {
+            let _ = c.delete(COUNTER);
+            let first = checked(c.increment(COUNTER, 5, 10, 0))?;
+            let second = checked(c.increment(COUNTER, 5, 10, 0))?;
+            let third = checked(c.decrement(COUNTER, 20, 10, 0))?;
+            if (first, second, third) == (10, 15, 0) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "expected 10, 15, 0, got {}, {}, {}",
+                    first, second, third
+                ))
+            }
+        }),
+        ("increment of a non-numeric value", |c| {
+            checked(c.set(KEY, b"v", 0, 0))?;
+            expect_status(
+                c.increment(KEY, 1, 0, 0),
+                Status::IncrOrDecrOnNonNumericValue,
+            )
+        }),
+        ("touch of a missing key is KeyNotFound", |c| {
+            checked(c.set(KEY, b"v", 0, 0))?;
+            checked(c.touch(KEY, 60))?;
+            checked(c.delete(KEY))?;
+            expect_status(c.touch(KEY, 60), Status::KeyNotFound)
+        }),
+        ("quiet set and delete are applied", |c| {
+            checked(c.set_noreply(KEY, b"q", 3, 0))?;
+            match checked(c.get(KEY))? {
+                (ref val, 3) if val == &b"q"[..] => {}
+                other => return Err(format!("unexpected {:?} after a quiet set", other)),
+            }
+            checked(c.delete_noreply(KEY))?;
+            expect_status(c.get(KEY), Status::KeyNotFound)
+        }),
+        ("failed quiet add leaves the next response in sync", |c| {
+            checked(c.set(KEY, b"v", 0, 0))?;
+            checked(c.add_noreply(KEY, b"w", 0, 0))?;
+            let (val, _) = checked(c.get(KEY))?;
+            if val == b"v"[..] {
+                Ok(())
+            } else {
+                Err(format!("expected value \"v\", got {:?}", val))
+            }
+        }),
+        (
+            "SASL mechanisms are listed, or SASL is unsupported",
+            |c| match status_of(c.list_mechanisms())? {
+                None | Some(Status::UnknownCommand) => Ok(()),
+                Some(status) => Err(format!("unexpected {:?}", status)),
+            },
+        ),
+        ("SASL auth with an unknown mechanism fails", |c| {
+            match c.auth_start("CONFORMANCE-UNKNOWN", b"") {
+                Ok(AuthResponse::Failed) => Ok(()),
+                Ok(other) => Err(format!("unexpected {:?}", other)),
+                Err(e) => match status_of::<()>(Err(e))? {
+                    Some(Status::AuthenticationError) | Some(Status::UnknownCommand) => Ok(()),
+                    other => Err(format!("unexpected {:?}", other)),
+                },
+            }
+        }),
+    ]
+}
+
+/// Run the scripted checks through a client implementation, which must surface server errors
+/// as `Error::Proto` with the status of the response
+pub fn run_client<C>(client: &mut C) -> Report
+where
+    C: Operation + NoReplyOperation + AuthOperation,
+{
+    let mut report = Report::default();
+    for (name, check) in client_checks::<C>() {
+        report.record(name, check(client));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::Memory;
+    use crate::{Decoded, Decoder};
+    use std::cell::Cell;
+    use std::io::Seek;
+    use std::rc::Rc;
+
+    /// Server answering every request that isn't quiet with an empty success
+    #[derive(Default)]
+    struct Agreeable {
+        decoder: Decoder,
+        replies: io::Cursor<Vec<u8>>,
+    }
+
+    impl Read for Agreeable {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for Agreeable {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.decoder.feed(buf);
+            while let Decoded::Packet(request) = self.decoder.decode()? {
+                if request.header.opcode.is_quiet() {
+                    continue;
+                }
+                let reply = Packet::builder(request.header.opcode)
+                    .opaque(request.header.opaque)
+                    .response(Status::NoError)
+                    .unwrap();
+                let pos = self.replies.position();
+                self.replies.seek(io::SeekFrom::End(0))?;
+                reply.write_to(&mut self.replies)?;
+                self.replies.set_position(pos);
+            }
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_run_endpoint() {
+        let connects = Rc::new(Cell::new(0));
+        let counter = connects.clone();
+        let report = run_endpoint(move || {
+            counter.set(counter.get() + 1);
+            Ok(Agreeable::default())
+        })
+        .unwrap();
+
+        for name in &[
+            "quiet get miss is silent",
+            "version is text",
+            "SASL mechanisms are text, or SASL is unsupported",
+        ] {
+            assert!(report.passed.contains(name), "{}", name);
+        }
+        let violation = |name| {
+            report
+                .violations
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(
+            violation("get of a missing key is KeyNotFound"),
+            Some("expected KeyNotFound, got NoError")
+        );
+        assert_eq!(
+            violation("set with a stale CAS is KeyExists"),
+            Some("set returned a zero CAS")
+        );
+        assert_eq!(
+            report.passed.len() + report.violations.len(),
+            ENDPOINT_CHECKS.len()
+        );
+        // a new connection after every violation
+        assert_eq!(connects.get(), 1 + report.violations.len());
+        assert!(!report.is_conformant());
+    }
+
+    #[test]
+    fn test_run_client() {
+        let mut client = Memory::default();
+        let report = run_client(&mut client);
+        assert!(report.is_conformant(), "{}", report);
+        assert_eq!(report.passed.len(), client_checks::<Memory>().len());
+
+        // the delete of the third check fails, leaving the key in place
+        let mut client = Memory::default();
+        client.conflicts.push_back(Status::OutOfMemory);
+        let report = run_client(&mut client);
+        assert_eq!(
+            report.violations,
+            vec![(
+                "get of a missing key is KeyNotFound",
+                "expected KeyNotFound, got None".to_string()
+            )]
+        );
+        assert!(report.to_string().ends_with(&format!(
+            "{} of {} checks passed",
+            report.passed.len(),
+            report.passed.len() + 1
+        )));
+    }
+}
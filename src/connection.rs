@@ -0,0 +1,256 @@
+//! Protocol state machine free of any I/O, for driving connections from any event loop
+use crate::code::{Opcode, Status};
+use crate::packet::{Decoded, Decoder, Packet};
+use crate::request::Request;
+use crate::response::Response;
+use bytes::{Bytes, BytesMut};
+use std::collections::VecDeque;
+use std::io;
+
+/// Something that happened on a `Connection`, in wire order
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    /// Response to the request sent with `opaque`;
+    /// Stat gets one per statistic, the last one with an empty key.
+    Response { opaque: u32, response: Response },
+    /// Response to an opcode without a typed `Response`, such as a vendor opcode
+    Packet(Packet),
+    /// A request answered by nothing before the response to a later one: a quiet command
+    /// that succeeded, or missed for GetQ/GetKQ/GATQ/GATKQ.
+    Silent { opaque: u32, opcode: Opcode },
+}
+
+/// Client side of a connection: turns requests into bytes to write and received bytes into
+/// events, matching responses to requests by opaque; the caller does all the I/O.
+///
+/// Quiet requests are only known to have completed once a later request is answered,
+/// so a batch of them is usually followed by a `NoOp`.
+///
+/// # Examples
+/// ```rust
+/// use memcached_proto::{Connection, Event, Request};
+///
+/// let mut conn = Connection::new();
+/// let (opaque, bytes) = conn.send_request(Request::NoOp);
+/// // write `bytes` to the socket, then feed whatever is read back:
+/// # let reply = [0x81, 0x0a, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+/// let events = conn.receive(&reply).unwrap();
+/// assert!(matches!(events[0], Event::Response { opaque: o, .. } if o == opaque));
+/// assert_eq!(conn.in_flight(), 0);
+/// ```
+#[derive(Debug, Default)]
+pub struct Connection {
+    decoder: Decoder,
+    next_opaque: u32,
+    in_flight: VecDeque<(u32, Opcode)>,
+    /// Events completed before a failing packet, returned by the next `receive`
+    pending: Vec<Event>,
+}
+
+impl Connection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encode `request` with the next opaque; returns the opaque and the bytes to write
+    pub fn send_request(&mut self, request: Request) -> (u32, Bytes) {
+        self.next_opaque = self.next_opaque.wrapping_add(1);
+        let opaque = self.next_opaque;
+        let packet = request.into_packet(0, opaque);
+        self.in_flight.push_back((opaque, packet.header.opcode));
+        let mut buf = BytesMut::new();
+        packet.encode_to(&mut buf);
+        (opaque, buf.freeze())
+    }
+
+    /// Feed bytes read from the peer and get the events they complete, possibly none;
+    /// a decoding error leaves the connection unusable (see `Decoder::resync`).
+    ///
+    /// A response that doesn't convert to its `Response` fails the call and is dropped,
+    /// leaving its request in flight until a later response reports it `Silent`; the events of the packets before it are kept
+    /// and returned first by the next call, which may feed no bytes.
+    pub fn receive(&mut self, bytes: &[u8]) -> io::Result<Vec<Event>> {
+        self.decoder.feed(bytes);
+        let mut events = std::mem::take(&mut self.pending);
+        loop {
+            let packet = match self.decoder.decode() {
+                Ok(Decoded::Packet(packet)) => packet,
+                Ok(_) => return Ok(events),
+                Err(e) => {
+                    self.pending = events;
+                    return Err(e);
+                }
+            };
+            if let Err(e) = self.on_packet(packet, &mut events) {
+                self.pending = events;
+                return Err(e);
+            }
+        }
+    }
+
+    /// Number of requests still waiting for a response
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    fn on_packet(&mut self, packet: Packet, events: &mut Vec<Event>) -> io::Result<()> {
+        let opaque = packet.header.opaque;
+        // convert first, so a failure leaves `in_flight` untouched
+        let event =
+            if packet.status() == Status::NoError && !Response::has_typed(packet.header.opcode) {
+                Event::Packet(packet)
+            } else {
+                let response = Response::from_packet(packet)?;
+                Event::Response { opaque, response }
+            };
+        // responses come in request order, whatever was sent before got none
+        if self.in_flight.iter().any(|&(o, _)| o == opaque) {
+            while let Some(&(o, opcode)) = self.in_flight.front() {
+                if o == opaque {
+                    break;
+                }
+                self.in_flight.pop_front();
+                events.push(Event::Silent { opaque: o, opcode });
+            }
+        }
+        let more_stats = matches!(
+            &event,
+            Event::Response { response: Response::Stat { key, .. }, .. } if !key.is_empty()
+        );
+        if !more_stats {
+            self.complete(opaque);
+        }
+        events.push(event);
+        Ok(())
+    }
+
+    fn complete(&mut self, opaque: u32) {
+        if let Some(&(o, _)) = self.in_flight.front() {
+            if o == opaque {
+                self.in_flight.pop_front();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Connection, Event};
+    use crate::{Extras, Opcode, Packet, Request, Response, Status, SyncOps};
+    use bytes::Bytes;
+
+    fn reply(opcode: Opcode, opaque: u32, extras: Extras, val: &'static str) -> Vec<u8> {
+        let packet = Packet::builder(opcode)
+            .opaque(opaque)
+            .extras(extras)
+            .value(val)
+            .response(Status::NoError)
+            .unwrap();
+        let mut written = Vec::new();
+        packet.write_to(&mut written).unwrap();
+        written
+    }
+
+    #[test]
+    fn test_quiet_requests() {
+        let mut conn = Connection::new();
+        let (quiet, _) = conn.send_request(Request::GetQ { key: "a".into() });
+        let (get, _) = conn.send_request(Request::Get { key: "b".into() });
+        let (noop, _) = conn.send_request(Request::NoOp);
+        assert_eq!(conn.in_flight(), 3);
+
+        let mut bytes = reply(Opcode::Get, get, Extras::Get { flags: 1 }, "v");
+        bytes.extend(reply(Opcode::NoOp, noop, Extras::None, ""));
+        let events = conn.receive(&bytes[..20]).unwrap();
+        assert!(events.is_empty());
+        let events = conn.receive(&bytes[20..]).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                Event::Silent {
+                    opaque: quiet,
+                    opcode: Opcode::GetQ
+                },
+                Event::Response {
+                    opaque: get,
+                    response: Response::GetHit {
+                        key: Bytes::new(),
+                        value: "v".into(),
+                        flags: 1,
+                        cas: 0
+                    }
+                },
+                Event::Response {
+                    opaque: noop,
+                    response: Response::Ok
+                },
+            ]
+        );
+        assert_eq!(conn.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_failing_response() {
+        let mut conn = Connection::new();
+        let (quiet, _) = conn.send_request(Request::SetQ {
+            key: "a".into(),
+            value: "v".into(),
+            flags: 0,
+            expiration: 0,
+            cas: 0,
+        });
+        let (incr, _) = conn.send_request(Request::Increment {
+            key: "b".into(),
+            amount: 1,
+            initial: 0,
+            expiration: 0,
+            cas: 0,
+        });
+        let (noop, _) = conn.send_request(Request::NoOp);
+
+        // a counter value must be 8 bytes
+        let mut bytes = reply(Opcode::Increment, incr, Extras::None, "x");
+        bytes.extend(reply(Opcode::NoOp, noop, Extras::None, ""));
+        assert!(conn.receive(&bytes).is_err());
+        assert_eq!(conn.in_flight(), 3);
+        assert_eq!(
+            conn.receive(&[]).unwrap(),
+            vec![
+                Event::Silent {
+                    opaque: quiet,
+                    opcode: Opcode::SetQ
+                },
+                Event::Silent {
+                    opaque: incr,
+                    opcode: Opcode::Increment
+                },
+                Event::Response {
+                    opaque: noop,
+                    response: Response::Ok
+                },
+            ]
+        );
+        assert_eq!(conn.in_flight(), 0);
+
+        // events before the failing response are kept
+        let (noop, _) = conn.send_request(Request::NoOp);
+        let (incr, _) = conn.send_request(Request::Increment {
+            key: "b".into(),
+            amount: 1,
+            initial: 0,
+            expiration: 0,
+            cas: 0,
+        });
+        let mut bytes = reply(Opcode::NoOp, noop, Extras::None, "");
+        bytes.extend(reply(Opcode::Increment, incr, Extras::None, "x"));
+        assert!(conn.receive(&bytes).is_err());
+        assert_eq!(conn.in_flight(), 1);
+        assert_eq!(
+            conn.receive(&[]).unwrap(),
+            vec![Event::Response {
+                opaque: noop,
+                response: Response::Ok
+            }]
+        );
+    }
+}
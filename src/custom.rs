@@ -0,0 +1,131 @@
+//! Registry of extras layouts for opcodes the crate doesn't know about
+use crate::code::{Magic, Opcode};
+use crate::io;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::sync::RwLock;
+
+/// Typed extras of a vendor opcode, produced by a parser registered with `Extras::register`
+pub trait CustomExtras: fmt::Debug + Send + Sync {
+    /// Encoded length
+    fn len(&self) -> usize;
+    /// Encode into `buf`, which is exactly `len()` bytes long
+    fn encode(&self, buf: &mut [u8]);
+    /// To downcast to the concrete type
+    fn as_any(&self) -> &dyn Any;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl dyn CustomExtras {
+    /// Encode into a new buffer
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut buf = vec![0; self.len()];
+        self.encode(&mut buf);
+        buf
+    }
+
+    #[inline]
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+}
+
+/// Two custom extras are equal if they encode to the same bytes
+impl PartialEq for dyn CustomExtras {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.to_vec() == other.to_vec()
+    }
+}
+
+/// Parses the (non-empty) extras of a registered opcode
+pub type ExtrasParser = fn(&[u8]) -> io::Result<Box<dyn CustomExtras>>;
+
+// (is request, opcode, parser), looked up linearly since only a few opcodes are ever registered
+#[cfg(feature = "std")]
+static PARSERS: RwLock<Vec<(bool, Opcode, ExtrasParser)>> = RwLock::new(Vec::new());
+
+#[cfg(feature = "std")]
+pub(crate) fn register(magic: Magic, opcode: Opcode, parser: ExtrasParser) -> io::Result<()> {
+    if !matches!(opcode, Opcode::Other(_)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} has built-in extras", opcode),
+        ));
+    }
+    let mut parsers = PARSERS.write().unwrap_or_else(|e| e.into_inner());
+    let key = (magic.is_request(), opcode);
+    parsers.retain(|(is_request, op, _)| (*is_request, *op) != key);
+    parsers.push((key.0, key.1, parser));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn lookup(magic: Magic, opcode: Opcode) -> Option<ExtrasParser> {
+    let parsers = PARSERS.read().unwrap_or_else(|e| e.into_inner());
+    parsers
+        .iter()
+        .find(|(is_request, op, _)| *is_request == magic.is_request() && *op == opcode)
+        .map(|(_, _, parser)| *parser)
+}
+
+/// Without `std` there is no process-wide registry, extras of unknown opcodes decode as `Unknown`
+#[cfg(not(feature = "std"))]
+pub(crate) fn lookup(_magic: Magic, _opcode: Opcode) -> Option<ExtrasParser> {
+    None
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::Extras;
+
+    #[derive(Debug)]
+    struct Tag(u8);
+
+    impl CustomExtras for Tag {
+        fn len(&self) -> usize {
+            1
+        }
+        fn encode(&self, buf: &mut [u8]) {
+            buf[0] = self.0
+        }
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    fn tag(buf: &[u8]) -> io::Result<Box<dyn CustomExtras>> {
+        Ok(Box::new(Tag(buf[0])))
+    }
+
+    #[test]
+    fn test_register() {
+        let err = Extras::register(Magic::Request, Opcode::Set, tag).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            Extras::parse_for(Magic::Request, Opcode::Set, &[0; 8]).unwrap(),
+            Extras::Store {
+                flags: 0,
+                expiration: 0
+            }
+        );
+
+        Extras::register(Magic::Response, Opcode::Other(0xe1), tag).unwrap();
+        match Extras::parse_for(Magic::Response, Opcode::Other(0xe1), &[9]).unwrap() {
+            Extras::Custom(c) => assert_eq!(c.downcast_ref::<Tag>().unwrap().0, 9),
+            e => panic!("unexpected extras {:?}", e),
+        }
+        // registered for responses only
+        assert!(matches!(
+            Extras::parse_for(Magic::Request, Opcode::Other(0xe1), &[9]).unwrap(),
+            Extras::Unknown(_)
+        ));
+    }
+}
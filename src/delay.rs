@@ -0,0 +1,263 @@
+//! Latency injection into a transport, to rehearse a slow cache with the production client code
+use futures_lite::{AsyncRead, AsyncWrite};
+use std::io::{self, IoSlice, Read, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Delay added by `Delayed` to every response
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delay {
+    Fixed(Duration),
+    /// Sampled uniformly from `min..=max`
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+}
+
+impl Delay {
+    pub fn sample(&self) -> Duration {
+        match *self {
+            Self::Fixed(d) => d,
+            Self::Uniform { min, max } if max > min => {
+                let nanos = (max - min).as_nanos().min(u64::MAX as u128) as u64;
+                min + Duration::from_nanos(fastrand::u64(..=nanos))
+            }
+            Self::Uniform { min, .. } => min,
+        }
+    }
+}
+
+/// Transport delaying the first read after every write by a sampled `Delay`,
+/// so each request (or pipelined batch) waits that much longer for its response;
+/// works with any client over `Read`/`Write` or `AsyncRead`/`AsyncWrite`.
+///
+/// The async side waits on a helper thread instead of a runtime timer, this is meant for
+/// staging environments, not for production traffic.
+///
+/// # Examples
+/// ```rust
+/// use memcached_proto::{Delay, Delayed};
+/// use std::io::{Read, Write};
+/// use std::time::{Duration, Instant};
+///
+/// let delay = Delay::Fixed(Duration::from_millis(5));
+/// let mut io = Delayed::new(std::io::Cursor::new(Vec::new()), delay);
+/// io.write_all(b"request").unwrap();
+/// let start = Instant::now();
+/// io.read(&mut [0u8; 8]).unwrap();
+/// assert!(start.elapsed() >= Duration::from_millis(5));
+/// ```
+#[derive(Debug)]
+pub struct Delayed<T> {
+    io: T,
+    delay: Delay,
+    /// Written since the last read, the next read is delayed
+    pending: bool,
+    deadline: Option<Instant>,
+    /// Waker the helper thread of the current deadline wakes, updated on every poll
+    timer: Option<Arc<Mutex<Waker>>>,
+}
+
+impl<T> Delayed<T> {
+    pub fn new(io: T, delay: Delay) -> Self {
+        Self {
+            io,
+            delay,
+            pending: false,
+            deadline: None,
+            timer: None,
+        }
+    }
+
+    /// Applies from the next write
+    pub fn set_delay(&mut self, delay: Delay) {
+        self.delay = delay;
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    /// Ready once the delay of the pending read is over, waking `cx` from a helper thread;
+    /// one thread is spawned per deadline, later polls only replace the waker it wakes
+    fn poll_delay(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.pending {
+            return Poll::Ready(());
+        }
+        let delay = self.delay;
+        let deadline = *self
+            .deadline
+            .get_or_insert_with(|| Instant::now() + delay.sample());
+        let now = Instant::now();
+        if now < deadline {
+            match &self.timer {
+                Some(waker) => waker.lock().unwrap().clone_from(cx.waker()),
+                None => {
+                    let waker = Arc::new(Mutex::new(cx.waker().clone()));
+                    self.timer = Some(waker.clone());
+                    let remaining = deadline - now;
+                    thread::spawn(move || {
+                        thread::sleep(remaining);
+                        waker.lock().unwrap().wake_by_ref();
+                    });
+                }
+            }
+            return Poll::Pending;
+        }
+        self.pending = false;
+        self.deadline = None;
+        self.timer = None;
+        Poll::Ready(())
+    }
+}
+
+impl<T: Read> Read for Delayed<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending {
+            thread::sleep(self.delay.sample());
+            self.pending = false;
+        }
+        self.io.read(buf)
+    }
+}
+
+impl<T: Write> Write for Delayed<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending = true;
+        self.io.write(buf)
+    }
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.pending = true;
+        self.io.write_vectored(bufs)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Delayed<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        futures_lite::ready!(this.poll_delay(cx));
+        Pin::new(&mut this.io).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Delayed<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.pending = true;
+        Pin::new(&mut this.io).poll_write(cx, buf)
+    }
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.pending = true;
+        Pin::new(&mut this.io).poll_write_vectored(cx, bufs)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::{AsyncReadExt, AsyncWriteExt};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    #[test]
+    fn test_uniform() {
+        let delay = Delay::Uniform {
+            min: Duration::from_millis(1),
+            max: Duration::from_millis(3),
+        };
+        for _ in 0..100 {
+            let d = delay.sample();
+            assert!(d >= Duration::from_millis(1) && d <= Duration::from_millis(3));
+        }
+        let empty = Delay::Uniform {
+            min: Duration::from_millis(2),
+            max: Duration::from_millis(1),
+        };
+        assert_eq!(empty.sample(), Duration::from_millis(2));
+    }
+
+    #[test]
+    fn test_async_delay() {
+        let delay = Duration::from_millis(20);
+        let mut io = Delayed::new(
+            futures_lite::io::Cursor::new(Vec::new()),
+            Delay::Fixed(delay),
+        );
+        futures_lite::future::block_on(async {
+            // nothing written, nothing to wait for
+            let start = Instant::now();
+            io.read(&mut [0u8; 4]).await.unwrap();
+            assert!(start.elapsed() < delay);
+
+            io.write_all(b"request").await.unwrap();
+            let start = Instant::now();
+            io.read(&mut [0u8; 4]).await.unwrap();
+            assert!(start.elapsed() >= delay);
+
+            // only the first read after a write waits
+            let start = Instant::now();
+            io.read(&mut [0u8; 4]).await.unwrap();
+            assert!(start.elapsed() < delay);
+        });
+    }
+
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_one_timer_per_deadline() {
+        let delay = Duration::from_millis(20);
+        let mut io = Delayed::new(io::Cursor::new(Vec::new()), Delay::Fixed(delay));
+        io.write_all(b"request").unwrap();
+
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        for _ in 0..10 {
+            assert_eq!(io.poll_delay(&mut cx), Poll::Pending);
+        }
+        thread::sleep(delay * 2);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(io.poll_delay(&mut cx), Poll::Ready(()));
+        assert!(io.timer.is_none());
+    }
+}
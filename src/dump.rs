@@ -0,0 +1,242 @@
+//! Human readable packet dumps in the style of the protocol documentation
+use crate::packet::Packet;
+use bytes::BytesMut;
+use core::fmt;
+
+// body bytes shown in the diagram, and key/value bytes shown in the breakdown
+const MAX_SHOWN: usize = 64;
+
+/// Display adapter returned by `Packet::dump`
+#[derive(Debug)]
+pub struct PacketDump<'a>(&'a Packet);
+
+impl Packet {
+    /// Multi-line view of the packet for troubleshooting: the bytes laid out 4 per row
+    /// like the diagrams of the protocol documentation, followed by each header field;
+    /// long bodies, keys and values are cut after 64 bytes.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use memcached_proto::{Extras, Opcode, Packet};
+    /// use bytes::Bytes;
+    ///
+    /// let p = Packet::request(Opcode::Get, 0, 0, 0, Extras::None, Bytes::from_static(b"Hello"), Bytes::new());
+    /// println!("{}", p.dump());
+    /// ```
+    pub fn dump(&self) -> PacketDump<'_> {
+        PacketDump(self)
+    }
+}
+
+impl fmt::Display for PacketDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let packet = self.0;
+        let header = &packet.header;
+        let mut bytes = BytesMut::new();
+        packet.encode_to(&mut bytes);
+
+        let border = "       +---------------+---------------+---------------+---------------+";
+        writeln!(
+            f,
+            "     Byte/     0       |       1       |       2       |       3       |"
+        )?;
+        writeln!(
+            f,
+            "        /              |               |               |               |"
+        )?;
+        writeln!(
+            f,
+            "       |0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|"
+        )?;
+        writeln!(f, "{}", border)?;
+        let shown = bytes.len().min(24 + MAX_SHOWN);
+        for (row, chunk) in bytes[..shown].chunks(4).enumerate() {
+            write!(f, "{:>7}|", row * 4)?;
+            for b in chunk {
+                if row >= 6 && b.is_ascii_graphic() {
+                    write!(f, " {:#04x} ('{}')    |", b, *b as char)?;
+                } else {
+                    write!(f, " {:#04x}          |", b)?;
+                }
+            }
+            writeln!(f)?;
+            writeln!(f, "{}", border)?;
+        }
+        if shown < bytes.len() {
+            writeln!(f, "       ... {} more bytes", bytes.len() - shown)?;
+        }
+        writeln!(
+            f,
+            "    Total {} bytes (24 byte header, {} byte body)",
+            bytes.len(),
+            header.body_len
+        )?;
+        writeln!(f)?;
+
+        writeln!(f, "Field        (offset) (value)")?;
+        writeln!(
+            f,
+            "Magic        (0)    : {:#04x} ({:?})",
+            header.magic as u8, header.magic
+        )?;
+        writeln!(
+            f,
+            "Opcode       (1)    : {:#04x} ({:?})",
+            u8::from(header.opcode),
+            header.opcode
+        )?;
+        if header.magic.is_alt() {
+            writeln!(
+                f,
+                "Framing extra(2)    : {:#04x}",
+                header.framing_extras_len
+            )?;
+            writeln!(f, "Key length   (3)    : {:#04x}", header.key_len)?;
+        } else {
+            writeln!(f, "Key length   (2,3)  : {:#06x}", header.key_len)?;
+        }
+        writeln!(f, "Extra length (4)    : {:#04x}", header.extras_len)?;
+        writeln!(f, "Data type    (5)    : {:#04x}", header.data_type.bits())?;
+        if header.magic.is_request() {
+            writeln!(
+                f,
+                "VBucket      (6,7)  : {:#06x}",
+                header.vbucket_id_or_status
+            )?;
+        } else {
+            writeln!(
+                f,
+                "Status       (6,7)  : {:#06x} ({:?})",
+                header.vbucket_id_or_status,
+                packet.status()
+            )?;
+        }
+        writeln!(f, "Total body   (8-11) : {:#010x}", header.body_len)?;
+        writeln!(f, "Opaque       (12-15): {:#010x}", header.opaque)?;
+        writeln!(f, "CAS          (16-23): {:#018x}", header.cas)?;
+        for info in &packet.framing_extras {
+            writeln!(f, "Framing extra       : {:?}", info)?;
+        }
+        if !packet.extras.is_empty() {
+            writeln!(f, "Extras              : {}", packet.extras)?;
+        }
+        if !packet.key.is_empty() {
+            writeln!(f, "Key                 : {}", Escaped(&packet.key))?;
+        }
+        if !packet.val.is_empty() {
+            writeln!(f, "Value               : {}", Escaped(&packet.val))?;
+        }
+        Ok(())
+    }
+}
+
+struct Escaped<'a>(&'a [u8]);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shown = self.0.len().min(MAX_SHOWN);
+        write!(f, "\"{}\"", self.0[..shown].escape_ascii())?;
+        if shown < self.0.len() {
+            write!(f, " ... {} more bytes", self.0.len() - shown)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::{Extras, Opcode, Packet, Status};
+    use bytes::Bytes;
+
+    #[test]
+    fn test_get_request() {
+        // the Get example of the protocol documentation
+        let p = Packet::request(
+            Opcode::Get,
+            0,
+            0,
+            0,
+            Extras::None,
+            Bytes::from_static(b"Hello"),
+            Bytes::new(),
+        );
+        let expected = "     Byte/     0       |       1       |       2       |       3       |
+        /              |               |               |               |
+       |0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|
+       +---------------+---------------+---------------+---------------+
+      0| 0x80          | 0x00          | 0x00          | 0x05          |
+       +---------------+---------------+---------------+---------------+
+      4| 0x00          | 0x00          | 0x00          | 0x00          |
+       +---------------+---------------+---------------+---------------+
+      8| 0x00          | 0x00          | 0x00          | 0x05          |
+       +---------------+---------------+---------------+---------------+
+     12| 0x00          | 0x00          | 0x00          | 0x00          |
+       +---------------+---------------+---------------+---------------+
+     16| 0x00          | 0x00          | 0x00          | 0x00          |
+       +---------------+---------------+---------------+---------------+
+     20| 0x00          | 0x00          | 0x00          | 0x00          |
+       +---------------+---------------+---------------+---------------+
+     24| 0x48 ('H')    | 0x65 ('e')    | 0x6c ('l')    | 0x6c ('l')    |
+       +---------------+---------------+---------------+---------------+
+     28| 0x6f ('o')    |
+       +---------------+---------------+---------------+---------------+
+    Total 29 bytes (24 byte header, 5 byte body)
+
+Field        (offset) (value)
+Magic        (0)    : 0x80 (Request)
+Opcode       (1)    : 0x00 (Get)
+Key length   (2,3)  : 0x0005
+Extra length (4)    : 0x00
+Data type    (5)    : 0x00
+VBucket      (6,7)  : 0x0000
+Total body   (8-11) : 0x00000005
+Opaque       (12-15): 0x00000000
+CAS          (16-23): 0x0000000000000000
+Key                 : \"Hello\"
+";
+        assert_eq!(p.dump().to_string(), expected);
+    }
+
+    #[test]
+    fn test_response_fields() {
+        let p = Packet::response(
+            Opcode::Get,
+            Status::KeyNotFound,
+            0x2a,
+            0,
+            Extras::None,
+            Bytes::new(),
+            Bytes::from_static(b"Not found\n"),
+        );
+        let dump = p.dump().to_string();
+        assert!(dump.contains("Status       (6,7)  : 0x0001 (KeyNotFound)\n"));
+        assert!(dump.contains("Opaque       (12-15): 0x0000002a\n"));
+        assert!(dump.contains("Value               : \"Not found\\n\"\n"));
+        assert!(!dump.contains("VBucket"));
+        assert!(!dump.contains("Key   "));
+    }
+
+    #[test]
+    fn test_long_body_cut() {
+        let p = Packet::request(
+            Opcode::Set,
+            0,
+            0,
+            0,
+            Extras::Store {
+                flags: 0,
+                expiration: 0,
+            },
+            Bytes::from_static(b"k"),
+            Bytes::from(vec![b'v'; 100]),
+        );
+        let dump = p.dump().to_string();
+        // 24 + 8 + 1 + 100 bytes, the diagram stops after 64 bytes of body
+        assert!(dump.contains("       ... 45 more bytes\n"));
+        assert!(dump.contains("Total 133 bytes (24 byte header, 109 byte body)\n"));
+        assert!(dump.contains(&format!(
+            "Value               : \"{}\" ... 36 more bytes\n",
+            "v".repeat(64)
+        )));
+    }
+}
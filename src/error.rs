@@ -1,7 +1,10 @@
-use crate::Status;
-use std::fmt;
+use crate::{Opcode, Status};
+use bytes::Bytes;
+use core::fmt;
+#[cfg(feature = "std")]
 use thiserror::Error;
 
+#[cfg(feature = "std")]
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("{0}")]
@@ -10,8 +13,10 @@ pub enum Error {
     Proto(#[from] ProtoError),
 }
 
+#[cfg(feature = "std")]
 pub type Result<T> = std::result::Result<T, Error>;
 
+#[cfg(feature = "std")]
 #[derive(Debug, Error)]
 pub struct ProtoError {
     status: Status,
@@ -19,6 +24,7 @@ pub struct ProtoError {
     detail: Option<String>,
 }
 
+#[cfg(feature = "std")]
 impl ProtoError {
     pub(crate) fn from_status(status: Status, detail: Option<String>) -> Self {
         Self {
@@ -39,6 +45,7 @@ impl ProtoError {
     }
 }
 
+#[cfg(feature = "std")]
 impl fmt::Display for ProtoError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.desc)?;
@@ -48,3 +55,147 @@ impl fmt::Display for ProtoError {
         }
     }
 }
+
+/// The header of an incoming packet announced a body longer than the decoding limit;
+/// returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Debug)]
+pub struct BodyTooLarge {
+    pub body_len: u32,
+    pub max_body_len: u32,
+}
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "body length {} exceeds the limit of {} bytes",
+            self.body_len, self.max_body_len
+        )
+    }
+}
+
+/// 24 bytes that don't form a valid packet header, kept by `Decoder` for inspection;
+/// returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Debug)]
+pub struct CorruptHeader {
+    pub header: Bytes,
+}
+
+impl fmt::Display for CorruptHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "corrupt packet header {:02x?}", self.header)
+    }
+}
+
+/// Invariant broken by a packet, returned by `Packet::validate`
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum InvalidPacket {
+    BodyLength {
+        body_len: u32,
+        actual: usize,
+    },
+    KeyTooLong(usize),
+    UnknownDataType(u8),
+    UnknownStatus(u16),
+    ErrorWithExtras(Status),
+    ExtrasLength {
+        opcode: Opcode,
+        expected: usize,
+        actual: usize,
+    },
+    NonZeroCas(Opcode),
+    FramingExtrasTooLong(usize),
+    AltKeyTooLong(usize),
+}
+
+impl fmt::Display for InvalidPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BodyLength { body_len, actual } => write!(
+                f,
+                "body length {} doesn't match the {} bytes of framing extras, extras, key and value",
+                body_len, actual
+            ),
+            Self::KeyTooLong(len) => write!(f, "key of {} bytes is longer than 250 bytes", len),
+            Self::UnknownDataType(bits) => write!(f, "unknown data type bits {:#04x}", bits),
+            Self::UnknownStatus(status) => write!(f, "unknown status {:#06x}", status),
+            Self::ErrorWithExtras(status) => write!(
+                f,
+                "{:?} response with extras, error responses only carry a message",
+                status
+            ),
+            Self::ExtrasLength {
+                opcode,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{:?} request with {} bytes of extras instead of {}",
+                opcode, actual, expected
+            ),
+            Self::NonZeroCas(opcode) => write!(f, "{:?} request with a non-zero CAS", opcode),
+            Self::FramingExtrasTooLong(len) => write!(
+                f,
+                "{} bytes of framing extras don't fit in the one-byte length",
+                len
+            ),
+            Self::AltKeyTooLong(len) => write!(
+                f,
+                "key of {} bytes is longer than the 255 bytes of the alternative magics",
+                len
+            ),
+        }
+    }
+}
+
+/// Error of `PacketHeader::try_parse`
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    Incomplete {
+        needed: usize,
+    },
+    /// Unknown magic
+    Invalid,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Incomplete { needed } => write!(f, "{} more bytes needed", needed),
+            Self::Invalid => f.write_str("invalid packet header"),
+        }
+    }
+}
+
+// hand-written rather than derived with thiserror, which needs `std`
+#[cfg(feature = "std")]
+impl std::error::Error for BodyTooLarge {}
+#[cfg(feature = "std")]
+impl std::error::Error for CorruptHeader {}
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidPacket {}
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+/// Expiration the server would misread, returned by the conversion of `Expiration` to its wire value
+#[cfg(feature = "std")]
+#[derive(Debug, Error, PartialEq)]
+pub enum InvalidExpiration {
+    #[error("relative expiration of {0} seconds is over 30 days, the server would read it as a unix time")]
+    TooLong(u32),
+    #[error(
+        "expiration time within 30 days of the unix epoch, the server would read it as relative"
+    )]
+    TooEarly,
+    #[error("expiration time past the largest 32-bit unix time")]
+    TooLate,
+}
+
+/// As an `Io` error of kind `InvalidInput`
+#[cfg(feature = "std")]
+impl From<InvalidExpiration> for Error {
+    fn from(e: InvalidExpiration) -> Self {
+        Self::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    }
+}
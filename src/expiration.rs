@@ -0,0 +1,103 @@
+//! Expirations as the server reads them: up to 30 days relative, a unix time beyond that
+use crate::error::InvalidExpiration;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Longest relative expiration, larger values are unix times
+pub const MAX_RELATIVE_SECS: u32 = 60 * 60 * 24 * 30;
+
+/// Expiration of an item, converted with `u32::try_from` to the value the operations take
+///
+/// # Examples
+/// ```rust
+/// use memcached_proto::Expiration;
+/// use std::convert::TryFrom;
+/// use std::time::{Duration, SystemTime};
+///
+/// assert_eq!(u32::try_from(Expiration::Seconds(60)), Ok(60));
+/// assert!(u32::try_from(Expiration::Seconds(60 * 60 * 24 * 31)).is_err());
+///
+/// let in_a_year = SystemTime::now() + Duration::from_secs(60 * 60 * 24 * 365);
+/// assert!(u32::try_from(Expiration::At(in_a_year)).unwrap() > 60 * 60 * 24 * 30);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Expiration {
+    #[default]
+    Never,
+    /// Relative, at most `MAX_RELATIVE_SECS`; 0 is the same as `Never`
+    Seconds(u32),
+    /// Absolute, sent as a unix time
+    At(SystemTime),
+}
+
+impl Expiration {
+    /// `Seconds` if `duration` fits in 30 days, `At` now + `duration` otherwise
+    pub fn after(duration: Duration) -> Self {
+        if duration.as_secs() <= MAX_RELATIVE_SECS as u64 {
+            Self::Seconds(duration.as_secs() as u32)
+        } else {
+            Self::At(SystemTime::now() + duration)
+        }
+    }
+}
+
+impl TryFrom<Expiration> for u32 {
+    type Error = InvalidExpiration;
+
+    fn try_from(expiration: Expiration) -> Result<Self, InvalidExpiration> {
+        match expiration {
+            Expiration::Never => Ok(0),
+            Expiration::Seconds(secs) if secs > MAX_RELATIVE_SECS => {
+                Err(InvalidExpiration::TooLong(secs))
+            }
+            Expiration::Seconds(secs) => Ok(secs),
+            Expiration::At(time) => {
+                let secs = time
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|_| InvalidExpiration::TooEarly)?
+                    .as_secs();
+                if secs <= MAX_RELATIVE_SECS as u64 {
+                    Err(InvalidExpiration::TooEarly)
+                } else {
+                    u32::try_from(secs).map_err(|_| InvalidExpiration::TooLate)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiration() {
+        assert_eq!(u32::try_from(Expiration::Never), Ok(0));
+        assert_eq!(
+            u32::try_from(Expiration::Seconds(MAX_RELATIVE_SECS)),
+            Ok(MAX_RELATIVE_SECS)
+        );
+        assert_eq!(
+            u32::try_from(Expiration::Seconds(MAX_RELATIVE_SECS + 1)),
+            Err(InvalidExpiration::TooLong(MAX_RELATIVE_SECS + 1))
+        );
+        let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(u32::try_from(Expiration::At(at)), Ok(1_700_000_000));
+        assert_eq!(
+            u32::try_from(Expiration::At(UNIX_EPOCH + Duration::from_secs(60))),
+            Err(InvalidExpiration::TooEarly)
+        );
+        assert_eq!(
+            u32::try_from(Expiration::At(UNIX_EPOCH + Duration::from_secs(1 << 32))),
+            Err(InvalidExpiration::TooLate)
+        );
+        assert_eq!(
+            Expiration::after(Duration::from_secs(60)),
+            Expiration::Seconds(60)
+        );
+        assert!(matches!(
+            Expiration::after(Duration::from_secs(MAX_RELATIVE_SECS as u64 + 1)),
+            Expiration::At(_)
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+//! Framing extras carried by the alternative magics (`0x08`/`0x18`)
+use crate::code::Magic;
+use crate::io;
+use alloc::vec::Vec;
+use bytes::{Buf, BufMut, Bytes};
+#[cfg(feature = "std")]
+use futures_lite::{AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "std")]
+use std::io::Write;
+
+// Each frame info starts with one byte: the upper nibble is the id and the
+// lower nibble is the length of the data. A nibble of 0xf means the real
+// value is 15 plus the following byte (id escape first, then length escape).
+const ESCAPE: u8 = 0x0f;
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameInfo {
+    /// Request id 0: the server must not reorder this command with others
+    Barrier,
+    /// Request id 1: durability requirement, `timeout` in milliseconds
+    Durability { level: u8, timeout: Option<u16> },
+    /// Request id 2: DCP stream id
+    DcpStreamId(u16),
+    /// Request id 3: OpenTracing context
+    OpenTracingContext(#[cfg_attr(feature = "serde", serde(with = "crate::serde_base64"))] Bytes),
+    /// Request id 4: execute the command as another user
+    Impersonate(#[cfg_attr(feature = "serde", serde(with = "crate::serde_base64"))] Bytes),
+    /// Request id 5: keep the current TTL of the document
+    PreserveTtl,
+    /// Response id 0: encoded time the server spent on the command
+    ServerDuration(u16),
+    /// Unknown frame info
+    Unknown {
+        id: u16,
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_base64"))]
+        data: Bytes,
+    },
+}
+
+impl FrameInfo {
+    fn id(&self) -> u16 {
+        match self {
+            Self::Barrier | Self::ServerDuration(_) => 0,
+            Self::Durability { .. } => 1,
+            Self::DcpStreamId(_) => 2,
+            Self::OpenTracingContext(_) => 3,
+            Self::Impersonate(_) => 4,
+            Self::PreserveTtl => 5,
+            Self::Unknown { id, .. } => *id,
+        }
+    }
+
+    fn data_len(&self) -> usize {
+        match self {
+            Self::Barrier | Self::PreserveTtl => 0,
+            Self::Durability { timeout, .. } => 1 + timeout.map_or(0, |_| 2),
+            Self::DcpStreamId(_) | Self::ServerDuration(_) => 2,
+            Self::OpenTracingContext(b) | Self::Impersonate(b) => b.len(),
+            Self::Unknown { data, .. } => data.len(),
+        }
+    }
+
+    /// Encoded length, including the id/length byte(s)
+    pub fn encoded_len(&self) -> usize {
+        let escapes =
+            (self.id() >= ESCAPE as u16) as usize + (self.data_len() >= ESCAPE as usize) as usize;
+        1 + escapes + self.data_len()
+    }
+
+    /// Encode into `buf`;
+    /// # Panics
+    /// if the id or the data length is above `15 + 255`
+    pub fn put<B: BufMut>(&self, buf: &mut B) {
+        let id = self.id();
+        let len = self.data_len();
+        assert!(
+            id < ESCAPE as u16 + 256 && len < ESCAPE as usize + 256,
+            "frame info too large"
+        );
+        let id_nibble = id.min(ESCAPE as u16) as u8;
+        let len_nibble = len.min(ESCAPE as usize) as u8;
+        buf.put_u8(id_nibble << 4 | len_nibble);
+        if id_nibble == ESCAPE {
+            buf.put_u8((id - ESCAPE as u16) as u8);
+        }
+        if len_nibble == ESCAPE {
+            buf.put_u8((len - ESCAPE as usize) as u8);
+        }
+        match self {
+            Self::Barrier | Self::PreserveTtl => {}
+            Self::Durability { level, timeout } => {
+                buf.put_u8(*level);
+                if let Some(timeout) = timeout {
+                    buf.put_u16(*timeout);
+                }
+            }
+            Self::DcpStreamId(v) | Self::ServerDuration(v) => buf.put_u16(*v),
+            Self::OpenTracingContext(b) | Self::Impersonate(b) => buf.put_slice(b),
+            Self::Unknown { data, .. } => buf.put_slice(data),
+        }
+    }
+
+    /// Total encoded length of `infos`
+    pub fn total_len(infos: &[FrameInfo]) -> usize {
+        infos.iter().map(Self::encoded_len).sum()
+    }
+
+    /// Write asynchronously without flush;
+    #[cfg(feature = "std")]
+    pub async fn write_all<W: AsyncWrite + Unpin>(
+        infos: &[FrameInfo],
+        w: &mut W,
+    ) -> io::Result<()> {
+        if infos.is_empty() {
+            return Ok(());
+        }
+        let mut buf = Vec::with_capacity(Self::total_len(infos));
+        for info in infos {
+            info.put(&mut buf);
+        }
+        w.write_all(&buf).await
+    }
+
+    /// Write synchronously without flush;
+    #[cfg(feature = "std")]
+    pub fn write_all_sync<W: Write>(infos: &[FrameInfo], w: &mut W) -> io::Result<()> {
+        if infos.is_empty() {
+            return Ok(());
+        }
+        let mut buf = Vec::with_capacity(Self::total_len(infos));
+        for info in infos {
+            info.put(&mut buf);
+        }
+        w.write_all(&buf)
+    }
+
+    /// Parse the whole framing extras section; ids are interpreted based on the direction of `magic`
+    pub fn parse_all(magic: Magic, mut buf: &[u8]) -> io::Result<Vec<Self>> {
+        let mut infos = Vec::new();
+        while buf.has_remaining() {
+            let first = buf.get_u8();
+            let mut id = (first >> 4) as u16;
+            let mut len = (first & ESCAPE) as usize;
+            if id == ESCAPE as u16 {
+                if !buf.has_remaining() {
+                    return Err(io::ErrorKind::InvalidData.into());
+                }
+                id += buf.get_u8() as u16;
+            }
+            if len == ESCAPE as usize {
+                if !buf.has_remaining() {
+                    return Err(io::ErrorKind::InvalidData.into());
+                }
+                len += buf.get_u8() as usize;
+            }
+            if buf.remaining() < len {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+            let mut data = &buf[..len];
+            buf.advance(len);
+            let info = match (magic.is_request(), id, len) {
+                (true, 0, 0) => Self::Barrier,
+                (true, 1, 1) => Self::Durability {
+                    level: data.get_u8(),
+                    timeout: None,
+                },
+                (true, 1, 3) => Self::Durability {
+                    level: data.get_u8(),
+                    timeout: Some(data.get_u16()),
+                },
+                (true, 2, 2) => Self::DcpStreamId(data.get_u16()),
+                (true, 3, _) => Self::OpenTracingContext(Bytes::copy_from_slice(data)),
+                (true, 4, _) => Self::Impersonate(Bytes::copy_from_slice(data)),
+                (true, 5, 0) => Self::PreserveTtl,
+                (false, 0, 2) => Self::ServerDuration(data.get_u16()),
+                _ => Self::Unknown {
+                    id,
+                    data: Bytes::copy_from_slice(data),
+                },
+            };
+            infos.push(info);
+        }
+        Ok(infos)
+    }
+}
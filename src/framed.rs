@@ -0,0 +1,173 @@
+//! Packet `Stream` over any `AsyncRead`/`AsyncWrite` transport, without the high-level client
+use crate::packet::{Decoded, Decoder, Packet, PacketRef};
+use futures_lite::{AsyncRead, AsyncWrite, AsyncWriteExt, Stream};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+const READ_CHUNK: usize = 8 * 1024;
+
+/// A transport framed into packets: a `Stream` of the packets read from it,
+/// and `feed`/`flush`/`send` to write packets to it.
+///
+/// A decoding error leaves the stream unusable, as with `Decoder`;
+/// a peer closing the transport in the middle of a packet ends the stream with `UnexpectedEof`.
+///
+/// # Examples
+/// ```rust
+/// use memcached_proto::{Extras, Framed, Opcode, Packet};
+/// use bytes::Bytes;
+/// use futures_lite::{future, StreamExt};
+///
+/// let p = Packet::request(Opcode::NoOp, 0, 1, 0, Extras::None, Bytes::new(), Bytes::new());
+/// future::block_on(async {
+///     let mut out = Framed::new(Vec::new());
+///     out.send(p.as_ref()).await.unwrap();
+///
+///     let bytes = out.into_inner();
+///     let mut input = Framed::new(&bytes[..]);
+///     assert_eq!(input.next().await.unwrap().unwrap(), p);
+///     assert!(input.next().await.is_none());
+/// });
+/// ```
+#[derive(Debug)]
+pub struct Framed<T> {
+    io: T,
+    decoder: Decoder,
+    write_buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<T> Framed<T> {
+    /// Decoding limited to `Packet::default_max_body_len`
+    pub fn new(io: T) -> Self {
+        Self::with_decoder(io, Decoder::new())
+    }
+
+    /// Decoding rejects bodies longer than `max_body_len` with a `BodyTooLarge` error
+    pub fn with_max_body_len(io: T, max_body_len: u32) -> Self {
+        Self::with_decoder(io, Decoder::with_max_body_len(max_body_len))
+    }
+
+    fn with_decoder(io: T, decoder: Decoder) -> Self {
+        Self {
+            io,
+            decoder,
+            write_buf: Vec::new(),
+            eof: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    /// Reading or writing through this may corrupt the framing
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    /// The transport; bytes read but not decoded yet and packets fed but not flushed are lost
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Framed<T> {
+    /// Buffer `packet` without writing it
+    pub fn feed(&mut self, packet: PacketRef<'_>) {
+        packet.encode_to(&mut self.write_buf);
+    }
+
+    /// Write every buffered packet and flush the transport
+    pub async fn flush(&mut self) -> io::Result<()> {
+        if !self.write_buf.is_empty() {
+            self.io.write_all(&self.write_buf).await?;
+            self.write_buf.clear();
+        }
+        self.io.flush().await
+    }
+
+    /// `feed` then `flush`
+    pub async fn send(&mut self, packet: PacketRef<'_>) -> io::Result<()> {
+        self.feed(packet);
+        self.flush().await
+    }
+}
+
+impl<T: AsyncRead + Unpin> Stream for Framed<T> {
+    type Item = io::Result<Packet>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut chunk = [0u8; READ_CHUNK];
+        loop {
+            match this.decoder.decode() {
+                Ok(Decoded::Packet(packet)) => return Poll::Ready(Some(Ok(packet))),
+                Ok(Decoded::Incomplete(_)) => {}
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+            if this.eof {
+                if this.decoder.buffered() == 0 {
+                    return Poll::Ready(None);
+                }
+                // drop the partial packet so the stream ends after this error
+                this.decoder = Decoder::new();
+                return Poll::Ready(Some(Err(io::ErrorKind::UnexpectedEof.into())));
+            }
+            match Pin::new(&mut this.io).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(0)) => this.eof = true,
+                Poll::Ready(Ok(n)) => this.decoder.feed(&chunk[..n]),
+                Poll::Ready(Err(e)) if e.kind() == io::ErrorKind::Interrupted => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Extras, Opcode};
+    use bytes::Bytes;
+    use futures_lite::{future, StreamExt};
+
+    #[test]
+    fn test_framed_stream() {
+        let packets: Vec<Packet> = (1..=3)
+            .map(|opaque| {
+                Packet::request(
+                    Opcode::Get,
+                    0,
+                    opaque,
+                    0,
+                    Extras::None,
+                    Bytes::from_static(b"key"),
+                    Bytes::new(),
+                )
+            })
+            .collect();
+        future::block_on(async {
+            let mut out = Framed::new(Vec::new());
+            for p in &packets {
+                out.feed(p.as_ref());
+            }
+            assert!(out.get_ref().is_empty());
+            out.flush().await.unwrap();
+            let bytes = out.into_inner();
+
+            let input = Framed::new(&bytes[..]);
+            let read: Vec<Packet> = input.map(Result::unwrap).collect().await;
+            assert_eq!(read, packets);
+
+            // truncated in the middle of the last packet
+            let mut input = Framed::new(&bytes[..bytes.len() - 1]);
+            assert!(input.next().await.unwrap().is_ok());
+            assert!(input.next().await.unwrap().is_ok());
+            let err = input.next().await.unwrap().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+            assert!(input.next().await.is_none());
+        });
+    }
+}
@@ -0,0 +1,194 @@
+//! `arbitrary::Arbitrary` for the protocol types, producing structurally valid frames:
+//! lengths are consistent and the extras follow the layout of the opcode,
+//! so decoding an encoded arbitrary packet gives it back, and it passes `Packet::validate`.
+use crate::code::{DataType, Magic, Opcode, Status};
+use crate::packet::{Extras, Packet, PacketHeader};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use bytes::Bytes;
+
+// Extras length the layout of `opcode` expects in the direction of `magic`,
+// `None` for opcodes whose extras decode as `Extras::Unknown`
+fn layout_len(magic: Magic, opcode: Opcode) -> Option<usize> {
+    if magic.is_response() {
+        return match opcode {
+            Opcode::Get
+            | Opcode::GetQ
+            | Opcode::GetK
+            | Opcode::GetKQ
+            | Opcode::GAT
+            | Opcode::GATQ
+            | Opcode::GATK
+            | Opcode::GATKQ => Some(4),
+            _ => None,
+        };
+    }
+    match opcode {
+        Opcode::Set
+        | Opcode::SetQ
+        | Opcode::Add
+        | Opcode::AddQ
+        | Opcode::Replace
+        | Opcode::ReplaceQ => Some(8),
+        Opcode::Increment | Opcode::IncrementQ | Opcode::Decrement | Opcode::DecrementQ => Some(20),
+        Opcode::Get
+        | Opcode::GetQ
+        | Opcode::GetK
+        | Opcode::GetKQ
+        | Opcode::Delete
+        | Opcode::DeleteQ
+        | Opcode::Append
+        | Opcode::AppendQ
+        | Opcode::Prepend
+        | Opcode::PrependQ
+        | Opcode::Quit
+        | Opcode::QuitQ
+        | Opcode::NoOp
+        | Opcode::Version
+        | Opcode::Stat => Some(0),
+        Opcode::Verbosity
+        | Opcode::Touch
+        | Opcode::GAT
+        | Opcode::GATQ
+        | Opcode::GATK
+        | Opcode::GATKQ
+        | Opcode::Flush
+        | Opcode::FlushQ
+        | Opcode::SetVBucket
+        | Opcode::TapConnect => Some(4),
+        Opcode::TapMutation => Some(16),
+        Opcode::TapDelete
+        | Opcode::TapFlush
+        | Opcode::TapOpaque
+        | Opcode::TapVBucketSet
+        | Opcode::TapCheckPointStart
+        | Opcode::TabCheckPointEnd => Some(8),
+        opcode if opcode.is_range() => Some(8),
+        _ => None,
+    }
+}
+
+const STATUSES: [Status; 18] = [
+    Status::NoError,
+    Status::KeyNotFound,
+    Status::KeyExits,
+    Status::ValueTooLarge,
+    Status::InvalidArguments,
+    Status::ItemNotStored,
+    Status::IncrOrDecrOnNonNumericValue,
+    Status::VbucketBelongsToAnotherServer,
+    Status::AuthenticationError,
+    Status::AuthenticationContinue,
+    Status::UnknownCommand,
+    Status::OutOfMemory,
+    Status::NotSupported,
+    Status::InternalError,
+    Status::Busy,
+    Status::TemporaryFailure,
+    Status::AuthenticationRequired,
+    Status::AuthenticationFurtherStepRequired,
+];
+
+fn magic(u: &mut Unstructured<'_>) -> Result<Magic> {
+    Ok(if u.arbitrary()? {
+        Magic::Request
+    } else {
+        Magic::Response
+    })
+}
+
+fn extras(u: &mut Unstructured<'_>, magic: Magic, opcode: Opcode) -> Result<Extras> {
+    let len = match layout_len(magic, opcode) {
+        Some(len) => len,
+        None => u.int_in_range(0..=32usize)?,
+    };
+    Extras::parse_for(magic, opcode, u.bytes(len)?).map_err(|_| arbitrary::Error::IncorrectFormat)
+}
+
+fn bytes(u: &mut Unstructured<'_>, len: usize) -> Result<Bytes> {
+    Ok(Bytes::copy_from_slice(u.bytes(len)?))
+}
+
+impl<'a> Arbitrary<'a> for Extras {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let magic = magic(u)?;
+        let opcode = Opcode::from(u8::arbitrary(u)?);
+        extras(u, magic, opcode)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Packet {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let magic = magic(u)?;
+        let opcode = Opcode::from(u8::arbitrary(u)?);
+        // a known status for responses
+        let vbucket_id_or_status = if magic.is_response() {
+            u16::from(*u.choose(&STATUSES)?)
+        } else {
+            u.arbitrary()?
+        };
+        // error responses only carry a message
+        let is_error = !matches!(
+            Status::from(vbucket_id_or_status),
+            Status::NoError | Status::AuthenticationContinue
+        );
+        let extras = if magic.is_response() && is_error {
+            Extras::None
+        } else {
+            extras(u, magic, opcode)?
+        };
+        let cas = if magic.is_request() && opcode.forbids_cas() {
+            0
+        } else {
+            u.arbitrary()?
+        };
+        let key_len = u.int_in_range(0..=250usize)?;
+        let key = bytes(u, key_len)?;
+        let val_len = u.arbitrary_len::<u8>()?;
+        let val = bytes(u, val_len)?;
+        let header = PacketHeader {
+            magic,
+            opcode,
+            framing_extras_len: 0,
+            key_len: key.len() as u16,
+            extras_len: extras.len() as u8,
+            data_type: DataType::from_bits(u.int_in_range(0..=7u8)?),
+            vbucket_id_or_status,
+            body_len: (extras.len() + key.len() + val.len()) as u32,
+            opaque: u.arbitrary()?,
+            cas,
+        };
+        Ok(Packet::new(header, extras, key, val))
+    }
+}
+
+impl<'a> Arbitrary<'a> for PacketHeader {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Packet::arbitrary(u)?.header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    #[test]
+    fn test_valid_round_trip() {
+        let rng = fastrand::Rng::with_seed(7);
+        let mut checked = 0;
+        for _ in 0..1000 {
+            let data: Vec<u8> = (0..rng.usize(..512)).map(|_| rng.u8(..)).collect();
+            let packet = match Packet::arbitrary(&mut Unstructured::new(&data)) {
+                Ok(packet) => packet,
+                Err(_) => continue,
+            };
+            assert_eq!(packet.validate(), Ok(()), "{}", packet);
+            let mut buf = BytesMut::new();
+            packet.encode_to(&mut buf);
+            let decoded = Packet::decode_from(&mut buf.freeze()).unwrap();
+            assert_eq!(decoded, packet);
+            checked += 1;
+        }
+        assert!(checked > 500, "{} packets checked", checked);
+    }
+}
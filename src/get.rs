@@ -0,0 +1,131 @@
+//! Fast path for the responses of the get commands, which dominate real traffic
+use crate::code::Status;
+use crate::error::ProtoError;
+use crate::packet::{AsyncOps, Packet, PacketHeader, SyncOps};
+use crate::Result;
+use bytes::{Buf, Bytes, BytesMut};
+use futures_lite::{AsyncRead, AsyncReadExt};
+use std::io::{self, Read};
+
+/// Successful response of the get and get-and-touch commands;
+/// `key` is empty unless the request was GetK/GetKQ/GATK/GATKQ.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetValue {
+    pub key: Bytes,
+    pub value: Bytes,
+    pub flags: u32,
+    pub cas: u64,
+}
+
+impl GetValue {
+    /// Decode the body of a get response straight into its parts, skipping `Extras`;
+    /// a miss is `None` and any other error status is returned as a `ProtoError`.
+    pub fn parse(header: &PacketHeader, mut body: BytesMut) -> Result<Option<Self>> {
+        let key_len = header.key_len as usize;
+        let extras_len = header.extras_len as usize;
+        let fixed_len = header.framing_extras_len as usize + extras_len + key_len;
+        if !header.magic.is_response()
+            || body.len() != header.body_len as usize
+            || fixed_len > body.len()
+        {
+            return Err(io::Error::from(io::ErrorKind::InvalidData).into());
+        }
+        body.advance(header.framing_extras_len as usize);
+        match Status::from(header.vbucket_id_or_status) {
+            Status::NoError => {}
+            Status::KeyNotFound => return Ok(None),
+            status => {
+                let message = &body[extras_len + key_len..];
+                let detail = if message.is_empty() {
+                    None
+                } else {
+                    Some(String::from_utf8_lossy(message).into_owned())
+                };
+                return Err(ProtoError::from_status(status, detail).into());
+            }
+        }
+        if extras_len != 4 {
+            return Err(io::Error::from(io::ErrorKind::InvalidData).into());
+        }
+        let flags = body.get_u32();
+        let key = body.split_to(key_len).freeze();
+        Ok(Some(Self {
+            key,
+            value: body.freeze(),
+            flags,
+            cas: header.cas,
+        }))
+    }
+
+    /// Read a get response asynchronously, reading the body into `buf`
+    /// like `Packet::read_from_with_buf`
+    pub async fn read_from<R: AsyncRead + Unpin + Send>(
+        r: &mut R,
+        buf: &mut BytesMut,
+    ) -> Result<Option<Self>> {
+        let header: PacketHeader = AsyncOps::read_from(r).await?;
+        header.check_body_len(Packet::default_max_body_len())?;
+
+        buf.clear();
+        buf.resize(header.body_len as usize, 0);
+        r.read_exact(buf.as_mut()).await?;
+
+        Self::parse(&header, buf.split())
+    }
+
+    /// Synchronous `read_from`
+    pub fn read_from_sync<R: Read>(r: &mut R, buf: &mut BytesMut) -> Result<Option<Self>> {
+        let header: PacketHeader = SyncOps::read_from(r)?;
+        header.check_body_len(Packet::default_max_body_len())?;
+
+        buf.clear();
+        buf.resize(header.body_len as usize, 0);
+        r.read_exact(buf.as_mut())?;
+
+        Self::parse(&header, buf.split())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GetValue;
+    use crate::{Extras, Opcode, Packet, Status, SyncOps};
+    use bytes::{Bytes, BytesMut};
+
+    fn response(status: Status, extras: Extras, val: &'static [u8]) -> Vec<u8> {
+        let packet = Packet::response(
+            Opcode::GetK,
+            status,
+            1,
+            9,
+            extras,
+            Bytes::from_static(b"key"),
+            Bytes::from_static(val),
+        );
+        let mut written = Vec::new();
+        packet.write_to(&mut written).unwrap();
+        written
+    }
+
+    #[test]
+    fn test_get_value() {
+        let mut buf = BytesMut::new();
+        let hit = response(Status::NoError, Extras::Get { flags: 7 }, b"value");
+        assert_eq!(
+            GetValue::read_from_sync(&mut &hit[..], &mut buf).unwrap(),
+            Some(GetValue {
+                key: Bytes::from_static(b"key"),
+                value: Bytes::from_static(b"value"),
+                flags: 7,
+                cas: 9,
+            })
+        );
+        let miss = response(Status::KeyNotFound, Extras::None, b"Not found");
+        assert_eq!(
+            GetValue::read_from_sync(&mut &miss[..], &mut buf).unwrap(),
+            None
+        );
+        let busy = response(Status::Busy, Extras::None, b"");
+        assert!(GetValue::read_from_sync(&mut &busy[..], &mut buf).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+//! Flag conventions of other clients, which keep the type and compression of a value in its 32-bit flags;
+//! storing values with the matching flags lets those clients read them, and the other way around.
+
+/// spymemcached `SerializingTranscoder`
+pub mod spymemcached {
+    /// Java serialized object
+    pub const SERIALIZED: u32 = 1;
+    /// gzip compressed
+    pub const COMPRESSED: u32 = 2;
+    /// Bits holding the type of the primitive values; strings have none
+    pub const SPECIAL_MASK: u32 = 0xff00;
+    pub const SPECIAL_BOOLEAN: u32 = 1 << 8;
+    pub const SPECIAL_INT: u32 = 2 << 8;
+    pub const SPECIAL_LONG: u32 = 3 << 8;
+    pub const SPECIAL_DATE: u32 = 4 << 8;
+    pub const SPECIAL_BYTE: u32 = 5 << 8;
+    pub const SPECIAL_FLOAT: u32 = 6 << 8;
+    pub const SPECIAL_DOUBLE: u32 = 7 << 8;
+    pub const SPECIAL_BYTEARRAY: u32 = 8 << 8;
+}
+
+/// pymemcache `pymemcache.serde`
+pub mod pymemcache {
+    pub const BYTES: u32 = 0;
+    pub const PICKLE: u32 = 1 << 0;
+    pub const INTEGER: u32 = 1 << 1;
+    /// Python 2 `long`
+    pub const LONG: u32 = 1 << 2;
+    /// zlib compressed
+    pub const COMPRESSED: u32 = 1 << 3;
+    pub const TEXT: u32 = 1 << 4;
+}
+
+/// php-memcached
+pub mod php {
+    pub const STRING: u32 = 0;
+    pub const LONG: u32 = 1;
+    pub const DOUBLE: u32 = 2;
+    pub const BOOL: u32 = 3;
+    pub const SERIALIZED: u32 = 4;
+    pub const IGBINARY: u32 = 5;
+    pub const JSON: u32 = 6;
+    pub const MSGPACK: u32 = 7;
+    /// Bits holding one of the types above
+    pub const TYPE_MASK: u32 = 0xf;
+    pub const COMPRESSED: u32 = 1 << 4;
+    pub const COMPRESSION_ZLIB: u32 = 1 << 5;
+    pub const COMPRESSION_FASTLZ: u32 = 1 << 6;
+    /// Application flags are stored above this bit
+    pub const USER_FLAGS_SHIFT: u32 = 16;
+}
+
+/// Portable type of a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Bytes,
+    /// UTF-8 text
+    Text,
+    Integer,
+    Float,
+    Bool,
+    Json,
+    /// Serialized by the client's own serializer (Java serialization, pickle, PHP `serialize`, ...),
+    /// only readable by that client
+    Native,
+}
+
+/// A client whose flag convention is followed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Convention {
+    Spymemcached,
+    Pymemcache,
+    PhpMemcached,
+}
+
+impl Convention {
+    /// Flags marking a value of `kind`; `None` if the client has no such type
+    ///
+    /// # Examples
+    /// ```rust
+    /// use memcached_proto::interop::{php, Convention, ValueKind};
+    ///
+    /// let flags = Convention::PhpMemcached.flags(ValueKind::Json, false);
+    /// assert_eq!(flags, Some(php::JSON));
+    /// assert_eq!(Convention::PhpMemcached.decode(php::JSON), Some((ValueKind::Json, false)));
+    /// ```
+    pub fn flags(self, kind: ValueKind, compressed: bool) -> Option<u32> {
+        let (kind, compression) = match self {
+            Self::Spymemcached => {
+                use self::spymemcached::*;
+                let kind = match kind {
+                    ValueKind::Bytes => SPECIAL_BYTEARRAY,
+                    ValueKind::Text => 0,
+                    ValueKind::Integer => SPECIAL_LONG,
+                    ValueKind::Float => SPECIAL_DOUBLE,
+                    ValueKind::Bool => SPECIAL_BOOLEAN,
+                    ValueKind::Json => return None,
+                    ValueKind::Native => SERIALIZED,
+                };
+                (kind, COMPRESSED)
+            }
+            Self::Pymemcache => {
+                use self::pymemcache::*;
+                let kind = match kind {
+                    ValueKind::Bytes => BYTES,
+                    ValueKind::Text => TEXT,
+                    ValueKind::Integer => INTEGER,
+                    ValueKind::Native => PICKLE,
+                    ValueKind::Float | ValueKind::Bool | ValueKind::Json => return None,
+                };
+                (kind, COMPRESSED)
+            }
+            Self::PhpMemcached => {
+                use self::php::*;
+                let kind = match kind {
+                    ValueKind::Bytes | ValueKind::Text => STRING,
+                    ValueKind::Integer => LONG,
+                    ValueKind::Float => DOUBLE,
+                    ValueKind::Bool => BOOL,
+                    ValueKind::Json => JSON,
+                    ValueKind::Native => SERIALIZED,
+                };
+                (kind, COMPRESSED | COMPRESSION_ZLIB)
+            }
+        };
+        Some(if compressed { kind | compression } else { kind })
+    }
+
+    /// Kind of a value stored with `flags` and whether it is compressed;
+    /// `None` for a type the convention doesn't define or that has no `ValueKind`.
+    /// php-memcached application flags (above `php::USER_FLAGS_SHIFT`) are ignored.
+    pub fn decode(self, flags: u32) -> Option<(ValueKind, bool)> {
+        match self {
+            Self::Spymemcached => {
+                use self::spymemcached::*;
+                let compressed = flags & COMPRESSED != 0;
+                let kind = match flags & SPECIAL_MASK {
+                    0 if flags & SERIALIZED != 0 => ValueKind::Native,
+                    0 => ValueKind::Text,
+                    SPECIAL_BOOLEAN => ValueKind::Bool,
+                    SPECIAL_INT | SPECIAL_LONG | SPECIAL_BYTE => ValueKind::Integer,
+                    SPECIAL_FLOAT | SPECIAL_DOUBLE => ValueKind::Float,
+                    SPECIAL_BYTEARRAY => ValueKind::Bytes,
+                    _ => return None,
+                };
+                Some((kind, compressed))
+            }
+            Self::Pymemcache => {
+                use self::pymemcache::*;
+                let kind = match flags & !COMPRESSED {
+                    BYTES => ValueKind::Bytes,
+                    TEXT => ValueKind::Text,
+                    INTEGER | LONG => ValueKind::Integer,
+                    PICKLE => ValueKind::Native,
+                    _ => return None,
+                };
+                Some((kind, flags & COMPRESSED != 0))
+            }
+            Self::PhpMemcached => {
+                use self::php::*;
+                let kind = match flags & TYPE_MASK {
+                    STRING => ValueKind::Bytes,
+                    LONG => ValueKind::Integer,
+                    DOUBLE => ValueKind::Float,
+                    BOOL => ValueKind::Bool,
+                    JSON => ValueKind::Json,
+                    SERIALIZED | IGBINARY | MSGPACK => ValueKind::Native,
+                    _ => return None,
+                };
+                Some((kind, flags & COMPRESSED != 0))
+            }
+        }
+    }
+
+    /// Value of an integer as the client writes it: big-endian without leading zero bytes
+    /// for spymemcached, ASCII decimal for the others
+    pub fn encode_integer(self, n: i64) -> Vec<u8> {
+        match self {
+            Self::Spymemcached => {
+                let bytes = n.to_be_bytes();
+                let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+                bytes[start..].to_vec()
+            }
+            Self::Pymemcache | Self::PhpMemcached => n.to_string().into_bytes(),
+        }
+    }
+
+    /// Inverse of `encode_integer` for a value stored with `flags`; `None` if `val` isn't an
+    /// integer in that encoding. spymemcached sign-extends from the width of the stored type
+    /// (1 byte for `SPECIAL_BYTE`, 4 for `SPECIAL_INT`, 8 for `SPECIAL_LONG`),
+    /// a shorter value had its leading zero bytes stripped.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use memcached_proto::interop::{spymemcached, Convention};
+    ///
+    /// let java = Convention::Spymemcached;
+    /// assert_eq!(java.decode_integer(spymemcached::SPECIAL_INT, &[0xff; 4]), Some(-1));
+    /// assert_eq!(java.decode_integer(spymemcached::SPECIAL_LONG, &[0xff; 4]), Some(0xffff_ffff));
+    /// ```
+    pub fn decode_integer(self, flags: u32, val: &[u8]) -> Option<i64> {
+        match self {
+            Self::Spymemcached => {
+                use self::spymemcached::*;
+                let width = match flags & SPECIAL_MASK {
+                    SPECIAL_BYTE => 1,
+                    SPECIAL_INT => 4,
+                    _ => 8,
+                };
+                if val.len() > width {
+                    return None;
+                }
+                let n = val.iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+                let shift = 64 - 8 * val.len() as u32;
+                if val.len() == width && shift < 64 {
+                    Some((n << shift) as i64 >> shift)
+                } else {
+                    Some(n as i64)
+                }
+            }
+            Self::Pymemcache | Self::PhpMemcached => std::str::from_utf8(val).ok()?.parse().ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let kinds = [
+            ValueKind::Bytes,
+            ValueKind::Text,
+            ValueKind::Integer,
+            ValueKind::Float,
+            ValueKind::Bool,
+            ValueKind::Json,
+            ValueKind::Native,
+        ];
+        let conventions = [
+            Convention::Spymemcached,
+            Convention::Pymemcache,
+            Convention::PhpMemcached,
+        ];
+        for &convention in &conventions {
+            for &kind in &kinds {
+                for &compressed in &[false, true] {
+                    if let Some(flags) = convention.flags(kind, compressed) {
+                        let decoded = convention.decode(flags).unwrap();
+                        // php-memcached stores bytes and text alike
+                        let kind = match (convention, kind) {
+                            (Convention::PhpMemcached, ValueKind::Text) => ValueKind::Bytes,
+                            _ => kind,
+                        };
+                        assert_eq!(decoded, (kind, compressed), "{:?}", convention);
+                    }
+                }
+            }
+            let flags = convention.flags(ValueKind::Integer, false).unwrap();
+            for &n in &[0, 1, 255, 256, -1, -256, i64::MAX, i64::MIN] {
+                let val = convention.encode_integer(n);
+                let decoded = convention.decode_integer(flags, &val);
+                assert_eq!(decoded, Some(n), "{:?}", convention);
+            }
+        }
+        assert_eq!(Convention::Spymemcached.encode_integer(256), [1, 0]);
+        // Java ints and bytes are sign-extended from their own width
+        let java = Convention::Spymemcached;
+        assert_eq!(
+            java.decode_integer(spymemcached::SPECIAL_INT, &[0xff; 4]),
+            Some(-1)
+        );
+        assert_eq!(
+            java.decode_integer(spymemcached::SPECIAL_INT, &[0xff]),
+            Some(255)
+        );
+        assert_eq!(
+            java.decode_integer(spymemcached::SPECIAL_BYTE, &[0x80]),
+            Some(-128)
+        );
+        assert_eq!(
+            java.decode_integer(spymemcached::SPECIAL_INT, &[1; 5]),
+            None
+        );
+        assert_eq!(
+            Convention::PhpMemcached.decode(php::LONG | 7 << php::USER_FLAGS_SHIFT),
+            Some((ValueKind::Integer, false))
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! The part of `std::io` the protocol types report errors with, for builds without `std`;
+//! with `std`, `crate::io` is `std::io` itself.
+use alloc::boxed::Box;
+use core::fmt;
+
+/// Subset of `std::io::ErrorKind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    InvalidInput,
+    InvalidData,
+    UnexpectedEof,
+}
+
+impl ErrorKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::InvalidInput => "invalid input parameter",
+            Self::InvalidData => "invalid data",
+            Self::UnexpectedEof => "unexpected end of file",
+        }
+    }
+}
+
+/// Payload of an `Error`
+pub trait Cause: fmt::Debug + fmt::Display + Send + Sync {}
+
+impl<T: fmt::Debug + fmt::Display + Send + Sync> Cause for T {}
+
+/// Stand-in for `std::io::Error`: a kind, optionally with the error that caused it
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    cause: Option<Box<dyn Cause>>,
+}
+
+impl Error {
+    pub fn new<E: Cause + 'static>(kind: ErrorKind, cause: E) -> Self {
+        Self {
+            kind,
+            cause: Some(Box::new(cause)),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn get_ref(&self) -> Option<&(dyn Cause + 'static)> {
+        self.cause.as_deref()
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self { kind, cause: None }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.cause {
+            Some(cause) => write!(f, "{}", cause),
+            None => f.write_str(self.kind.as_str()),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
@@ -1,8 +1,87 @@
+//! Without the default `std` feature only the protocol types are built
+//! (packets and their headers, extras and framing extras, typed requests and responses),
+//! as `no_std` with `alloc`.
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+mod builder;
+#[cfg(feature = "std")]
 pub mod client;
 mod code;
+#[cfg(feature = "tokio-util")]
+mod codec;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+#[cfg(feature = "std")]
+mod connection;
+mod custom;
+#[cfg(feature = "std")]
+mod delay;
+mod dump;
 mod error;
+#[cfg(feature = "std")]
+mod expiration;
+mod frame;
+#[cfg(feature = "std")]
+mod framed;
+#[cfg(feature = "arbitrary")]
+mod fuzz;
+#[cfg(feature = "std")]
+mod get;
+#[cfg(feature = "std")]
+pub mod interop;
+#[cfg(not(feature = "std"))]
+mod io;
+#[cfg(feature = "lz4")]
+mod lz4;
 mod packet;
+mod raw;
+mod request;
+mod response;
+#[cfg(feature = "serde")]
+mod serde_base64;
+#[cfg(feature = "trace-frames")]
+mod trace;
+#[cfg(feature = "std")]
+pub mod udp;
+#[cfg(feature = "std")]
+mod value;
+
+#[cfg(feature = "std")]
+use std::io;
 
-pub use code::{Magic, Opcode, Status};
-pub use error::{Error, Result};
-pub use packet::{AsyncOps, Extras, Packet, PacketHeader, PacketRef, SyncOps};
+pub use builder::PacketBuilder;
+pub use code::{DataType, Magic, Opcode, Status, VBucketState};
+#[cfg(feature = "tokio-util")]
+pub use codec::BinaryCodec;
+#[cfg(feature = "std")]
+pub use connection::{Connection, Event};
+pub use custom::{CustomExtras, ExtrasParser};
+#[cfg(feature = "std")]
+pub use delay::{Delay, Delayed};
+pub use dump::PacketDump;
+pub use error::{BodyTooLarge, CorruptHeader, InvalidPacket, ParseError};
+#[cfg(feature = "std")]
+pub use error::{Error, InvalidExpiration, Result};
+#[cfg(feature = "std")]
+pub use expiration::{Expiration, MAX_RELATIVE_SECS};
+pub use frame::FrameInfo;
+#[cfg(feature = "std")]
+pub use framed::Framed;
+#[cfg(feature = "std")]
+pub use get::GetValue;
+#[cfg(feature = "lz4")]
+pub use lz4::Lz4Stream;
+pub use packet::{
+    Decoded, Decoder, EncodedPrefix, Extras, Packet, PacketHeader, PacketRef, RequestHeader,
+    ResponseHeader, DEFAULT_MAX_BODY_LEN,
+};
+#[cfg(feature = "std")]
+pub use packet::{AsyncOps, SyncOps};
+pub use raw::RawHeader;
+pub use request::Request;
+pub use response::Response;
+#[cfg(feature = "json")]
+pub use value::JSON_FLAGS;
+#[cfg(feature = "std")]
+pub use value::{FromMemcachedValue, ToMemcachedValue};
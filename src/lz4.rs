@@ -0,0 +1,466 @@
+//! LZ4 compression of a whole connection, as opposed to `client::Compressed` compressing values
+use bytes::{Buf, BytesMut};
+use futures_lite::{AsyncRead, AsyncWrite};
+use lz4_flex::block::{decompress_into, decompress_into_with_dict};
+use lz4_flex::frame::{BlockMode, BlockSize, FrameEncoder, FrameInfo};
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use twox_hash::XxHash32;
+
+const MAGIC: u32 = 0x184D_2204;
+const READ_CHUNK: usize = 8 * 1024;
+// linked blocks may reference the last 64 KiB decoded
+const WINDOW: usize = 64 * 1024;
+
+/// Transport compressing both directions with the streaming
+/// [LZ4 frame format](https://github.com/lz4/lz4/blob/dev/doc/lz4_Frame_format.md),
+/// for links where bandwidth costs more than CPU; the other end (a proxy or a server build
+/// with the same framing) must wrap its side of the connection too, nothing is negotiated.
+///
+/// Written bytes are buffered and go out as one LZ4 block on `flush`, which the packet
+/// writers of this crate call after every request; the frame stays open until `finish`
+/// (`close` for async writers), a stream ending inside a frame fails with `UnexpectedEof`.
+/// Reading accepts independent and linked blocks and verifies the frame header checksum;
+/// block and content checksums are skipped without being verified.
+///
+/// # Examples
+/// ```rust
+/// use bytes::Bytes;
+/// use memcached_proto::{Extras, Lz4Stream, Opcode, Packet, SyncOps};
+/// use std::io::Cursor;
+///
+/// let p = Packet::request(Opcode::Set, 0, 0, 0, Extras::Store { flags: 0, expiration: 0 },
+///     Bytes::from("k"), Bytes::from(vec![b'v'; 4096]));
+/// let mut w = Lz4Stream::new(Vec::new());
+/// p.write_to(&mut w).unwrap();
+/// w.finish().unwrap();
+/// let compressed = w.into_inner();
+/// assert!(compressed.len() < 200);
+///
+/// let mut r = Lz4Stream::new(Cursor::new(compressed));
+/// assert_eq!(Packet::read_from(&mut r).unwrap(), p);
+/// ```
+pub struct Lz4Stream<T> {
+    io: T,
+    encoder: FrameEncoder<Vec<u8>>,
+    /// Bytes of the encoder output already written to `io`
+    sent: usize,
+    decoder: Decoder,
+}
+
+impl<T> Lz4Stream<T> {
+    pub fn new(io: T) -> Self {
+        let info = FrameInfo::new()
+            .block_size(BlockSize::Max64KB)
+            .block_mode(BlockMode::Independent);
+        Self {
+            io,
+            encoder: FrameEncoder::with_frame_info(info, Vec::new()),
+            sent: 0,
+            decoder: Decoder::default(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    /// Bytes written but not flushed yet are lost
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    /// Flush and end the frame, the next write starts another
+    pub fn finish(&mut self) -> io::Result<()>
+    where
+        T: Write,
+    {
+        self.encoder.try_finish().map_err(io::Error::from)?;
+        self.io.write_all(self.encoder.get_ref())?;
+        self.encoder.get_mut().clear();
+        self.io.flush()
+    }
+
+    /// Ready once the compressed output is all written to `io`
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>
+    where
+        T: AsyncWrite + Unpin,
+    {
+        let out = self.encoder.get_mut();
+        while self.sent < out.len() {
+            match futures_lite::ready!(Pin::new(&mut self.io).poll_write(cx, &out[self.sent..])) {
+                Ok(0) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Ok(n) => self.sent += n,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+        out.clear();
+        self.sent = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: Read> Read for Lz4Stream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.decoder.has_output() {
+                return Ok(self.decoder.copy_to(buf));
+            }
+            if self.decoder.step()? {
+                continue;
+            }
+            let mut chunk = [0u8; READ_CHUNK];
+            let n = self.io.read(&mut chunk)?;
+            self.decoder.feed(&chunk[..n])?;
+            if n == 0 {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl<T: Write> Write for Lz4Stream<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write_all(buf)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()?;
+        self.io.write_all(self.encoder.get_ref())?;
+        self.encoder.get_mut().clear();
+        self.io.flush()
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Lz4Stream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.decoder.has_output() {
+                return Poll::Ready(Ok(this.decoder.copy_to(buf)));
+            }
+            if this.decoder.step()? {
+                continue;
+            }
+            let mut chunk = [0u8; READ_CHUNK];
+            let n = futures_lite::ready!(Pin::new(&mut this.io).poll_read(cx, &mut chunk))?;
+            this.decoder.feed(&chunk[..n])?;
+            if n == 0 {
+                return Poll::Ready(Ok(0));
+            }
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Lz4Stream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        // don't let unsent output pile up
+        futures_lite::ready!(this.poll_send(cx))?;
+        this.encoder.write_all(buf)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.encoder.flush()?;
+        futures_lite::ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.io).poll_flush(cx)
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        // ends the frame, the next write would start another
+        this.encoder.try_finish().map_err(io::Error::from)?;
+        futures_lite::ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.io).poll_close(cx)
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Lz4Stream<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Lz4Stream").field("io", &self.io).finish()
+    }
+}
+
+/// Flags of the frame being read
+#[derive(Debug)]
+struct Frame {
+    linked: bool,
+    block_checksums: bool,
+    content_checksum: bool,
+    max_block_len: usize,
+}
+
+/// Incremental reader of LZ4 frames, decoding a block once all of it is buffered
+#[derive(Debug, Default)]
+struct Decoder {
+    input: BytesMut,
+    frame: Option<Frame>,
+    output: Vec<u8>,
+    pos: usize,
+    /// End of the previously decoded data, referenced by linked blocks
+    window: Vec<u8>,
+}
+
+impl Decoder {
+    fn has_output(&self) -> bool {
+        self.pos < self.output.len()
+    }
+
+    fn copy_to(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.output.len() - self.pos);
+        buf[..n].copy_from_slice(&self.output[self.pos..self.pos + n]);
+        self.pos += n;
+        n
+    }
+
+    /// Buffer compressed bytes, an empty `chunk` is the end of the stream
+    /// and fails with `UnexpectedEof` in the middle of a frame
+    fn feed(&mut self, chunk: &[u8]) -> io::Result<()> {
+        if chunk.is_empty() && (self.frame.is_some() || !self.input.is_empty()) {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.input.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    /// Consume the frame header or the next block if it is all buffered,
+    /// returns whether it did
+    fn step(&mut self) -> io::Result<bool> {
+        match &self.frame {
+            None => self.header(),
+            Some(_) => self.block(),
+        }
+    }
+
+    fn header(&mut self) -> io::Result<bool> {
+        // magic, FLG, BD and the header checksum
+        if self.input.len() < 7 {
+            return Ok(false);
+        }
+        let magic =
+            u32::from_le_bytes([self.input[0], self.input[1], self.input[2], self.input[3]]);
+        let (flg, bd) = (self.input[4], self.input[5]);
+        if magic != MAGIC || flg >> 6 != 0b01 {
+            return Err(invalid("not an LZ4 frame"));
+        }
+        if flg & 0b1 != 0 {
+            return Err(invalid("LZ4 dictionaries are not supported"));
+        }
+        let max_block_len = match (bd >> 4) & 0b111 {
+            4 => 64 * 1024,
+            5 => 256 * 1024,
+            6 => 1024 * 1024,
+            7 => 4 * 1024 * 1024,
+            _ => return Err(invalid("invalid LZ4 block size")),
+        };
+        let content_size_len = if flg & 0b1000 != 0 { 8 } else { 0 };
+        let len = 7 + content_size_len;
+        if self.input.len() < len {
+            return Ok(false);
+        }
+        // second byte of the xxHash32 of the descriptor, FLG to the content size
+        let checksum = (XxHash32::oneshot(0, &self.input[4..len - 1]) >> 8) as u8;
+        if checksum != self.input[len - 1] {
+            return Err(invalid("LZ4 frame header checksum mismatch"));
+        }
+        self.input.advance(len);
+        self.window.clear();
+        self.frame = Some(Frame {
+            linked: flg & 0b10_0000 == 0,
+            block_checksums: flg & 0b1_0000 != 0,
+            content_checksum: flg & 0b100 != 0,
+            max_block_len,
+        });
+        Ok(true)
+    }
+
+    fn block(&mut self) -> io::Result<bool> {
+        let frame = self.frame.as_ref().expect("frame header read");
+        if self.input.len() < 4 {
+            return Ok(false);
+        }
+        let size = u32::from_le_bytes([self.input[0], self.input[1], self.input[2], self.input[3]]);
+        if size == 0 {
+            // end mark
+            let len = 4 + if frame.content_checksum { 4 } else { 0 };
+            if self.input.len() < len {
+                return Ok(false);
+            }
+            self.input.advance(len);
+            self.frame = None;
+            return Ok(true);
+        }
+        let compressed = size & 0x8000_0000 == 0;
+        let data_len = (size & 0x7fff_ffff) as usize;
+        if data_len > frame.max_block_len {
+            return Err(invalid("LZ4 block longer than the frame allows"));
+        }
+        let len = 4 + data_len + if frame.block_checksums { 4 } else { 0 };
+        if self.input.len() < len {
+            return Ok(false);
+        }
+        let data = &self.input[4..4 + data_len];
+        if compressed {
+            self.output.resize(frame.max_block_len, 0);
+            let n = if frame.linked {
+                decompress_into_with_dict(data, &mut self.output, &self.window)
+            } else {
+                decompress_into(data, &mut self.output)
+            }
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            self.output.truncate(n);
+        } else {
+            self.output.clear();
+            self.output.extend_from_slice(data);
+        }
+        if frame.linked {
+            self.window.extend_from_slice(&self.output);
+            let excess = self.window.len().saturating_sub(WINDOW);
+            self.window.drain(..excess);
+        }
+        self.pos = 0;
+        self.input.advance(len);
+        Ok(true)
+    }
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // 300 KiB compressible enough to check the ratio, spanning several blocks
+    fn data() -> Vec<u8> {
+        (0..300 * 1024).map(|i| (i / 7 % 251) as u8).collect()
+    }
+
+    /// Hands out one byte per read, so the decoder sees every block in pieces
+    struct Trickle(Cursor<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = data();
+        let mut w = Lz4Stream::new(Vec::new());
+        w.write_all(b"first").unwrap();
+        w.flush().unwrap();
+        w.write_all(&data).unwrap();
+        w.finish().unwrap();
+        let compressed = w.into_inner();
+        assert!(compressed.len() < data.len() / 4);
+
+        // a standard LZ4 frame
+        let mut plain = Vec::new();
+        lz4_flex::frame::FrameDecoder::new(&compressed[..])
+            .read_to_end(&mut plain)
+            .unwrap();
+        assert_eq!(&plain[..5], b"first");
+        assert_eq!(&plain[5..], &data[..]);
+
+        let mut r = Lz4Stream::new(Trickle(Cursor::new(compressed)));
+        let mut decoded = Vec::new();
+        r.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, plain);
+    }
+
+    #[test]
+    fn test_linked_blocks() {
+        let data = data();
+        let info = FrameInfo::new()
+            .block_mode(BlockMode::Linked)
+            .block_checksums(true)
+            .content_checksum(true)
+            .content_size(Some(data.len() as u64));
+        let mut encoder = FrameEncoder::with_frame_info(info, Vec::new());
+        encoder.write_all(&data).unwrap();
+        // two frames back to back
+        let mut compressed = encoder.finish().unwrap();
+        let mut encoder = FrameEncoder::new(compressed);
+        encoder.write_all(b"second frame").unwrap();
+        compressed = encoder.finish().unwrap();
+
+        let mut r = Lz4Stream::new(Cursor::new(compressed));
+        let mut decoded = Vec::new();
+        r.read_to_end(&mut decoded).unwrap();
+        assert_eq!(&decoded[..data.len()], &data[..]);
+        assert_eq!(&decoded[data.len()..], b"second frame");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut r = Lz4Stream::new(Cursor::new(vec![0u8; 16]));
+        let err = r.read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // cut in the middle of a block
+        let mut w = Lz4Stream::new(Vec::new());
+        w.write_all(&data()).unwrap();
+        w.flush().unwrap();
+        let mut compressed = w.into_inner();
+        compressed.truncate(compressed.len() - 1);
+        let mut r = Lz4Stream::new(Cursor::new(compressed));
+        let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // cut between the blocks of a frame
+        let mut w = Lz4Stream::new(Vec::new());
+        w.write_all(b"block").unwrap();
+        w.flush().unwrap();
+        let mut r = Lz4Stream::new(Cursor::new(w.into_inner()));
+        let mut decoded = Vec::new();
+        let err = r.read_to_end(&mut decoded).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(decoded, b"block");
+
+        // corrupt header checksum
+        let mut w = Lz4Stream::new(Vec::new());
+        w.write_all(b"block").unwrap();
+        w.finish().unwrap();
+        let mut compressed = w.into_inner();
+        compressed[6] ^= 1;
+        let mut r = Lz4Stream::new(Cursor::new(compressed));
+        let err = r.read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_async() {
+        use futures_lite::{AsyncReadExt, AsyncWriteExt};
+
+        let data = data();
+        futures_lite::future::block_on(async {
+            let mut w = Lz4Stream::new(futures_lite::io::Cursor::new(Vec::new()));
+            w.write_all(&data).await.unwrap();
+            w.close().await.unwrap();
+            let compressed = w.into_inner().into_inner();
+
+            let mut r = Lz4Stream::new(futures_lite::io::Cursor::new(compressed));
+            let mut decoded = Vec::new();
+            r.read_to_end(&mut decoded).await.unwrap();
+            assert_eq!(decoded, data);
+        });
+    }
+}
@@ -1,23 +1,69 @@
 //! [Memcached Binary Protocol](https://github.com/memcached/memcached/wiki/BinaryProtocolRevamped)
-use crate::code::{Magic, Opcode, Status};
+use crate::code::{DataType, Magic, Opcode, Status};
+use crate::custom::{self, CustomExtras};
+use crate::error::{BodyTooLarge, CorruptHeader, InvalidPacket, ParseError};
+use crate::frame::FrameInfo;
+use crate::io;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use async_trait::async_trait;
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use bytes::{Buf, Bytes, BytesMut};
+#[cfg(feature = "std")]
+use byteorder::{BigEndian, WriteBytesExt};
+use bytes::buf::BufExt;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use core::convert::TryFrom;
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "std")]
 use futures_lite::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use num_traits::FromPrimitive;
-use std::io::{self, Read, Write};
+#[cfg(feature = "std")]
+use std::io::{IoSlice, Read, Write};
 
+#[cfg(feature = "std")]
 pub trait SyncOps: Sized {
     /// Write synchronously without flush;
     fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()>;
     fn read_from<R: Read>(r: &mut R) -> io::Result<Self>;
+    /// Write synchronously without flush, using vectored writes where the type supports them;
+    fn write_vectored_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_to(w)
+    }
 }
 
+#[cfg(feature = "std")]
 #[async_trait]
 pub trait AsyncOps: Sized {
     /// Write asynchronously without flush;
     async fn write_to<W: AsyncWrite + Unpin + Send>(&self, w: &mut W) -> io::Result<()>;
     async fn read_from<R: AsyncRead + Unpin + Send>(r: &mut R) -> io::Result<Self>;
+    /// Write asynchronously without flush, using vectored writes where the type supports them;
+    async fn write_vectored_to<W: AsyncWrite + Unpin + Send>(&self, w: &mut W) -> io::Result<()>
+    where
+        Self: Sync,
+    {
+        self.write_to(w).await
+    }
+}
+
+/// Default of `Packet::default_max_body_len`
+pub const DEFAULT_MAX_BODY_LEN: u32 = 64 * 1024 * 1024;
+static MAX_BODY_LEN: AtomicU32 = AtomicU32::new(DEFAULT_MAX_BODY_LEN);
+
+/// Longest header + framing extras + extras a packet can have
+const MAX_PREFIX_LEN: usize = PacketHeader::size() + u8::MAX as usize + u8::MAX as usize;
+
+/// Header, framing extras and extras of a packet encoded on the stack, see `PacketRef::io_slices`
+pub struct EncodedPrefix {
+    buf: [u8; MAX_PREFIX_LEN],
+    len: usize,
+}
+
+impl EncodedPrefix {
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
 }
 
 // Byte/     0       |       1       |       2       |       3       |
@@ -36,16 +82,21 @@ pub trait AsyncOps: Sized {
 //   |                                                               |
 //   +---------------+---------------+---------------+---------------+
 //   Total 24 bytes
-#[derive(Debug, PartialEq)]
+//
+// With the alternative magics (`0x08`/`0x18`) the key length field is split
+// into a one byte framing extras length followed by a one byte key length.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PacketHeader {
     pub magic: Magic,
     pub opcode: Opcode,
+    /// always 0 unless `magic` is one of the alternative magics
+    pub framing_extras_len: u8,
     pub key_len: u16,
     pub extras_len: u8,
-    /// raw bytes(0x00)
-    pub data_type: u8,
+    pub data_type: DataType,
     pub vbucket_id_or_status: u16,
-    /// `key_len` + `extras_len` + `val_len`
+    /// `framing_extras_len` + `key_len` + `extras_len` + `val_len`
     pub body_len: u32,
     pub opaque: u32,
     pub cas: u64,
@@ -68,9 +119,10 @@ impl PacketHeader {
         Self {
             magic: Magic::Request,
             opcode,
+            framing_extras_len: 0,
             key_len,
             extras_len,
-            data_type: 0,
+            data_type: DataType::RAW,
             vbucket_id_or_status: vbucket_id,
             body_len,
             opaque,
@@ -91,12 +143,13 @@ impl PacketHeader {
         let body_len = (key.len() + extras.len() + val.len()) as u32;
 
         Self {
-            magic: Magic::Request,
+            magic: Magic::Response,
             opcode,
+            framing_extras_len: 0,
             key_len,
             extras_len,
-            data_type: 0,
-            vbucket_id_or_status: status as u16,
+            data_type: DataType::RAW,
+            vbucket_id_or_status: status.into(),
             body_len,
             opaque,
             cas,
@@ -110,14 +163,95 @@ impl PacketHeader {
     /// Parse from buffer;
     /// if anything incorrect such as wrong magic number returns `None`;
     /// # Panics
-    /// This function panics if there is not enough remaining data in `buf`.
+    /// This function panics if there is not enough remaining data in `buf`, see `try_parse`.
     pub fn parse(mut buf: &[u8]) -> io::Result<Self> {
+        Self::get_from(&mut buf)
+    }
+
+    /// Non-panicking `parse`, telling how many bytes are missing if `buf` is short
+    ///
+    /// # Examples
+    /// ```rust
+    /// use memcached_proto::{PacketHeader, ParseError};
+    ///
+    /// assert_eq!(PacketHeader::try_parse(&[0x80; 10]), Err(ParseError::Incomplete { needed: 14 }));
+    /// assert_eq!(PacketHeader::try_parse(&[0; 24]), Err(ParseError::Invalid));
+    /// ```
+    pub fn try_parse(buf: &[u8]) -> Result<Self, ParseError> {
+        if buf.len() < Self::size() {
+            return Err(ParseError::Incomplete {
+                needed: Self::size() - buf.len(),
+            });
+        }
+        Self::parse(buf).map_err(|_| ParseError::Invalid)
+    }
+
+    /// Decode from `buf`, advancing it past the header;
+    /// fails with `UnexpectedEof` (leaving `buf` untouched) if there is not enough remaining data.
+    pub fn decode_from<B: Buf>(buf: &mut B) -> io::Result<Self> {
+        if buf.remaining() < Self::size() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Self::get_from(buf)
+    }
+
+    pub(crate) fn check_body_len(&self, max_body_len: u32) -> io::Result<()> {
+        if self.body_len > max_body_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                BodyTooLarge {
+                    body_len: self.body_len,
+                    max_body_len,
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    /// Encode on the stack
+    pub fn to_bytes(&self) -> [u8; Self::size()] {
+        let mut buf = [0u8; Self::size()];
+        self.encode_to(&mut &mut buf[..]);
+        buf
+    }
+
+    /// Encode into `buf`
+    pub fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        buf.put_u8(self.magic as u8);
+        buf.put_u8(self.opcode.into());
+        if self.magic.is_alt() {
+            debug_assert!(
+                self.key_len <= u8::MAX as u16,
+                "key too long for the alt magic"
+            );
+            buf.put_u8(self.framing_extras_len);
+            buf.put_u8(self.key_len as u8);
+        } else {
+            buf.put_u16(self.key_len);
+        }
+        buf.put_u8(self.extras_len);
+        buf.put_u8(self.data_type.into());
+        buf.put_u16(self.vbucket_id_or_status);
+        buf.put_u32(self.body_len);
+        buf.put_u32(self.opaque);
+        buf.put_u64(self.cas);
+    }
+
+    fn get_from<B: Buf>(buf: &mut B) -> io::Result<Self> {
+        let magic = Magic::from_u8(buf.get_u8()).ok_or(io::ErrorKind::InvalidData)?;
+        let opcode = Opcode::from(buf.get_u8());
+        let (framing_extras_len, key_len) = if magic.is_alt() {
+            (buf.get_u8(), buf.get_u8() as u16)
+        } else {
+            (0, buf.get_u16())
+        };
         Ok(Self {
-            magic: Magic::from_u8(buf.get_u8()).ok_or(io::ErrorKind::InvalidData)?,
-            opcode: Opcode::from_u8(buf.get_u8()).ok_or(io::ErrorKind::InvalidData)?,
-            key_len: buf.get_u16(),
+            magic,
+            opcode,
+            framing_extras_len,
+            key_len,
             extras_len: buf.get_u8(),
-            data_type: buf.get_u8(),
+            data_type: DataType::from(buf.get_u8()),
             vbucket_id_or_status: buf.get_u16(),
             body_len: buf.get_u32(),
             opaque: buf.get_u32(),
@@ -126,20 +260,11 @@ impl PacketHeader {
     }
 }
 
+#[cfg(feature = "std")]
 #[async_trait]
 impl AsyncOps for PacketHeader {
     async fn write_to<W: AsyncWrite + Unpin + Send>(&self, w: &mut W) -> io::Result<()> {
-        w.write_all(&(self.magic as u8).to_be_bytes()).await?;
-        w.write_all(&(self.opcode as u8).to_be_bytes()).await?;
-        w.write_all(&self.key_len.to_be_bytes()).await?;
-        w.write_all(&self.extras_len.to_be_bytes()).await?;
-        w.write_all(&self.data_type.to_be_bytes()).await?;
-        w.write_all(&self.vbucket_id_or_status.to_be_bytes())
-            .await?;
-        w.write_all(&self.body_len.to_be_bytes()).await?;
-        w.write_all(&self.opaque.to_be_bytes()).await?;
-        w.write_all(&self.cas.to_be_bytes()).await?;
-        Ok(())
+        w.write_all(&self.to_bytes()).await
     }
 
     async fn read_from<R: AsyncRead + Unpin + Send>(r: &mut R) -> io::Result<Self> {
@@ -149,18 +274,10 @@ impl AsyncOps for PacketHeader {
     }
 }
 
+#[cfg(feature = "std")]
 impl SyncOps for PacketHeader {
     fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        w.write_u8(self.magic as u8)?;
-        w.write_u8(self.opcode as u8)?;
-        w.write_u16::<BigEndian>(self.key_len)?;
-        w.write_u8(self.extras_len)?;
-        w.write_u8(self.data_type)?;
-        w.write_u16::<BigEndian>(self.vbucket_id_or_status)?;
-        w.write_u32::<BigEndian>(self.body_len)?;
-        w.write_u32::<BigEndian>(self.opaque)?;
-        w.write_u64::<BigEndian>(self.cas)?;
-        Ok(())
+        w.write_all(&self.to_bytes())
     }
 
     fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
@@ -170,12 +287,155 @@ impl SyncOps for PacketHeader {
     }
 }
 
+/// Single line summary for per-request logs, e.g.
+/// `op=Get klen=12 elen=0 vlen=0 status=0x0001 opaque=0x2a cas=0x0`;
+/// requests show `vbucket=` instead of `status=`.
+impl fmt::Display for PacketHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val_len = self.body_len.saturating_sub(
+            self.framing_extras_len as u32 + self.key_len as u32 + self.extras_len as u32,
+        );
+        write!(
+            f,
+            "op={:?} klen={} elen={} vlen={}",
+            self.opcode, self.key_len, self.extras_len, val_len
+        )?;
+        if self.framing_extras_len > 0 {
+            write!(f, " flen={}", self.framing_extras_len)?;
+        }
+        if self.magic.is_request() {
+            write!(f, " vbucket={}", self.vbucket_id_or_status)?;
+        } else {
+            write!(f, " status={:#06x}", self.vbucket_id_or_status)?;
+        }
+        write!(f, " opaque={:#x} cas={:#x}", self.opaque, self.cas)
+    }
+}
+
+/// `PacketHeader` of a request, with the vbucket id typed as such
 #[derive(Debug, PartialEq)]
+pub struct RequestHeader {
+    pub opcode: Opcode,
+    /// a non-zero length selects `Magic::AltRequest` on the wire
+    pub framing_extras_len: u8,
+    pub key_len: u16,
+    pub extras_len: u8,
+    pub data_type: DataType,
+    pub vbucket_id: u16,
+    pub body_len: u32,
+    pub opaque: u32,
+    pub cas: u64,
+}
+
+/// `PacketHeader` of a response, with the status typed as such
+#[derive(Debug, PartialEq)]
+pub struct ResponseHeader {
+    pub opcode: Opcode,
+    /// a non-zero length selects `Magic::AltResponse` on the wire
+    pub framing_extras_len: u8,
+    pub key_len: u16,
+    pub extras_len: u8,
+    pub data_type: DataType,
+    pub status: Status,
+    pub body_len: u32,
+    pub opaque: u32,
+    pub cas: u64,
+}
+
+impl From<RequestHeader> for PacketHeader {
+    fn from(h: RequestHeader) -> Self {
+        Self {
+            magic: if h.framing_extras_len > 0 {
+                Magic::AltRequest
+            } else {
+                Magic::Request
+            },
+            opcode: h.opcode,
+            framing_extras_len: h.framing_extras_len,
+            key_len: h.key_len,
+            extras_len: h.extras_len,
+            data_type: h.data_type,
+            vbucket_id_or_status: h.vbucket_id,
+            body_len: h.body_len,
+            opaque: h.opaque,
+            cas: h.cas,
+        }
+    }
+}
+
+impl From<ResponseHeader> for PacketHeader {
+    fn from(h: ResponseHeader) -> Self {
+        Self {
+            magic: if h.framing_extras_len > 0 {
+                Magic::AltResponse
+            } else {
+                Magic::Response
+            },
+            opcode: h.opcode,
+            framing_extras_len: h.framing_extras_len,
+            key_len: h.key_len,
+            extras_len: h.extras_len,
+            data_type: h.data_type,
+            vbucket_id_or_status: h.status.into(),
+            body_len: h.body_len,
+            opaque: h.opaque,
+            cas: h.cas,
+        }
+    }
+}
+
+impl TryFrom<PacketHeader> for RequestHeader {
+    type Error = io::Error;
+
+    /// Fails with `InvalidData` if `h` isn't a request header
+    fn try_from(h: PacketHeader) -> io::Result<Self> {
+        if !h.magic.is_request() {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        Ok(Self {
+            opcode: h.opcode,
+            framing_extras_len: h.framing_extras_len,
+            key_len: h.key_len,
+            extras_len: h.extras_len,
+            data_type: h.data_type,
+            vbucket_id: h.vbucket_id_or_status,
+            body_len: h.body_len,
+            opaque: h.opaque,
+            cas: h.cas,
+        })
+    }
+}
+
+impl TryFrom<PacketHeader> for ResponseHeader {
+    type Error = io::Error;
+
+    /// Fails with `InvalidData` if `h` isn't a response header or its status is unknown
+    fn try_from(h: PacketHeader) -> io::Result<Self> {
+        if !h.magic.is_response() {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        Ok(Self {
+            opcode: h.opcode,
+            framing_extras_len: h.framing_extras_len,
+            key_len: h.key_len,
+            extras_len: h.extras_len,
+            data_type: h.data_type,
+            status: Status::from(h.vbucket_id_or_status),
+            body_len: h.body_len,
+            opaque: h.opaque,
+            cas: h.cas,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Extras {
     /// No Extra data
     None,
     /// Unknown extras data
-    Unknown(Bytes),
+    Unknown(#[cfg_attr(feature = "serde", serde(with = "crate::serde_base64"))] Bytes),
     /// Extra data for set/add/replace
     Store { flags: u32, expiration: u32 },
     /// Extra data for incr/decr
@@ -188,10 +448,40 @@ pub enum Extras {
     Flush { expiration: u32 },
     /// Extra data for incr/decr
     Verbosity { verbosity: u32 },
-    /// Extra data for touch/GAT/GATQ
+    /// Extra data for touch and the get-and-touch commands
     Touch { expiration: u32 },
     /// Extra data for the get commands in `Response`
     Get { flags: u32 },
+    /// Extra data for SetVBucket, `state` converts to and from `VBucketState`
+    VBucketState { state: u32 },
+    /// Extra data for TapConnect
+    TapConnect { flags: u32 },
+    /// Extra data for TapDelete/TapFlush/TapOpaque/TapVBucketSet/TapCheckPointStart/TabCheckPointEnd,
+    /// followed on the wire by 3 reserved bytes
+    Tap {
+        engine_private_len: u16,
+        flags: u16,
+        ttl: u8,
+    },
+    /// Extra data for TapMutation, the `Tap` fields followed by the item's flags and expiration
+    TapMutation {
+        engine_private_len: u16,
+        flags: u16,
+        ttl: u8,
+        item_flags: u32,
+        item_expiration: u32,
+    },
+    /// Extra data for the range commands (RGet/RSet/RAppend/RPrepend/RDelete/RIncr/RDecr and their quiet variants),
+    /// followed on the wire by the start key then the end key in the key field;
+    /// `start_key_len` splits the two, one reserved byte precedes `flags`
+    Range {
+        start_key_len: u16,
+        flags: u8,
+        max_results: u32,
+    },
+    /// Extra data of an opcode registered with `Extras::register`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(Box<dyn CustomExtras>),
 }
 
 impl Extras {
@@ -207,13 +497,81 @@ impl Extras {
             Self::Verbosity { .. } => 4,
             Self::Touch { .. } => 4,
             Self::Get { .. } => 4,
+            Self::VBucketState { .. } => 4,
+            Self::TapConnect { .. } => 4,
+            Self::Tap { .. } => 2 + 2 + 1 + 3,
+            Self::TapMutation { .. } => 2 + 2 + 1 + 3 + 4 + 4,
+            Self::Range { .. } => 2 + 1 + 1 + 4,
+            Self::Custom(c) => c.len(),
         }
     }
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+    /// Encode into `buf`
+    pub fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        match self {
+            Self::None => {}
+            Self::Unknown(b) => buf.put_slice(b),
+            Self::Store { flags, expiration } => {
+                buf.put_u32(*flags);
+                buf.put_u32(*expiration);
+            }
+            Self::Counter {
+                amount,
+                initial,
+                expiration,
+            } => {
+                buf.put_u64(*amount);
+                buf.put_u64(*initial);
+                buf.put_u32(*expiration);
+            }
+            Self::Flush { expiration } => buf.put_u32(*expiration),
+            Self::Verbosity { verbosity } => buf.put_u32(*verbosity),
+            Self::Touch { expiration } => buf.put_u32(*expiration),
+            Self::Get { flags } => buf.put_u32(*flags),
+            Self::VBucketState { state } => buf.put_u32(*state),
+            Self::TapConnect { flags } => buf.put_u32(*flags),
+            Self::Tap {
+                engine_private_len,
+                flags,
+                ttl,
+            } => {
+                buf.put_u16(*engine_private_len);
+                buf.put_u16(*flags);
+                buf.put_u8(*ttl);
+                buf.put_slice(&[0; 3]);
+            }
+            Self::TapMutation {
+                engine_private_len,
+                flags,
+                ttl,
+                item_flags,
+                item_expiration,
+            } => {
+                buf.put_u16(*engine_private_len);
+                buf.put_u16(*flags);
+                buf.put_u8(*ttl);
+                buf.put_slice(&[0; 3]);
+                buf.put_u32(*item_flags);
+                buf.put_u32(*item_expiration);
+            }
+            Self::Range {
+                start_key_len,
+                flags,
+                max_results,
+            } => {
+                buf.put_u16(*start_key_len);
+                buf.put_u8(0);
+                buf.put_u8(*flags);
+                buf.put_u32(*max_results);
+            }
+            Self::Custom(c) => buf.put_slice(&c.to_vec()),
+        }
+    }
     /// Write asynchronously without flush;
+    #[cfg(feature = "std")]
     pub async fn write<W: AsyncWrite + Unpin>(&self, w: &mut W) -> io::Result<()> {
         match self {
             Self::None => {}
@@ -245,10 +603,25 @@ impl Extras {
             Self::Get { flags } => {
                 w.write_all(&flags.to_be_bytes()).await?;
             }
+            Self::VBucketState { state } => {
+                w.write_all(&state.to_be_bytes()).await?;
+            }
+            Self::TapConnect { flags } => {
+                w.write_all(&flags.to_be_bytes()).await?;
+            }
+            Self::Tap { .. } | Self::TapMutation { .. } | Self::Range { .. } => {
+                let mut buf = Vec::with_capacity(self.len());
+                self.encode_to(&mut buf);
+                w.write_all(&buf).await?;
+            }
+            Self::Custom(c) => {
+                w.write_all(&c.to_vec()).await?;
+            }
         }
         Ok(())
     }
     /// Write asynchronously without flush;
+    #[cfg(feature = "std")]
     pub fn write_sync<W: Write>(&self, w: &mut W) -> io::Result<()> {
         match self {
             Self::None => {}
@@ -280,10 +653,86 @@ impl Extras {
             Self::Get { flags } => {
                 w.write_u32::<BigEndian>(*flags)?;
             }
+            Self::VBucketState { state } => {
+                w.write_u32::<BigEndian>(*state)?;
+            }
+            Self::TapConnect { flags } => {
+                w.write_u32::<BigEndian>(*flags)?;
+            }
+            Self::Tap { .. } | Self::TapMutation { .. } | Self::Range { .. } => {
+                let mut buf = Vec::with_capacity(self.len());
+                self.encode_to(&mut buf);
+                w.write_all(&buf)?;
+            }
+            Self::Custom(c) => {
+                w.write_all(&c.to_vec())?;
+            }
         }
         Ok(())
     }
-    /// Parse from buf based on `buf.len()` and `Opcode`
+    /// Parse non-empty extras of `opcode` in the direction of `magic` with `parser`
+    /// instead of decoding them as `Unknown`, replacing any parser registered before;
+    /// applies to the whole process. Only opcodes unknown to the crate (`Opcode::Other`)
+    /// can be registered, others fail with `InvalidInput` so the core layouts can't be overridden.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memcached_proto::{CustomExtras, Extras, Magic, Opcode};
+    /// use std::any::Any;
+    ///
+    /// #[derive(Debug)]
+    /// struct Priority(u8);
+    ///
+    /// impl CustomExtras for Priority {
+    ///     fn len(&self) -> usize { 1 }
+    ///     fn encode(&self, buf: &mut [u8]) { buf[0] = self.0 }
+    ///     fn as_any(&self) -> &dyn Any { self }
+    /// }
+    ///
+    /// Extras::register(Magic::Request, Opcode::Other(0xe0), |buf| Ok(Box::new(Priority(buf[0]))))
+    ///     .unwrap();
+    /// assert!(Extras::register(Magic::Request, Opcode::Set, |buf| Ok(Box::new(Priority(buf[0]))))
+    ///     .is_err());
+    /// match Extras::parse_for(Magic::Request, Opcode::Other(0xe0), &[3]).unwrap() {
+    ///     Extras::Custom(c) => assert_eq!(c.downcast_ref::<Priority>().unwrap().0, 3),
+    ///     e => panic!("unexpected extras {:?}", e),
+    /// }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn register(magic: Magic, opcode: Opcode, parser: custom::ExtrasParser) -> io::Result<()> {
+        custom::register(magic, opcode, parser)
+    }
+    /// Parse from buf based on `buf.len()`, `Magic` and `Opcode`;
+    /// responses only carry extras for the get commands, anything else decodes as `Unknown`
+    /// unless a parser was registered with `Extras::register`.
+    pub fn parse_for(magic: Magic, opcode: Opcode, mut buf: &[u8]) -> io::Result<Self> {
+        if !buf.is_empty() {
+            if let Some(parser) = custom::lookup(magic, opcode) {
+                return parser(buf).map(Self::Custom);
+            }
+        }
+        if magic.is_request() {
+            return Self::parse(opcode, buf);
+        }
+        if buf.is_empty() {
+            return Ok(Self::None);
+        }
+        Ok(match opcode {
+            Opcode::Get
+            | Opcode::GetQ
+            | Opcode::GetK
+            | Opcode::GetKQ
+            | Opcode::GAT
+            | Opcode::GATQ
+            | Opcode::GATK
+            | Opcode::GATKQ => Self::Get {
+                flags: buf.read_u32()?,
+            },
+            _ => Self::Unknown(buf.to_bytes()),
+        })
+    }
+    /// Parse from buf based on `buf.len()` and `Opcode`, assuming the layout of a request
     pub fn parse(opcode: Opcode, mut buf: &[u8]) -> io::Result<Self> {
         if buf.is_empty() {
             return Ok(Self::None);
@@ -295,38 +744,178 @@ impl Extras {
             | Opcode::AddQ
             | Opcode::Replace
             | Opcode::ReplaceQ => Self::Store {
-                flags: buf.read_u32::<BigEndian>()?,
-                expiration: buf.read_u32::<BigEndian>()?,
+                flags: buf.read_u32()?,
+                expiration: buf.read_u32()?,
             },
             Opcode::Get | Opcode::GetQ | Opcode::GetK | Opcode::GetKQ => Self::Get {
-                flags: buf.read_u32::<BigEndian>()?,
+                flags: buf.read_u32()?,
             },
             Opcode::Increment | Opcode::IncrementQ | Opcode::Decrement | Opcode::DecrementQ => {
                 Self::Counter {
-                    amount: buf.read_u64::<BigEndian>()?,
-                    initial: buf.read_u64::<BigEndian>()?,
-                    expiration: buf.read_u32::<BigEndian>()?,
+                    amount: buf.read_u64()?,
+                    initial: buf.read_u64()?,
+                    expiration: buf.read_u32()?,
                 }
             }
             Opcode::Verbosity => Self::Verbosity {
-                verbosity: buf.read_u32::<BigEndian>()?,
+                verbosity: buf.read_u32()?,
+            },
+            Opcode::Touch | Opcode::GAT | Opcode::GATQ | Opcode::GATK | Opcode::GATKQ => {
+                Self::Touch {
+                    expiration: buf.read_u32()?,
+                }
+            }
+            Opcode::Flush | Opcode::FlushQ => Self::Flush {
+                expiration: buf.read_u32()?,
             },
-            Opcode::Touch | Opcode::GAT | Opcode::GATQ => Self::Touch {
-                expiration: buf.read_u32::<BigEndian>()?,
+            Opcode::SetVBucket => Self::VBucketState {
+                state: buf.read_u32()?,
             },
-            Opcode::Flush => Self::Flush {
-                expiration: buf.read_u32::<BigEndian>()?,
+            Opcode::TapConnect => Self::TapConnect {
+                flags: buf.read_u32()?,
             },
+            Opcode::TapMutation => {
+                let (engine_private_len, flags, ttl) = Self::read_tap(&mut buf)?;
+                Self::TapMutation {
+                    engine_private_len,
+                    flags,
+                    ttl,
+                    item_flags: buf.read_u32()?,
+                    item_expiration: buf.read_u32()?,
+                }
+            }
+            Opcode::TapDelete
+            | Opcode::TapFlush
+            | Opcode::TapOpaque
+            | Opcode::TapVBucketSet
+            | Opcode::TapCheckPointStart
+            | Opcode::TabCheckPointEnd => {
+                let (engine_private_len, flags, ttl) = Self::read_tap(&mut buf)?;
+                Self::Tap {
+                    engine_private_len,
+                    flags,
+                    ttl,
+                }
+            }
+            opcode if opcode.is_range() => {
+                let start_key_len = buf.read_u16()?;
+                let _reserved = buf.read_u8()?;
+                Self::Range {
+                    start_key_len,
+                    flags: buf.read_u8()?,
+                    max_results: buf.read_u32()?,
+                }
+            }
             _ => Self::Unknown(buf.to_bytes()),
         })
     }
+
+    // engine private length, flags, TTL and 3 reserved bytes common to the TAP messages
+    fn read_tap(buf: &mut &[u8]) -> io::Result<(u16, u16, u8)> {
+        let engine_private_len = buf.read_u16()?;
+        let flags = buf.read_u16()?;
+        let ttl = buf.read_u8()?;
+        let _reserved = buf.read_slice(3)?;
+        Ok((engine_private_len, flags, ttl))
+    }
+}
+
+/// Big endian reads off the extras, failing with `UnexpectedEof` past the end
+trait ReadBe<'a> {
+    fn read_slice(&mut self, len: usize) -> io::Result<&'a [u8]>;
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read_slice(1)?[0])
+    }
+    fn read_u16(&mut self) -> io::Result<u16> {
+        Ok((&mut self.read_slice(2)?).get_u16())
+    }
+    fn read_u32(&mut self) -> io::Result<u32> {
+        Ok((&mut self.read_slice(4)?).get_u32())
+    }
+    fn read_u64(&mut self) -> io::Result<u64> {
+        Ok((&mut self.read_slice(8)?).get_u64())
+    }
+}
+
+impl<'a> ReadBe<'a> for &'a [u8] {
+    fn read_slice(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let (head, rest) = self.split_at(len);
+        *self = rest;
+        Ok(head)
+    }
+}
+
+/// Single line summary, e.g. `flags=0x7 exp=60`; empty for `Extras::None`
+impl fmt::Display for Extras {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => Ok(()),
+            Self::Unknown(b) => write!(f, "extras=<{} bytes>", b.len()),
+            Self::Store { flags, expiration } => write!(f, "flags={:#x} exp={}", flags, expiration),
+            Self::Counter {
+                amount,
+                initial,
+                expiration,
+            } => write!(
+                f,
+                "amount={} initial={} exp={}",
+                amount, initial, expiration
+            ),
+            Self::Flush { expiration } | Self::Touch { expiration } => {
+                write!(f, "exp={}", expiration)
+            }
+            Self::Verbosity { verbosity } => write!(f, "verbosity={}", verbosity),
+            Self::Get { flags } => write!(f, "flags={:#x}", flags),
+            Self::VBucketState { state } => write!(f, "vbucket_state={}", state),
+            Self::TapConnect { flags } => write!(f, "tap_flags={:#x}", flags),
+            Self::Tap {
+                engine_private_len,
+                flags,
+                ttl,
+            } => write!(
+                f,
+                "engine_len={} tap_flags={:#x} ttl={}",
+                engine_private_len, flags, ttl
+            ),
+            Self::TapMutation {
+                engine_private_len,
+                flags,
+                ttl,
+                item_flags,
+                item_expiration,
+            } => write!(
+                f,
+                "engine_len={} tap_flags={:#x} ttl={} flags={:#x} exp={}",
+                engine_private_len, flags, ttl, item_flags, item_expiration
+            ),
+            Self::Range {
+                start_key_len,
+                flags,
+                max_results,
+            } => write!(
+                f,
+                "start_len={} range_flags={:#x} max_results={}",
+                start_key_len, flags, max_results
+            ),
+            Self::Custom(c) => write!(f, "extras={:?}", c),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Packet {
     pub header: PacketHeader,
+    /// only present with the alternative magics
+    pub framing_extras: Vec<FrameInfo>,
     pub extras: Extras,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_base64"))]
     pub key: Bytes,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_base64"))]
     pub val: Bytes,
 }
 
@@ -353,9 +942,10 @@ impl Packet {
         let header = PacketHeader {
             magic: Magic::Request,
             opcode,
+            framing_extras_len: 0,
             key_len: key.len() as u16,
             extras_len: extras.len() as u8,
-            data_type: 0,
+            data_type: DataType::RAW,
             vbucket_id_or_status: vbucket_id,
             body_len: (key.len() + extras.len() + val.len()) as u32,
             opaque,
@@ -363,6 +953,7 @@ impl Packet {
         };
         Self {
             header,
+            framing_extras: Vec::new(),
             extras,
             key,
             val,
@@ -389,128 +980,589 @@ impl Packet {
         val: Bytes,
     ) -> Self {
         let header = PacketHeader {
-            magic: Magic::Request,
+            magic: Magic::Response,
             opcode,
+            framing_extras_len: 0,
             key_len: key.len() as u16,
             extras_len: extras.len() as u8,
-            data_type: 0,
-            vbucket_id_or_status: status as u16,
+            data_type: DataType::RAW,
+            vbucket_id_or_status: status.into(),
             body_len: (key.len() + extras.len() + val.len()) as u32,
             opaque,
             cas,
         };
         Self {
             header,
+            framing_extras: Vec::new(),
             extras,
             key,
             val,
         }
     }
+    /// Successful response to `request`, with its opcode, opaque and CAS;
+    /// set `header.cas` to the item's CAS when the command changes or returns it.
+    /// `extras` are dropped for the core opcodes whose responses have none,
+    /// only the get commands answer with (`Extras::Get`) flags.
+    pub fn success_for(request: &Packet, extras: Extras, key: Bytes, val: Bytes) -> Self {
+        let extras = match request.header.opcode {
+            Opcode::Get
+            | Opcode::GetQ
+            | Opcode::GetK
+            | Opcode::GetKQ
+            | Opcode::GAT
+            | Opcode::GATQ
+            | Opcode::GATK
+            | Opcode::GATKQ
+            | Opcode::Other(_) => extras,
+            _ => Extras::None,
+        };
+        Self::response(
+            request.header.opcode,
+            Status::NoError,
+            request.header.opaque,
+            request.header.cas,
+            extras,
+            key,
+            val,
+        )
+    }
+
+    /// Response to `request` failing with `status`, carrying `message` as its value
+    ///
+    /// # Examples
+    /// ```rust
+    /// use memcached_proto::{Extras, Opcode, Packet, Status};
+    /// use bytes::Bytes;
+    ///
+    /// let req = Packet::request(Opcode::Get, 0, 7, 0, Extras::None, Bytes::from("k"), Bytes::new());
+    /// let resp = Packet::error_for(&req, Status::KeyNotFound, "Not found");
+    /// assert!(resp.is_response());
+    /// assert_eq!(resp.header.opaque, 7);
+    /// assert_eq!(resp.status(), Status::KeyNotFound);
+    /// ```
+    pub fn error_for(request: &Packet, status: Status, message: &str) -> Self {
+        Self::response(
+            request.header.opcode,
+            status,
+            request.header.opaque,
+            0,
+            Extras::None,
+            Bytes::new(),
+            Bytes::copy_from_slice(message.as_bytes()),
+        )
+    }
     /// Constructs new `Packet`, just pass `Bytes::new()` to represents an empty key or value;
     ///
     pub fn new(header: PacketHeader, extras: Extras, key: Bytes, val: Bytes) -> Self {
         Self {
             header,
+            framing_extras: Vec::new(),
             extras,
             key,
             val,
         }
     }
+    /// Attach framing extras, switching to the alternative magic of the same direction;
+    /// the alternative magics have one byte for the framing extras length and the key length,
+    /// longer framing extras or keys fail instead of being truncated.
+    pub fn with_framing_extras(
+        mut self,
+        framing_extras: Vec<FrameInfo>,
+    ) -> Result<Self, InvalidPacket> {
+        let len = FrameInfo::total_len(&framing_extras);
+        if len > u8::MAX as usize {
+            return Err(InvalidPacket::FramingExtrasTooLong(len));
+        }
+        if self.key.len() > u8::MAX as usize {
+            return Err(InvalidPacket::AltKeyTooLong(self.key.len()));
+        }
+        self.header.magic = if self.header.magic.is_request() {
+            Magic::AltRequest
+        } else {
+            Magic::AltResponse
+        };
+        self.header.body_len =
+            self.header.body_len - self.header.framing_extras_len as u32 + len as u32;
+        self.header.framing_extras_len = len as u8;
+        self.framing_extras = framing_extras;
+        Ok(self)
+    }
+    #[inline]
+    pub fn data_type(&self) -> DataType {
+        self.header.data_type
+    }
+    /// Set the data type of the value, e.g. `DataType::JSON`
+    pub fn with_data_type(mut self, data_type: DataType) -> Self {
+        self.header.data_type = data_type;
+        self
+    }
+    /// Encode into `buf` without any intermediate allocation
+    pub fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        self.as_ref().encode_to(buf)
+    }
+
+    /// Exact number of bytes `encode_to` writes
+    pub fn encoded_len(&self) -> usize {
+        self.as_ref().encoded_len()
+    }
+
+    /// Decode one whole packet from `buf`;
+    /// fails with `UnexpectedEof` if `buf` doesn't hold all of it,
+    /// in which case the header may already have been consumed (see `Decoder` for partial input).
+    pub fn decode_from<B: Buf>(buf: &mut B) -> io::Result<Self> {
+        let header = PacketHeader::decode_from(buf)?;
+        let body_len = header.body_len as usize;
+        if buf.remaining() < body_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut body = BytesMut::with_capacity(body_len);
+        body.put(BufExt::take(buf, body_len));
+        Packet::parse_body(header, body)
+    }
+
+    /// Borrow as a `PacketRef`
+    pub fn as_ref(&self) -> PacketRef<'_> {
+        PacketRef {
+            header: &self.header,
+            framing_extras: &self.framing_extras,
+            extras: &self.extras,
+            key: &self.key,
+            val: &self.val,
+        }
+    }
+
+    /// Limit on `body_len` applied by `read_from`, `read_from_with_buf` and `Decoder::new`
+    pub fn default_max_body_len() -> u32 {
+        MAX_BODY_LEN.load(Ordering::Relaxed)
+    }
+
+    /// Change the limit returned by `default_max_body_len` for the whole process
+    pub fn set_default_max_body_len(max_body_len: u32) {
+        MAX_BODY_LEN.store(max_body_len, Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "std")]
+    /// Read asynchronously, reading the body into `buf`;
+    /// once the returned packet (and every `Bytes` taken from it) is dropped,
+    /// the next call reuses the allocation instead of allocating a new one.
+    pub async fn read_from_with_buf<R: AsyncRead + Unpin + Send>(
+        r: &mut R,
+        buf: &mut BytesMut,
+    ) -> io::Result<Self> {
+        Self::read_limited(r, buf, Self::default_max_body_len()).await
+    }
+
+    #[cfg(feature = "std")]
+    /// Synchronous `read_from_with_buf`
+    pub fn read_from_with_buf_sync<R: Read>(r: &mut R, buf: &mut BytesMut) -> io::Result<Self> {
+        Self::read_limited_sync(r, buf, Self::default_max_body_len())
+    }
+
+    #[cfg(feature = "std")]
+    /// Read asynchronously, failing with a `BodyTooLarge` error (kind `InvalidData`)
+    /// instead of allocating if the header announces more than `max_body_len` bytes.
+    pub async fn read_from_limited<R: AsyncRead + Unpin + Send>(
+        r: &mut R,
+        max_body_len: u32,
+    ) -> io::Result<Self> {
+        Self::read_limited(r, &mut BytesMut::new(), max_body_len).await
+    }
+
+    #[cfg(feature = "std")]
+    /// Synchronous `read_from_limited`
+    pub fn read_from_limited_sync<R: Read>(r: &mut R, max_body_len: u32) -> io::Result<Self> {
+        Self::read_limited_sync(r, &mut BytesMut::new(), max_body_len)
+    }
+
+    #[cfg(feature = "std")]
+    async fn read_limited<R: AsyncRead + Unpin + Send>(
+        r: &mut R,
+        buf: &mut BytesMut,
+        max_body_len: u32,
+    ) -> io::Result<Self> {
+        let header: PacketHeader = AsyncOps::read_from(r).await?;
+        header.check_body_len(max_body_len)?;
+
+        buf.clear();
+        buf.resize(header.body_len as usize, 0);
+        r.read_exact(buf.as_mut()).await?;
+
+        Packet::parse_body(header, buf.split())
+    }
+
+    #[cfg(feature = "std")]
+    fn read_limited_sync<R: Read>(
+        r: &mut R,
+        buf: &mut BytesMut,
+        max_body_len: u32,
+    ) -> io::Result<Self> {
+        let header: PacketHeader = SyncOps::read_from(r)?;
+        header.check_body_len(max_body_len)?;
+
+        buf.clear();
+        buf.resize(header.body_len as usize, 0);
+        r.read_exact(buf.as_mut())?;
+
+        Packet::parse_body(header, buf.split())
+    }
+
+    #[cfg(feature = "std")]
+    /// Read asynchronously everything but the value, which is copied to `w` as it arrives
+    /// instead of being buffered; the returned packet has an empty `val`, `header.body_len` still counts it.
+    /// No body length limit applies, `w` decides what to do with long values.
+    pub async fn read_streaming<R, W>(r: &mut R, w: &mut W) -> io::Result<Self>
+    where
+        R: AsyncRead + Unpin + Send,
+        W: AsyncWrite + Unpin,
+    {
+        let mut header: PacketHeader = AsyncOps::read_from(r).await?;
+        let body_len = header.body_len;
+        let fixed_len =
+            header.framing_extras_len as u32 + header.extras_len as u32 + header.key_len as u32;
+        if fixed_len > body_len {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let mut buf = BytesMut::new();
+        buf.resize(fixed_len as usize, 0);
+        r.read_exact(buf.as_mut()).await?;
+        header.body_len = fixed_len;
+        let mut packet = Packet::parse_body(header, buf)?;
+        packet.header.body_len = body_len;
+
+        let val_len = (body_len - fixed_len) as u64;
+        if futures_lite::io::copy(r.take(val_len), &mut *w).await? != val_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(packet)
+    }
+
+    /// Parse the body following `header`, `body` must hold exactly `header.body_len` bytes
+    pub fn parse_body(header: PacketHeader, mut body: BytesMut) -> io::Result<Self> {
+        let fixed_len = header.framing_extras_len as usize
+            + header.extras_len as usize
+            + header.key_len as usize;
+        if body.len() != header.body_len as usize || fixed_len > body.len() {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let framing_extras = FrameInfo::parse_all(
+            header.magic,
+            body.split_to(header.framing_extras_len as usize).bytes(),
+        )?;
+        let extras = Extras::parse_for(
+            header.magic,
+            header.opcode,
+            body.split_to(header.extras_len as usize).bytes(),
+        )?;
+        let key = body.split_to(header.key_len as usize).freeze();
+        let value = body.freeze();
+
+        let packet = Packet {
+            header,
+            framing_extras,
+            extras,
+            key,
+            val: value,
+        };
+        #[cfg(feature = "trace-frames")]
+        crate::trace::frame("decode", &packet.as_ref());
+        Ok(packet)
+    }
     #[inline]
     pub fn is_request(&self) -> bool {
-        matches!(self.header.magic, Magic::Request)
+        self.header.magic.is_request()
     }
 
     #[inline]
     pub fn is_response(&self) -> bool {
-        matches!(self.header.magic, Magic::Response)
+        self.header.magic.is_response()
     }
-    /// Response status;
+    /// Response status, codes this crate doesn't know are kept as `Status::Unknown`;
     /// # Panics
-    /// if data is incorrect or packet isn't response
+    /// in debug builds if packet isn't response
     #[inline]
     pub fn status(&self) -> Status {
         debug_assert!(self.is_response());
-        Status::from_u16(self.header.vbucket_id_or_status).unwrap()
+        Status::from(self.header.vbucket_id_or_status)
+    }
+    /// Check the invariants decoding doesn't enforce, for proxies and test harnesses
+    /// that must reject malformed frames: consistent lengths, key of at most 250 bytes
+    /// and one a single length byte holds under the alternative magics,
+    /// known data type bits; for responses a known status and no extras on errors;
+    /// for requests to the core opcodes the extras length and a zero CAS where the command
+    /// forbids one. The vbucket isn't checked, how many a cluster has is up to its configuration.
+    pub fn validate(&self) -> core::result::Result<(), InvalidPacket> {
+        let header = &self.header;
+        if header.magic.is_alt() && header.key_len > u8::MAX as u16 {
+            return Err(InvalidPacket::AltKeyTooLong(header.key_len as usize));
+        }
+        let actual = FrameInfo::total_len(&self.framing_extras)
+            + self.extras.len()
+            + self.key.len()
+            + self.val.len();
+        if header.body_len as usize != actual
+            || header.framing_extras_len as usize != FrameInfo::total_len(&self.framing_extras)
+            || header.extras_len as usize != self.extras.len()
+            || header.key_len as usize != self.key.len()
+        {
+            return Err(InvalidPacket::BodyLength {
+                body_len: header.body_len,
+                actual,
+            });
+        }
+        if self.key.len() > 250 {
+            return Err(InvalidPacket::KeyTooLong(self.key.len()));
+        }
+        let known = DataType::JSON | DataType::SNAPPY | DataType::XATTR;
+        if !known.contains(header.data_type) {
+            return Err(InvalidPacket::UnknownDataType(header.data_type.bits()));
+        }
+        if self.is_response() {
+            return match self.status() {
+                Status::Unknown(status) => Err(InvalidPacket::UnknownStatus(status)),
+                Status::NoError | Status::AuthenticationContinue => Ok(()),
+                status if !self.extras.is_empty() => Err(InvalidPacket::ErrorWithExtras(status)),
+                _ => Ok(()),
+            };
+        }
+        let opcode = header.opcode;
+        let expected = match opcode {
+            Opcode::Set
+            | Opcode::SetQ
+            | Opcode::Add
+            | Opcode::AddQ
+            | Opcode::Replace
+            | Opcode::ReplaceQ => Some(8),
+            Opcode::Increment | Opcode::IncrementQ | Opcode::Decrement | Opcode::DecrementQ => {
+                Some(20)
+            }
+            Opcode::Touch
+            | Opcode::GAT
+            | Opcode::GATQ
+            | Opcode::GATK
+            | Opcode::GATKQ
+            | Opcode::Verbosity => Some(4),
+            // the expiration is optional
+            Opcode::Flush | Opcode::FlushQ if self.extras.len() == 4 => Some(4),
+            Opcode::Get
+            | Opcode::GetQ
+            | Opcode::GetK
+            | Opcode::GetKQ
+            | Opcode::Delete
+            | Opcode::DeleteQ
+            | Opcode::Append
+            | Opcode::AppendQ
+            | Opcode::Prepend
+            | Opcode::PrependQ
+            | Opcode::Flush
+            | Opcode::FlushQ
+            | Opcode::Quit
+            | Opcode::QuitQ
+            | Opcode::NoOp
+            | Opcode::Version
+            | Opcode::Stat => Some(0),
+            _ => None,
+        };
+        if let Some(expected) = expected {
+            if self.extras.len() != expected {
+                return Err(InvalidPacket::ExtrasLength {
+                    opcode,
+                    expected,
+                    actual: self.extras.len(),
+                });
+            }
+        }
+        if opcode.forbids_cas() && header.cas != 0 {
+            return Err(InvalidPacket::NonZeroCas(opcode));
+        }
+        Ok(())
     }
 }
 
+/// The header summary followed by the extras, keys and values are left out
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.header)?;
+        if !self.extras.is_empty() {
+            write!(f, " {}", self.extras)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
 impl SyncOps for Packet {
     fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        SyncOps::write_to(&self.header, w)?;
-        self.extras.write_sync(w)?;
-        w.write_all(self.key.bytes())?;
-        w.write_all(self.val.bytes())?;
+        SyncOps::write_to(&self.as_ref(), w)?;
         w.flush()?;
         Ok(())
     }
 
-    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
-        let header: PacketHeader = SyncOps::read_from(r)?;
-
-        let body_len = header.body_len as usize;
-        let mut buf = BytesMut::with_capacity(body_len);
-        unsafe {
-            buf.set_len(body_len);
-        }
-        r.read_exact(buf.as_mut())?;
-
-        let extras = Extras::parse(
-            header.opcode,
-            buf.split_to(header.extras_len as usize).bytes(),
-        )?;
-        let key = buf.split_to(header.key_len as usize).freeze();
-        let value = buf.freeze();
+    fn write_vectored_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        SyncOps::write_vectored_to(&self.as_ref(), w)?;
+        w.flush()
+    }
 
-        Ok(Packet {
-            header,
-            extras,
-            key,
-            val: value,
-        })
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        Packet::read_from_with_buf_sync(r, &mut BytesMut::new())
     }
 }
 
+#[cfg(feature = "std")]
 #[async_trait]
 impl AsyncOps for Packet {
     async fn write_to<W: AsyncWrite + Unpin + Send>(&self, w: &mut W) -> io::Result<()> {
-        AsyncOps::write_to(&self.header, w).await?;
-        self.extras.write(w).await?;
-        w.write_all(self.key.bytes()).await?;
-        w.write_all(self.val.bytes()).await?;
+        AsyncOps::write_to(&self.as_ref(), w).await?;
         w.flush().await?;
         Ok(())
     }
 
+    async fn write_vectored_to<W: AsyncWrite + Unpin + Send>(&self, w: &mut W) -> io::Result<()> {
+        AsyncOps::write_vectored_to(&self.as_ref(), w).await?;
+        w.flush().await
+    }
+
     async fn read_from<R: AsyncRead + Unpin + Send>(r: &mut R) -> io::Result<Self> {
-        let header: PacketHeader = AsyncOps::read_from(r).await?;
+        Packet::read_from_with_buf(r, &mut BytesMut::new()).await
+    }
+}
+
+/// Outcome of `Decoder::decode`
+#[derive(Debug, PartialEq)]
+pub enum Decoded {
+    Packet(Packet),
+    /// At least this many more bytes are needed to make progress
+    Incomplete(usize),
+}
+
+/// Push-based decoder keeping partial state across chunks, for use without `Read`/`AsyncRead`
+///
+/// # Examples
+/// ```rust
+/// use memcached_proto::{Decoded, Decoder, Extras, Opcode, Packet};
+/// use bytes::{Bytes, BytesMut};
+///
+/// let p = Packet::request(Opcode::NoOp, 0, 0, 0, Extras::None, Bytes::new(), Bytes::new());
+/// let mut bytes = BytesMut::new();
+/// p.encode_to(&mut bytes);
+///
+/// let mut decoder = Decoder::new();
+/// assert_eq!(decoder.push(&bytes[..10]).unwrap(), Decoded::Incomplete(14));
+/// assert_eq!(decoder.push(&bytes[10..]).unwrap(), Decoded::Packet(p));
+/// ```
+#[derive(Debug)]
+pub struct Decoder {
+    buf: BytesMut,
+    header: Option<PacketHeader>,
+    max_body_len: u32,
+}
+
+impl Decoder {
+    /// Decoder limited to `Packet::default_max_body_len`
+    pub fn new() -> Self {
+        Self::with_max_body_len(Packet::default_max_body_len())
+    }
+
+    /// Decoder rejecting headers announcing more than `max_body_len` bytes with a `BodyTooLarge` error
+    pub fn with_max_body_len(max_body_len: u32) -> Self {
+        Self {
+            buf: BytesMut::new(),
+            header: None,
+            max_body_len,
+        }
+    }
+
+    /// Append a chunk without decoding
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
 
+    /// Append a chunk and decode the next packet;
+    /// a chunk may hold several packets, call `decode` until `Incomplete` to drain them.
+    pub fn push(&mut self, chunk: &[u8]) -> io::Result<Decoded> {
+        self.feed(chunk);
+        self.decode()
+    }
+
+    /// Decode the next packet out of the buffered bytes;
+    /// a header that can't be parsed fails with a `CorruptHeader` error and stays buffered,
+    /// as does one rejected with `BodyTooLarge`, call `resync` to skip past it.
+    pub fn decode(&mut self) -> io::Result<Decoded> {
+        let header = match self.header.take() {
+            Some(header) => header,
+            None => {
+                if self.buf.len() < PacketHeader::size() {
+                    return Ok(Decoded::Incomplete(PacketHeader::size() - self.buf.len()));
+                }
+                let raw = &self.buf[..PacketHeader::size()];
+                let header = PacketHeader::parse(raw).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        CorruptHeader {
+                            header: Bytes::copy_from_slice(raw),
+                        },
+                    )
+                })?;
+                header.check_body_len(self.max_body_len)?;
+                self.buf.advance(PacketHeader::size());
+                header
+            }
+        };
         let body_len = header.body_len as usize;
-        let mut buf = BytesMut::with_capacity(body_len);
-        unsafe {
-            buf.set_len(body_len);
+        if self.buf.len() < body_len {
+            let needed = body_len - self.buf.len();
+            self.header = Some(header);
+            return Ok(Decoded::Incomplete(needed));
         }
-        r.read_exact(buf.as_mut()).await?;
+        let body = self.buf.split_to(body_len);
+        Packet::parse_body(header, body).map(Decoded::Packet)
+    }
 
-        let extras = Extras::parse(
-            header.opcode,
-            buf.split_to(header.extras_len as usize).bytes(),
-        )?;
-        let key = buf.split_to(header.key_len as usize).freeze();
-        let value = buf.freeze();
+    /// Drop buffered bytes up to the next offset that starts a plausible header
+    /// (known magic, parsable, lengths fitting within the limit), after `decode` failed;
+    /// stops early at a known magic too close to the end to tell yet.
+    /// Returns the number of bytes dropped.
+    pub fn resync(&mut self) -> usize {
+        self.header = None;
+        // the first byte started the rejected header
+        let mut skip = 1;
+        while skip < self.buf.len() {
+            let rest = &self.buf[skip..];
+            if Magic::from_u8(rest[0]).is_some() {
+                if rest.len() < PacketHeader::size() {
+                    break;
+                }
+                if let Ok(header) = PacketHeader::parse(&rest[..PacketHeader::size()]) {
+                    let fixed_len = header.framing_extras_len as u32
+                        + header.extras_len as u32
+                        + header.key_len as u32;
+                    if header.body_len <= self.max_body_len && fixed_len <= header.body_len {
+                        break;
+                    }
+                }
+            }
+            skip += 1;
+        }
+        let skip = skip.min(self.buf.len());
+        self.buf.advance(skip);
+        skip
+    }
 
-        Ok(Packet {
-            header,
-            extras,
-            key,
-            val: value,
-        })
+    /// Number of bytes buffered but not decoded yet
+    pub fn buffered(&self) -> usize {
+        self.buf.len() + self.header.as_ref().map_or(0, |_| PacketHeader::size())
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[derive(Debug)]
 pub struct PacketRef<'a> {
     pub header: &'a PacketHeader,
+    pub framing_extras: &'a [FrameInfo],
     pub extras: &'a Extras,
     pub key: &'a [u8],
     pub val: &'a [u8],
@@ -525,17 +1577,103 @@ impl<'a> PacketRef<'a> {
     ) -> PacketRef<'a> {
         PacketRef {
             header,
+            framing_extras: &[],
             extras,
             key,
             val,
         }
     }
+
+    /// Copy into an owned `Packet`; the extras are decoded back from their encoding,
+    /// as `Extras::Unknown` if that fails, so they are sent unchanged
+    pub fn to_packet(&self) -> Packet {
+        let mut extras = Vec::with_capacity(self.extras.len());
+        self.extras.encode_to(&mut extras);
+        let extras = Extras::parse_for(self.header.magic, self.header.opcode, &extras)
+            .unwrap_or_else(|_| Extras::Unknown(extras.into()));
+        Packet {
+            header: self.header.clone(),
+            framing_extras: self.framing_extras.to_vec(),
+            extras,
+            key: Bytes::copy_from_slice(self.key),
+            val: Bytes::copy_from_slice(self.val),
+        }
+    }
+
+    /// Encode into `buf` without any intermediate allocation
+    pub fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        #[cfg(feature = "trace-frames")]
+        crate::trace::frame("encode", self);
+        self.header.encode_to(buf);
+        for info in self.framing_extras {
+            info.put(buf);
+        }
+        self.extras.encode_to(buf);
+        buf.put_slice(self.key);
+        buf.put_slice(self.val);
+    }
+
+    /// Exact number of bytes `encode_to` writes: header, framing extras, extras, key and value
+    pub fn encoded_len(&self) -> usize {
+        PacketHeader::size()
+            + FrameInfo::total_len(self.framing_extras)
+            + self.extras.len()
+            + self.key.len()
+            + self.val.len()
+    }
+
+    /// Encode everything but the key and value on the stack
+    /// # Panics
+    /// if the framing extras or the extras are longer than their header length fields allow
+    pub fn encode_prefix(&self) -> EncodedPrefix {
+        #[cfg(feature = "trace-frames")]
+        crate::trace::frame("encode", self);
+        let mut buf = [0u8; MAX_PREFIX_LEN];
+        let mut rest = &mut buf[..];
+        self.header.encode_to(&mut rest);
+        for info in self.framing_extras {
+            info.put(&mut rest);
+        }
+        self.extras.encode_to(&mut rest);
+        let len = MAX_PREFIX_LEN - rest.len();
+        EncodedPrefix { buf, len }
+    }
+
+    #[cfg(feature = "std")]
+    /// Write asynchronously without flush, the value being `val_len` bytes copied from `val`
+    /// instead of `self.val`, which must be empty; `header.body_len` has to count the `val_len` bytes.
+    /// Fails with `UnexpectedEof` if `val` ends before `val_len` bytes, leaving a truncated packet behind.
+    pub async fn write_streaming<W, R>(&self, w: &mut W, val: R, val_len: u32) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+        R: AsyncRead + Unpin,
+    {
+        debug_assert!(self.val.is_empty());
+        w.write_all(self.encode_prefix().as_slice()).await?;
+        w.write_all(self.key).await?;
+        let val_len = val_len as u64;
+        if futures_lite::io::copy(val.take(val_len), &mut *w).await? != val_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    /// The packet as `[prefix, key, value]`, `prefix` comes from `encode_prefix`
+    pub fn io_slices<'b>(&'b self, prefix: &'b EncodedPrefix) -> [IoSlice<'b>; 3] {
+        [
+            IoSlice::new(prefix.as_slice()),
+            IoSlice::new(self.key),
+            IoSlice::new(self.val),
+        ]
+    }
 }
 
+#[cfg(feature = "std")]
 impl<'a> SyncOps for PacketRef<'a> {
     fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        SyncOps::write_to(self.header, w)?;
-        self.extras.write_sync(w)?;
+        // header and extras in a single write
+        w.write_all(self.encode_prefix().as_slice())?;
         w.write_all(self.key)?;
         w.write_all(self.val)?;
 
@@ -545,13 +1683,28 @@ impl<'a> SyncOps for PacketRef<'a> {
     fn read_from<R: Read>(_r: &mut R) -> io::Result<Self> {
         unimplemented!()
     }
+    fn write_vectored_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let prefix = self.encode_prefix();
+        let mut slices = self.io_slices(&prefix);
+        let mut slices = &mut slices[..];
+        while !slices.is_empty() {
+            match w.write_vectored(slices) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => IoSlice::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
 }
 
+#[cfg(feature = "std")]
 #[async_trait]
 impl<'a> AsyncOps for PacketRef<'a> {
     async fn write_to<W: AsyncWrite + Unpin + Send>(&self, w: &mut W) -> io::Result<()> {
-        AsyncOps::write_to(self.header, w).await?;
-        self.extras.write(w).await?;
+        // header and extras in a single write
+        w.write_all(self.encode_prefix().as_slice()).await?;
         w.write_all(self.key).await?;
         w.write_all(self.val).await?;
 
@@ -561,84 +1714,602 @@ impl<'a> AsyncOps for PacketRef<'a> {
     async fn read_from<R: AsyncRead + Unpin + Send>(_r: &mut R) -> io::Result<Self> {
         unimplemented!()
     }
+    async fn write_vectored_to<W: AsyncWrite + Unpin + Send>(&self, w: &mut W) -> io::Result<()> {
+        let prefix = self.encode_prefix();
+        let mut slices = self.io_slices(&prefix);
+        let mut slices = &mut slices[..];
+        while !slices.is_empty() {
+            match w.write_vectored(slices).await {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => IoSlice::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use super::{Extras, Opcode, Packet, Status, SyncOps};
+    use super::{
+        BodyTooLarge, CorruptHeader, Decoded, Decoder, Extras, InvalidPacket, Opcode, Packet,
+        SyncOps,
+    };
+    use crate::{DataType, FrameInfo, Magic, Status, VBucketState};
+    use std::io;
+
+    use bytes::{Bytes, BytesMut};
 
-    use std::io::Write;
-    use std::net::TcpStream;
+    #[test]
+    fn test_framing_extras_round_trip() {
+        let req_packet = Packet::request(
+            Opcode::Set,
+            0,
+            0x2a,
+            0,
+            Extras::Store {
+                flags: 0,
+                expiration: 0,
+            },
+            b"key".as_ref().into(),
+            b"value".as_ref().into(),
+        )
+        .with_framing_extras(vec![
+            FrameInfo::Barrier,
+            FrameInfo::Durability {
+                level: 1,
+                timeout: Some(500),
+            },
+            FrameInfo::Impersonate(b"a-rather-long-user-name".as_ref().into()),
+        ])
+        .unwrap();
+        assert_eq!(req_packet.header.magic, Magic::AltRequest);
 
-    use bytes::{Buf, Bytes};
+        let mut buf = Vec::new();
+        req_packet.write_to(&mut buf).unwrap();
+        assert_eq!(buf[2] as usize, 1 + 4 + 25);
+        assert_eq!(buf[3], 3);
+        assert_eq!(buf.len(), req_packet.encoded_len());
 
-    fn test_stream() -> TcpStream {
-        TcpStream::connect("127.0.0.1:11211").unwrap()
+        let decoded = Packet::read_from(&mut buf.as_slice()).unwrap();
+        assert_eq!(decoded, req_packet);
     }
 
     #[test]
-    fn test_binary_protocol() {
-        let mut stream = test_stream();
+    fn test_framing_extras_too_long() {
+        let packet =
+            |key: Bytes| Packet::request(Opcode::Get, 0, 0, 0, Extras::None, key, Bytes::new());
+        let err = packet(vec![b'k'; 256].into())
+            .with_framing_extras(vec![FrameInfo::Barrier])
+            .unwrap_err();
+        assert_eq!(err, InvalidPacket::AltKeyTooLong(256));
 
-        {
-            let req_packet = Packet::request(
-                Opcode::Set,
-                0,
-                0,
-                0,
-                Extras::Store {
-                    flags: 0,
-                    expiration: 0,
-                },
-                b"test:binary_proto:hello".as_ref().into(),
-                b"world".as_ref().into(),
-            );
+        let user = Bytes::from(vec![b'u'; 300]);
+        let err = packet(b"k".as_ref().into())
+            .with_framing_extras(vec![FrameInfo::Impersonate(user)])
+            .unwrap_err();
+        assert!(matches!(err, InvalidPacket::FramingExtrasTooLong(_)));
 
-            req_packet.write_to(&mut stream).unwrap();
-            stream.flush().unwrap();
+        let err = Packet::builder(Opcode::Get)
+            .key(vec![b'k'; 256])
+            .framing_extras(vec![FrameInfo::Barrier])
+            .request()
+            .unwrap_err();
+        assert_eq!(err, InvalidPacket::AltKeyTooLong(256));
 
-            let resp_packet = Packet::read_from(&mut stream).unwrap();
+        let mut packet = packet(vec![b'k'; 256].into());
+        packet.header.magic = Magic::AltRequest;
+        assert_eq!(packet.validate(), Err(InvalidPacket::AltKeyTooLong(256)));
+    }
 
-            assert_eq!(resp_packet.status(), Status::NoError);
-        }
+    #[test]
+    fn test_encode_decode_buf() {
+        let packet = Packet::request(
+            Opcode::Increment,
+            0,
+            1,
+            0,
+            Extras::Counter {
+                amount: 1,
+                initial: 0,
+                expiration: 0,
+            },
+            b"counter".as_ref().into(),
+            Bytes::new(),
+        );
+        let mut buf = BytesMut::new();
+        packet.encode_to(&mut buf);
+        assert_eq!(buf.len(), packet.encoded_len());
+        packet.encode_to(&mut buf);
 
-        {
-            let req_packet = Packet::request(
-                Opcode::Get,
-                0,
-                0,
-                0,
-                Extras::None,
-                b"test:binary_proto:hello".as_ref().into(),
-                Bytes::new(),
+        let mut written = Vec::new();
+        packet.write_to(&mut written).unwrap();
+        assert_eq!(&buf[..written.len()], &written[..]);
+
+        let mut buf = buf.freeze();
+        assert_eq!(Packet::decode_from(&mut buf).unwrap(), packet);
+        assert_eq!(Packet::decode_from(&mut buf).unwrap(), packet);
+        assert!(Packet::decode_from(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_streaming() {
+        let packet = Packet::request(
+            Opcode::Set,
+            0,
+            1,
+            0,
+            Extras::Store {
+                flags: 0,
+                expiration: 0,
+            },
+            Bytes::from_static(b"key"),
+            Bytes::from(vec![7u8; 10_000]),
+        );
+        let mut expected = Vec::new();
+        SyncOps::write_to(&packet, &mut expected).unwrap();
+
+        futures_lite::future::block_on(async {
+            let prefix = crate::PacketRef {
+                header: &packet.header,
+                framing_extras: &[],
+                extras: &packet.extras,
+                key: &packet.key,
+                val: &[],
+            };
+            let mut written = Vec::new();
+            prefix
+                .write_streaming(&mut written, &packet.val[..], packet.val.len() as u32)
+                .await
+                .unwrap();
+            assert_eq!(written, expected);
+            let mut short = Vec::new();
+            assert!(prefix
+                .write_streaming(&mut short, &[0u8; 3][..], 4)
+                .await
+                .is_err());
+
+            let mut val = Vec::new();
+            let read = Packet::read_streaming(&mut &written[..], &mut val)
+                .await
+                .unwrap();
+            assert_eq!(val, packet.val);
+            assert!(read.val.is_empty());
+            assert_eq!(read.key, packet.key);
+            assert_eq!(read.header, packet.header);
+            assert!(
+                Packet::read_streaming(&mut &written[..100], &mut Vec::new())
+                    .await
+                    .is_err()
             );
+        });
+    }
+    #[test]
+    fn test_max_body_len() {
+        let packet = Packet::request(
+            Opcode::Set,
+            0,
+            1,
+            0,
+            Extras::None,
+            Bytes::from_static(b"key"),
+            Bytes::from_static(b"value"),
+        );
+        let mut written = Vec::new();
+        packet.write_to(&mut written).unwrap();
 
-            req_packet.write_to(&mut stream).unwrap();
-            stream.flush().unwrap();
+        let err = Packet::read_from_limited_sync(&mut &written[..], 7).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.into_inner().unwrap().is::<BodyTooLarge>());
+        assert_eq!(
+            Packet::read_from_limited_sync(&mut &written[..], 8).unwrap(),
+            packet
+        );
 
-            let resp_packet = Packet::read_from(&mut stream).unwrap();
+        let mut decoder = Decoder::with_max_body_len(7);
+        decoder.feed(&written);
+        assert!(decoder.decode().is_err());
+    }
+    #[test]
+    fn test_display() {
+        let mut packet = Packet::request(
+            Opcode::Set,
+            0,
+            0x2a,
+            0,
+            Extras::Store {
+                flags: 7,
+                expiration: 60,
+            },
+            Bytes::from_static(b"key"),
+            Bytes::from_static(b"value"),
+        );
+        assert_eq!(
+            packet.to_string(),
+            "op=Set klen=3 elen=8 vlen=5 vbucket=0 opaque=0x2a cas=0x0 flags=0x7 exp=60"
+        );
+        packet.header.magic = Magic::Response;
+        packet.header.vbucket_id_or_status = Status::KeyNotFound.into();
+        packet.extras = Extras::None;
+        assert_eq!(
+            packet.header.to_string(),
+            "op=Set klen=3 elen=8 vlen=5 status=0x0001 opaque=0x2a cas=0x0"
+        );
+    }
+    #[test]
+    fn test_unknown_opcode_round_trip() {
+        let packet = Packet::request(
+            Opcode::from(0xfe),
+            0,
+            1,
+            0,
+            Extras::Unknown(Bytes::from_static(b"xtra")),
+            Bytes::from_static(b"key"),
+            Bytes::from_static(b"value"),
+        );
+        assert_eq!(packet.header.opcode, Opcode::Other(0xfe));
+        let mut written = Vec::new();
+        packet.write_to(&mut written).unwrap();
+        assert_eq!(written[1], 0xfe);
+        assert_eq!(Packet::read_from(&mut &written[..]).unwrap(), packet);
+        assert_eq!(Opcode::from(0x01), Opcode::Set);
+    }
+    #[test]
+    fn test_unknown_status() {
+        let mut packet = Packet::response(
+            Opcode::Get,
+            Status::NoError,
+            1,
+            0,
+            Extras::None,
+            Bytes::new(),
+            Bytes::new(),
+        );
+        packet.header.vbucket_id_or_status = 0x00ff;
+        assert_eq!(packet.status(), Status::Unknown(0x00ff));
+        assert_eq!(u16::from(packet.status()), 0x00ff);
+        assert_eq!(Status::from(0x0001), Status::KeyNotFound);
+        assert_eq!(packet.validate(), Err(InvalidPacket::UnknownStatus(0x00ff)));
+    }
+    #[test]
+    fn test_data_type_round_trip() {
+        let packet = Packet::request(
+            Opcode::Set,
+            0,
+            1,
+            0,
+            Extras::None,
+            Bytes::from_static(b"key"),
+            Bytes::from_static(b"{}"),
+        )
+        .with_data_type(DataType::JSON | DataType::SNAPPY);
+        let mut written = Vec::new();
+        packet.write_to(&mut written).unwrap();
+        assert_eq!(written[5], 0x03);
+        let read = Packet::read_from(&mut &written[..]).unwrap();
+        assert!(read.data_type().is_json() && read.data_type().is_snappy());
+        assert!(!read.data_type().contains(DataType::XATTR));
+    }
+    #[test]
+    fn test_tap_extras_round_trip() {
+        let extras = Extras::TapMutation {
+            engine_private_len: 0,
+            flags: 0x0100,
+            ttl: 0xff,
+            item_flags: 7,
+            item_expiration: 60,
+        };
+        let mut buf = Vec::new();
+        extras.write_sync(&mut buf).unwrap();
+        assert_eq!(buf.len(), 16);
+        assert_eq!(&buf[5..8], &[0; 3]);
+        assert_eq!(Extras::parse(Opcode::TapMutation, &buf).unwrap(), extras);
+        assert!(matches!(
+            Extras::parse(Opcode::TapDelete, &buf[..8]).unwrap(),
+            Extras::Tap { flags: 0x0100, .. }
+        ));
+        assert_eq!(
+            Extras::parse(Opcode::SetVBucket, &[0, 0, 0, 2]).unwrap(),
+            Extras::VBucketState { state: 2 }
+        );
+        assert_eq!(VBucketState::from(2), VBucketState::Replica);
+        assert_eq!(u32::from(VBucketState::Other(9)), 9);
 
-            assert_eq!(resp_packet.status(), Status::NoError);
-            assert_eq!(&resp_packet.val.bytes(), b"world");
+        let extras = Extras::Range {
+            start_key_len: 3,
+            flags: 1,
+            max_results: 100,
+        };
+        let mut buf = Vec::new();
+        extras.write_sync(&mut buf).unwrap();
+        assert_eq!(buf, [0, 3, 0, 1, 0, 0, 0, 100]);
+        assert_eq!(Extras::parse(Opcode::RGet, &buf).unwrap(), extras);
+    }
+    #[test]
+    fn test_flush_extras() {
+        let buf = [0, 0, 0, 5];
+        for &opcode in &[Opcode::Flush, Opcode::FlushQ] {
+            assert_eq!(
+                Extras::parse(opcode, &buf).unwrap(),
+                Extras::Flush { expiration: 5 }
+            );
         }
+    }
+    #[test]
+    fn test_extras_direction() {
+        let buf = [0, 0, 0, 60];
+        assert_eq!(
+            Extras::parse_for(Magic::Request, Opcode::GAT, &buf).unwrap(),
+            Extras::Touch { expiration: 60 }
+        );
+        assert_eq!(
+            Extras::parse_for(Magic::Response, Opcode::GAT, &buf).unwrap(),
+            Extras::Get { flags: 60 }
+        );
+        assert_eq!(
+            Extras::parse_for(Magic::Request, Opcode::GATKQ, &buf).unwrap(),
+            Extras::Touch { expiration: 60 }
+        );
+        assert_eq!(
+            Extras::parse_for(Magic::Response, Opcode::GATK, &buf).unwrap(),
+            Extras::Get { flags: 60 }
+        );
+        assert_eq!(
+            Extras::parse_for(Magic::AltResponse, Opcode::Touch, &[]).unwrap(),
+            Extras::None
+        );
+    }
+    #[test]
+    fn test_decoder_resync() {
+        let packet = Packet::request(
+            Opcode::Get,
+            0,
+            1,
+            0,
+            Extras::None,
+            Bytes::from_static(b"key"),
+            Bytes::new(),
+        );
+        let mut written = Vec::new();
+        packet.write_to(&mut written).unwrap();
 
-        {
-            let req_packet = Packet::request(
-                Opcode::Delete,
-                0,
-                0,
-                0,
-                Extras::None,
-                b"test:binary_proto:hello".as_ref().into(),
-                Bytes::new(),
-            );
+        let mut decoder = Decoder::new();
+        decoder.feed(&[0xde; 30]);
+        decoder.feed(&written);
+        let err = decoder.decode().unwrap_err();
+        let corrupt = err.into_inner().unwrap();
+        assert_eq!(
+            corrupt.downcast_ref::<CorruptHeader>().unwrap().header,
+            &[0xde; 24][..]
+        );
+        assert_eq!(decoder.resync(), 30);
+        assert_eq!(decoder.decode().unwrap(), Decoded::Packet(packet));
+    }
+    #[test]
+    fn test_validate() {
+        let mut packet = Packet::request(
+            Opcode::Add,
+            0,
+            1,
+            0,
+            Extras::Store {
+                flags: 0,
+                expiration: 0,
+            },
+            Bytes::from_static(b"key"),
+            Bytes::from_static(b"value"),
+        );
+        assert_eq!(packet.validate(), Ok(()));
+        packet.header.cas = 1;
+        assert_eq!(
+            packet.validate(),
+            Err(InvalidPacket::NonZeroCas(Opcode::Add))
+        );
+        packet.header.cas = 0;
+        packet.extras = Extras::None;
+        assert!(matches!(
+            packet.validate(),
+            Err(InvalidPacket::BodyLength { .. })
+        ));
+        packet.header.extras_len = 0;
+        packet.header.body_len -= 8;
+        assert_eq!(
+            packet.validate(),
+            Err(InvalidPacket::ExtrasLength {
+                opcode: Opcode::Add,
+                expected: 8,
+                actual: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_status() {
+        let mut packet = Packet::response(
+            Opcode::Get,
+            Status::KeyNotFound,
+            1,
+            0,
+            Extras::Get { flags: 0 },
+            Bytes::new(),
+            Bytes::from_static(b"Not found"),
+        );
+        assert_eq!(
+            packet.validate(),
+            Err(InvalidPacket::ErrorWithExtras(Status::KeyNotFound))
+        );
+        packet.header.vbucket_id_or_status = Status::NoError.into();
+        assert_eq!(packet.validate(), Ok(()));
+    }
+    #[test]
+    fn test_validate_vbucket() {
+        let packet = Packet::request(
+            Opcode::Get,
+            u16::MAX,
+            1,
+            0,
+            Extras::None,
+            Bytes::from_static(b"key"),
+            Bytes::new(),
+        );
+        assert_eq!(packet.validate(), Ok(()));
+    }
+    #[test]
+    fn test_response_for() {
+        let req = Packet::request(
+            Opcode::Set,
+            0,
+            9,
+            0x1234,
+            Extras::Store {
+                flags: 1,
+                expiration: 0,
+            },
+            Bytes::from_static(b"key"),
+            Bytes::from_static(b"value"),
+        );
+        let store = Extras::Store {
+            flags: 1,
+            expiration: 0,
+        };
+        let resp = Packet::success_for(&req, store, Bytes::new(), Bytes::new());
+        assert_eq!(resp.header.magic, Magic::Response);
+        assert_eq!(resp.header.opcode, Opcode::Set);
+        assert_eq!(resp.header.opaque, 9);
+        assert_eq!(resp.header.cas, 0x1234);
+        assert_eq!(resp.extras, Extras::None);
+        assert_eq!(resp.validate(), Ok(()));
+
+        let get = Packet::request(
+            Opcode::GetK,
+            0,
+            9,
+            0,
+            Extras::None,
+            Bytes::from_static(b"key"),
+            Bytes::new(),
+        );
+        let resp = Packet::success_for(
+            &get,
+            Extras::Get { flags: 1 },
+            req.key.clone(),
+            req.val.clone(),
+        );
+        assert_eq!(resp.extras, Extras::Get { flags: 1 });
+        assert_eq!(resp.header.body_len, 4 + 3 + 5);
+
+        let resp = Packet::error_for(&req, Status::KeyExits, "Data exists for key.");
+        assert_eq!(resp.status(), Status::KeyExits);
+        assert_eq!(resp.header.cas, 0);
+        assert_eq!(&resp.val[..], b"Data exists for key.");
+        assert_eq!(resp.validate(), Ok(()));
+    }
 
-            req_packet.write_to(&mut stream).unwrap();
-            stream.flush().unwrap();
+    /// Accepts at most 5 bytes per write, spread over the slices
+    #[derive(Default)]
+    struct Trickle {
+        written: Vec<u8>,
+        writes: usize,
+    }
 
-            let resp_packet = Packet::read_from(&mut stream).unwrap();
+    impl Trickle {
+        fn take(&mut self, bufs: &[io::IoSlice<'_>]) -> usize {
+            self.writes += 1;
+            let mut n = 0;
+            for buf in bufs {
+                let len = buf.len().min(5 - n);
+                self.written.extend_from_slice(&buf[..len]);
+                n += len;
+                if n == 5 {
+                    break;
+                }
+            }
+            n
+        }
+    }
 
-            assert_eq!(resp_packet.status(), Status::NoError);
+    impl io::Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(self.take(&[io::IoSlice::new(buf)]))
+        }
+        fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+            Ok(self.take(bufs))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
         }
     }
+
+    impl futures_lite::AsyncWrite for Trickle {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<io::Result<usize>> {
+            std::task::Poll::Ready(Ok(self.get_mut().take(&[io::IoSlice::new(buf)])))
+        }
+        fn poll_write_vectored(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            bufs: &[io::IoSlice<'_>],
+        ) -> std::task::Poll<io::Result<usize>> {
+            std::task::Poll::Ready(Ok(self.get_mut().take(bufs)))
+        }
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+        fn poll_close(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn test_write_vectored_short_writes() {
+        let packet = Packet::request(
+            Opcode::Set,
+            0,
+            1,
+            0,
+            Extras::Store {
+                flags: 0,
+                expiration: 0,
+            },
+            Bytes::from_static(b"key"),
+            Bytes::from_static(b"a value longer than one write"),
+        );
+        let mut expected = Vec::new();
+        SyncOps::write_to(&packet, &mut expected).unwrap();
+
+        let mut w = Trickle::default();
+        SyncOps::write_vectored_to(&packet, &mut w).unwrap();
+        assert_eq!(w.written, expected);
+        assert_eq!(w.writes, expected.len().div_ceil(5));
+
+        let mut w = Trickle::default();
+        futures_lite::future::block_on(crate::AsyncOps::write_vectored_to(&packet, &mut w))
+            .unwrap();
+        assert_eq!(w.written, expected);
+
+        // empty key and value end the write with the prefix
+        let noop = Packet::request(
+            Opcode::NoOp,
+            0,
+            0,
+            0,
+            Extras::None,
+            Bytes::new(),
+            Bytes::new(),
+        );
+        let mut w = Trickle::default();
+        SyncOps::write_vectored_to(&noop, &mut w).unwrap();
+        assert_eq!(w.written.len(), 24);
+        assert_eq!(w.writes, 5);
+    }
 }
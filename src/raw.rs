@@ -0,0 +1,112 @@
+//! Header as laid out on the wire, for reinterpreting received bytes in place
+use crate::code::Magic;
+use crate::io;
+use crate::packet::PacketHeader;
+use core::convert::TryFrom;
+
+/// The 24 header bytes viewed as fields, all big endian;
+/// `PacketHeader::try_from` gives the typed header.
+///
+/// # Examples
+/// ```rust
+/// use memcached_proto::{Extras, Opcode, PacketHeader, RawHeader};
+/// use std::convert::TryFrom;
+///
+/// let header = PacketHeader::request_from_payload(Opcode::Get, 0, 42, 0, &Extras::None, b"key", b"");
+/// let bytes = header.to_bytes();
+/// let raw = RawHeader::from_bytes(&bytes);
+/// assert_eq!(raw.opaque(), 42);
+/// assert_eq!(PacketHeader::try_from(raw).unwrap(), header);
+/// ```
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawHeader {
+    pub magic: u8,
+    pub opcode: u8,
+    /// framing extras length and key length with the alternative magics
+    pub key_len: [u8; 2],
+    pub extras_len: u8,
+    pub data_type: u8,
+    pub vbucket_id_or_status: [u8; 2],
+    pub body_len: [u8; 4],
+    pub opaque: [u8; 4],
+    pub cas: [u8; 8],
+}
+
+// Only byte fields, so no padding and an alignment of 1
+const _: () = assert!(core::mem::size_of::<RawHeader>() == 24);
+const _: () = assert!(core::mem::align_of::<RawHeader>() == 1);
+
+impl RawHeader {
+    #[inline]
+    pub fn from_bytes(buf: &[u8; 24]) -> &Self {
+        // SAFETY: `RawHeader` is `repr(C)`, made of byte fields only, 24 bytes long
+        // and 1-aligned, so every 24-byte array is a valid `RawHeader`.
+        unsafe { &*(buf as *const [u8; 24] as *const Self) }
+    }
+
+    /// View the first 24 bytes of `buf`, `None` if it's shorter
+    #[inline]
+    pub fn from_slice(buf: &[u8]) -> Option<&Self> {
+        let buf = buf.get(..PacketHeader::size())?;
+        <&[u8; 24]>::try_from(buf).ok().map(Self::from_bytes)
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8; 24] {
+        // SAFETY: see `from_bytes`
+        unsafe { &*(self as *const Self as *const [u8; 24]) }
+    }
+
+    #[inline]
+    fn is_alt(&self) -> bool {
+        self.magic == Magic::AltRequest as u8 || self.magic == Magic::AltResponse as u8
+    }
+
+    #[inline]
+    pub fn framing_extras_len(&self) -> u8 {
+        if self.is_alt() {
+            self.key_len[0]
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    pub fn key_len(&self) -> u16 {
+        if self.is_alt() {
+            self.key_len[1] as u16
+        } else {
+            u16::from_be_bytes(self.key_len)
+        }
+    }
+
+    #[inline]
+    pub fn vbucket_id_or_status(&self) -> u16 {
+        u16::from_be_bytes(self.vbucket_id_or_status)
+    }
+
+    #[inline]
+    pub fn body_len(&self) -> u32 {
+        u32::from_be_bytes(self.body_len)
+    }
+
+    #[inline]
+    pub fn opaque(&self) -> u32 {
+        u32::from_be_bytes(self.opaque)
+    }
+
+    #[inline]
+    pub fn cas(&self) -> u64 {
+        u64::from_be_bytes(self.cas)
+    }
+}
+
+impl TryFrom<&RawHeader> for PacketHeader {
+    type Error = io::Error;
+
+    /// Fails with `InvalidData` on an unknown magic
+    fn try_from(raw: &RawHeader) -> io::Result<Self> {
+        PacketHeader::parse(raw.as_bytes())
+    }
+}
@@ -0,0 +1,723 @@
+//! Typed requests, one variant per opcode
+use crate::code::Opcode;
+use crate::io;
+use crate::packet::{Extras, Packet};
+use bytes::Bytes;
+use core::convert::TryFrom;
+
+/// A request whose extras always match its opcode;
+/// opcodes without a variant (range, vbucket and TAP commands) have to be built with `Packet::request`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Request {
+    Get {
+        key: Bytes,
+    },
+    GetQ {
+        key: Bytes,
+    },
+    GetK {
+        key: Bytes,
+    },
+    GetKQ {
+        key: Bytes,
+    },
+    Set {
+        key: Bytes,
+        value: Bytes,
+        flags: u32,
+        expiration: u32,
+        cas: u64,
+    },
+    SetQ {
+        key: Bytes,
+        value: Bytes,
+        flags: u32,
+        expiration: u32,
+        cas: u64,
+    },
+    Add {
+        key: Bytes,
+        value: Bytes,
+        flags: u32,
+        expiration: u32,
+    },
+    AddQ {
+        key: Bytes,
+        value: Bytes,
+        flags: u32,
+        expiration: u32,
+    },
+    Replace {
+        key: Bytes,
+        value: Bytes,
+        flags: u32,
+        expiration: u32,
+        cas: u64,
+    },
+    ReplaceQ {
+        key: Bytes,
+        value: Bytes,
+        flags: u32,
+        expiration: u32,
+        cas: u64,
+    },
+    Delete {
+        key: Bytes,
+        cas: u64,
+    },
+    DeleteQ {
+        key: Bytes,
+        cas: u64,
+    },
+    Increment {
+        key: Bytes,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+        cas: u64,
+    },
+    IncrementQ {
+        key: Bytes,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+        cas: u64,
+    },
+    Decrement {
+        key: Bytes,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+        cas: u64,
+    },
+    DecrementQ {
+        key: Bytes,
+        amount: u64,
+        initial: u64,
+        expiration: u32,
+        cas: u64,
+    },
+    Quit,
+    QuitQ,
+    Flush {
+        expiration: u32,
+    },
+    FlushQ {
+        expiration: u32,
+    },
+    NoOp,
+    Version,
+    Append {
+        key: Bytes,
+        value: Bytes,
+        cas: u64,
+    },
+    AppendQ {
+        key: Bytes,
+        value: Bytes,
+        cas: u64,
+    },
+    Prepend {
+        key: Bytes,
+        value: Bytes,
+        cas: u64,
+    },
+    PrependQ {
+        key: Bytes,
+        value: Bytes,
+        cas: u64,
+    },
+    /// An empty `key` requests all the statistics
+    Stat {
+        key: Bytes,
+    },
+    Verbosity {
+        level: u32,
+    },
+    Touch {
+        key: Bytes,
+        expiration: u32,
+    },
+    GAT {
+        key: Bytes,
+        expiration: u32,
+    },
+    GATQ {
+        key: Bytes,
+        expiration: u32,
+    },
+    GATK {
+        key: Bytes,
+        expiration: u32,
+    },
+    GATKQ {
+        key: Bytes,
+        expiration: u32,
+    },
+    SASLListMechs,
+    SASLAuth {
+        mechanism: Bytes,
+        data: Bytes,
+    },
+    SASLStep {
+        mechanism: Bytes,
+        data: Bytes,
+    },
+}
+
+impl Request {
+    pub fn opcode(&self) -> Opcode {
+        match self {
+            Self::Get { .. } => Opcode::Get,
+            Self::GetQ { .. } => Opcode::GetQ,
+            Self::GetK { .. } => Opcode::GetK,
+            Self::GetKQ { .. } => Opcode::GetKQ,
+            Self::Set { .. } => Opcode::Set,
+            Self::SetQ { .. } => Opcode::SetQ,
+            Self::Add { .. } => Opcode::Add,
+            Self::AddQ { .. } => Opcode::AddQ,
+            Self::Replace { .. } => Opcode::Replace,
+            Self::ReplaceQ { .. } => Opcode::ReplaceQ,
+            Self::Delete { .. } => Opcode::Delete,
+            Self::DeleteQ { .. } => Opcode::DeleteQ,
+            Self::Increment { .. } => Opcode::Increment,
+            Self::IncrementQ { .. } => Opcode::IncrementQ,
+            Self::Decrement { .. } => Opcode::Decrement,
+            Self::DecrementQ { .. } => Opcode::DecrementQ,
+            Self::Quit => Opcode::Quit,
+            Self::QuitQ => Opcode::QuitQ,
+            Self::Flush { .. } => Opcode::Flush,
+            Self::FlushQ { .. } => Opcode::FlushQ,
+            Self::NoOp => Opcode::NoOp,
+            Self::Version => Opcode::Version,
+            Self::Append { .. } => Opcode::Append,
+            Self::AppendQ { .. } => Opcode::AppendQ,
+            Self::Prepend { .. } => Opcode::Prepend,
+            Self::PrependQ { .. } => Opcode::PrependQ,
+            Self::Stat { .. } => Opcode::Stat,
+            Self::Verbosity { .. } => Opcode::Verbosity,
+            Self::Touch { .. } => Opcode::Touch,
+            Self::GAT { .. } => Opcode::GAT,
+            Self::GATQ { .. } => Opcode::GATQ,
+            Self::GATK { .. } => Opcode::GATK,
+            Self::GATKQ { .. } => Opcode::GATKQ,
+            Self::SASLListMechs => Opcode::SASLListMechs,
+            Self::SASLAuth { .. } => Opcode::SASLAuth,
+            Self::SASLStep { .. } => Opcode::SASLStep,
+        }
+    }
+
+    /// Convert into a request `Packet`
+    ///
+    /// # Examples
+    /// ```rust
+    /// use memcached_proto::{Extras, Opcode, Request};
+    ///
+    /// let p = Request::Touch { key: "k".into(), expiration: 60 }.into_packet(0, 1);
+    /// assert_eq!(p.header.opcode, Opcode::Touch);
+    /// assert_eq!(p.extras, Extras::Touch { expiration: 60 });
+    /// assert_eq!(Request::from_packet(p).unwrap(), Request::Touch { key: "k".into(), expiration: 60 });
+    /// ```
+    pub fn into_packet(self, vbucket_id: u16, opaque: u32) -> Packet {
+        let opcode = self.opcode();
+        let (cas, extras, key, val) = match self {
+            Self::Get { key } | Self::GetQ { key } | Self::GetK { key } | Self::GetKQ { key } => {
+                (0, Extras::None, key, Bytes::new())
+            }
+            Self::Set {
+                key,
+                value,
+                flags,
+                expiration,
+                cas,
+            }
+            | Self::SetQ {
+                key,
+                value,
+                flags,
+                expiration,
+                cas,
+            }
+            | Self::Replace {
+                key,
+                value,
+                flags,
+                expiration,
+                cas,
+            }
+            | Self::ReplaceQ {
+                key,
+                value,
+                flags,
+                expiration,
+                cas,
+            } => (cas, Extras::Store { flags, expiration }, key, value),
+            Self::Add {
+                key,
+                value,
+                flags,
+                expiration,
+            }
+            | Self::AddQ {
+                key,
+                value,
+                flags,
+                expiration,
+            } => (0, Extras::Store { flags, expiration }, key, value),
+            Self::Delete { key, cas } | Self::DeleteQ { key, cas } => {
+                (cas, Extras::None, key, Bytes::new())
+            }
+            Self::Increment {
+                key,
+                amount,
+                initial,
+                expiration,
+                cas,
+            }
+            | Self::IncrementQ {
+                key,
+                amount,
+                initial,
+                expiration,
+                cas,
+            }
+            | Self::Decrement {
+                key,
+                amount,
+                initial,
+                expiration,
+                cas,
+            }
+            | Self::DecrementQ {
+                key,
+                amount,
+                initial,
+                expiration,
+                cas,
+            } => (
+                cas,
+                Extras::Counter {
+                    amount,
+                    initial,
+                    expiration,
+                },
+                key,
+                Bytes::new(),
+            ),
+            Self::Quit | Self::QuitQ | Self::NoOp | Self::Version | Self::SASLListMechs => {
+                (0, Extras::None, Bytes::new(), Bytes::new())
+            }
+            Self::Flush { expiration } | Self::FlushQ { expiration } => {
+                (0, Extras::Flush { expiration }, Bytes::new(), Bytes::new())
+            }
+            Self::Append { key, value, cas }
+            | Self::AppendQ { key, value, cas }
+            | Self::Prepend { key, value, cas }
+            | Self::PrependQ { key, value, cas } => (cas, Extras::None, key, value),
+            Self::Stat { key } => (0, Extras::None, key, Bytes::new()),
+            Self::Verbosity { level } => (
+                0,
+                Extras::Verbosity { verbosity: level },
+                Bytes::new(),
+                Bytes::new(),
+            ),
+            Self::Touch { key, expiration }
+            | Self::GAT { key, expiration }
+            | Self::GATQ { key, expiration }
+            | Self::GATK { key, expiration }
+            | Self::GATKQ { key, expiration } => {
+                (0, Extras::Touch { expiration }, key, Bytes::new())
+            }
+            Self::SASLAuth { mechanism, data } | Self::SASLStep { mechanism, data } => {
+                (0, Extras::None, mechanism, data)
+            }
+        };
+        Packet::request(opcode, vbucket_id, opaque, cas, extras, key, val)
+    }
+
+    /// Whether the variant has a `cas` field
+    fn has_cas(&self) -> bool {
+        matches!(
+            self,
+            Self::Set { .. }
+                | Self::SetQ { .. }
+                | Self::Replace { .. }
+                | Self::ReplaceQ { .. }
+                | Self::Delete { .. }
+                | Self::DeleteQ { .. }
+                | Self::Increment { .. }
+                | Self::IncrementQ { .. }
+                | Self::Decrement { .. }
+                | Self::DecrementQ { .. }
+                | Self::Append { .. }
+                | Self::AppendQ { .. }
+                | Self::Prepend { .. }
+                | Self::PrependQ { .. }
+        )
+    }
+
+    /// Convert from a request `Packet`;
+    /// fails with `InvalidData` if the packet isn't a request, its extras don't match the opcode
+    /// or it has a CAS its variant can't hold, and with `InvalidInput` for opcodes without a variant
+    /// and packets with framing extras, which would be lost.
+    pub fn from_packet(packet: Packet) -> io::Result<Self> {
+        if !packet.is_request() {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let Packet {
+            header,
+            framing_extras,
+            extras,
+            key,
+            val: value,
+        } = packet;
+        if !framing_extras.is_empty() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        let cas = header.cas;
+        let request = match (header.opcode, extras) {
+            (Opcode::Get, Extras::None) => Self::Get { key },
+            (Opcode::GetQ, Extras::None) => Self::GetQ { key },
+            (Opcode::GetK, Extras::None) => Self::GetK { key },
+            (Opcode::GetKQ, Extras::None) => Self::GetKQ { key },
+            (Opcode::Set, Extras::Store { flags, expiration }) => Self::Set {
+                key,
+                value,
+                flags,
+                expiration,
+                cas,
+            },
+            (Opcode::SetQ, Extras::Store { flags, expiration }) => Self::SetQ {
+                key,
+                value,
+                flags,
+                expiration,
+                cas,
+            },
+            (Opcode::Add, Extras::Store { flags, expiration }) => Self::Add {
+                key,
+                value,
+                flags,
+                expiration,
+            },
+            (Opcode::AddQ, Extras::Store { flags, expiration }) => Self::AddQ {
+                key,
+                value,
+                flags,
+                expiration,
+            },
+            (Opcode::Replace, Extras::Store { flags, expiration }) => Self::Replace {
+                key,
+                value,
+                flags,
+                expiration,
+                cas,
+            },
+            (Opcode::ReplaceQ, Extras::Store { flags, expiration }) => Self::ReplaceQ {
+                key,
+                value,
+                flags,
+                expiration,
+                cas,
+            },
+            (Opcode::Delete, Extras::None) => Self::Delete { key, cas },
+            (Opcode::DeleteQ, Extras::None) => Self::DeleteQ { key, cas },
+            (
+                opcode,
+                Extras::Counter {
+                    amount,
+                    initial,
+                    expiration,
+                },
+            ) if matches!(
+                opcode,
+                Opcode::Increment | Opcode::IncrementQ | Opcode::Decrement | Opcode::DecrementQ
+            ) =>
+            {
+                match opcode {
+                    Opcode::Increment => Self::Increment {
+                        key,
+                        amount,
+                        initial,
+                        expiration,
+                        cas,
+                    },
+                    Opcode::IncrementQ => Self::IncrementQ {
+                        key,
+                        amount,
+                        initial,
+                        expiration,
+                        cas,
+                    },
+                    Opcode::Decrement => Self::Decrement {
+                        key,
+                        amount,
+                        initial,
+                        expiration,
+                        cas,
+                    },
+                    _ => Self::DecrementQ {
+                        key,
+                        amount,
+                        initial,
+                        expiration,
+                        cas,
+                    },
+                }
+            }
+            (Opcode::Quit, Extras::None) => Self::Quit,
+            (Opcode::QuitQ, Extras::None) => Self::QuitQ,
+            (Opcode::Flush, Extras::None) => Self::Flush { expiration: 0 },
+            (Opcode::Flush, Extras::Flush { expiration }) => Self::Flush { expiration },
+            (Opcode::FlushQ, Extras::None) => Self::FlushQ { expiration: 0 },
+            (Opcode::FlushQ, Extras::Flush { expiration }) => Self::FlushQ { expiration },
+            (Opcode::NoOp, Extras::None) => Self::NoOp,
+            (Opcode::Version, Extras::None) => Self::Version,
+            (Opcode::Append, Extras::None) => Self::Append { key, value, cas },
+            (Opcode::AppendQ, Extras::None) => Self::AppendQ { key, value, cas },
+            (Opcode::Prepend, Extras::None) => Self::Prepend { key, value, cas },
+            (Opcode::PrependQ, Extras::None) => Self::PrependQ { key, value, cas },
+            (Opcode::Stat, Extras::None) => Self::Stat { key },
+            (Opcode::Verbosity, Extras::Verbosity { verbosity }) => {
+                Self::Verbosity { level: verbosity }
+            }
+            (Opcode::Touch, Extras::Touch { expiration }) => Self::Touch { key, expiration },
+            (Opcode::GAT, Extras::Touch { expiration }) => Self::GAT { key, expiration },
+            (Opcode::GATQ, Extras::Touch { expiration }) => Self::GATQ { key, expiration },
+            (Opcode::GATK, Extras::Touch { expiration }) => Self::GATK { key, expiration },
+            (Opcode::GATKQ, Extras::Touch { expiration }) => Self::GATKQ { key, expiration },
+            (Opcode::SASLListMechs, Extras::None) => Self::SASLListMechs,
+            (Opcode::SASLAuth, Extras::None) => Self::SASLAuth {
+                mechanism: key,
+                data: value,
+            },
+            (Opcode::SASLStep, Extras::None) => Self::SASLStep {
+                mechanism: key,
+                data: value,
+            },
+            (
+                Opcode::RGet
+                | Opcode::RSet
+                | Opcode::RSetQ
+                | Opcode::RAppend
+                | Opcode::RAppendQ
+                | Opcode::RPrepend
+                | Opcode::RPrependQ
+                | Opcode::RDelete
+                | Opcode::RDeleteQ
+                | Opcode::RIncr
+                | Opcode::RIncrQ
+                | Opcode::RDecr
+                | Opcode::RDecrQ
+                | Opcode::SetVBucket
+                | Opcode::GetVBucket
+                | Opcode::DelVBucket
+                | Opcode::TapConnect
+                | Opcode::TapMutation
+                | Opcode::TapDelete
+                | Opcode::TapFlush
+                | Opcode::TapOpaque
+                | Opcode::TapVBucketSet
+                | Opcode::TapCheckPointStart
+                | Opcode::TabCheckPointEnd,
+                _,
+            ) => return Err(io::ErrorKind::InvalidInput.into()),
+            _ => return Err(io::ErrorKind::InvalidData.into()),
+        };
+        if cas != 0 && !request.has_cas() {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        Ok(request)
+    }
+}
+
+impl From<Request> for Packet {
+    fn from(request: Request) -> Self {
+        request.into_packet(0, 0)
+    }
+}
+
+impl TryFrom<Packet> for Request {
+    type Error = io::Error;
+
+    fn try_from(packet: Packet) -> io::Result<Self> {
+        Self::from_packet(packet)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{FrameInfo, SyncOps};
+
+    fn all() -> Vec<Request> {
+        let key = || Bytes::from_static(b"key");
+        let value = || Bytes::from_static(b"value");
+        vec![
+            Request::Get { key: key() },
+            Request::GetQ { key: key() },
+            Request::GetK { key: key() },
+            Request::GetKQ { key: key() },
+            Request::Set {
+                key: key(),
+                value: value(),
+                flags: 1,
+                expiration: 2,
+                cas: 3,
+            },
+            Request::SetQ {
+                key: key(),
+                value: value(),
+                flags: 1,
+                expiration: 2,
+                cas: 3,
+            },
+            Request::Add {
+                key: key(),
+                value: value(),
+                flags: 1,
+                expiration: 2,
+            },
+            Request::AddQ {
+                key: key(),
+                value: value(),
+                flags: 1,
+                expiration: 2,
+            },
+            Request::Replace {
+                key: key(),
+                value: value(),
+                flags: 1,
+                expiration: 2,
+                cas: 3,
+            },
+            Request::ReplaceQ {
+                key: key(),
+                value: value(),
+                flags: 1,
+                expiration: 2,
+                cas: 3,
+            },
+            Request::Delete { key: key(), cas: 3 },
+            Request::DeleteQ { key: key(), cas: 3 },
+            Request::Increment {
+                key: key(),
+                amount: 1,
+                initial: 2,
+                expiration: 3,
+                cas: 4,
+            },
+            Request::IncrementQ {
+                key: key(),
+                amount: 1,
+                initial: 2,
+                expiration: 3,
+                cas: 4,
+            },
+            Request::Decrement {
+                key: key(),
+                amount: 1,
+                initial: 2,
+                expiration: 3,
+                cas: 4,
+            },
+            Request::DecrementQ {
+                key: key(),
+                amount: 1,
+                initial: 2,
+                expiration: 3,
+                cas: 4,
+            },
+            Request::Quit,
+            Request::QuitQ,
+            Request::Flush { expiration: 5 },
+            Request::FlushQ { expiration: 5 },
+            Request::NoOp,
+            Request::Version,
+            Request::Append {
+                key: key(),
+                value: value(),
+                cas: 3,
+            },
+            Request::AppendQ {
+                key: key(),
+                value: value(),
+                cas: 3,
+            },
+            Request::Prepend {
+                key: key(),
+                value: value(),
+                cas: 3,
+            },
+            Request::PrependQ {
+                key: key(),
+                value: value(),
+                cas: 3,
+            },
+            Request::Stat { key: Bytes::new() },
+            Request::Verbosity { level: 1 },
+            Request::Touch {
+                key: key(),
+                expiration: 5,
+            },
+            Request::GAT {
+                key: key(),
+                expiration: 5,
+            },
+            Request::GATQ {
+                key: key(),
+                expiration: 5,
+            },
+            Request::GATK {
+                key: key(),
+                expiration: 5,
+            },
+            Request::GATKQ {
+                key: key(),
+                expiration: 5,
+            },
+            Request::SASLListMechs,
+            Request::SASLAuth {
+                mechanism: Bytes::from_static(b"PLAIN"),
+                data: Bytes::from_static(b"\0user\0pass"),
+            },
+            Request::SASLStep {
+                mechanism: Bytes::from_static(b"PLAIN"),
+                data: Bytes::from_static(b"more"),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for request in all() {
+            let packet = request.clone().into_packet(0, 7);
+            assert_eq!(packet.header.opcode, request.opcode());
+            let mut buf = Vec::new();
+            packet.write_to(&mut buf).unwrap();
+            assert_eq!(Request::from_packet(packet).unwrap(), request);
+
+            let decoded = Packet::read_from(&mut buf.as_slice()).unwrap();
+            assert_eq!(Request::from_packet(decoded).unwrap(), request);
+        }
+    }
+
+    #[test]
+    fn test_from_packet_rejects() {
+        let get = || Request::Get { key: "k".into() }.into_packet(0, 0);
+
+        let mut packet = get();
+        packet.header.cas = 1;
+        let err = Request::from_packet(packet).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let packet = get().with_framing_extras(vec![FrameInfo::Barrier]).unwrap();
+        let err = Request::from_packet(packet).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let mut packet = get();
+        packet.extras = Extras::Touch { expiration: 0 };
+        let err = Request::from_packet(packet).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
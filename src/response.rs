@@ -0,0 +1,340 @@
+//! Typed responses
+use crate::code::{Opcode, Status};
+use crate::io;
+use crate::packet::{Extras, Packet};
+use alloc::string::String;
+use alloc::vec::Vec;
+use bytes::Bytes;
+use core::convert::TryFrom;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Response {
+    /// Hit of a get or get-and-touch command; `key` is empty unless the request was GetK/GetKQ/GATK/GATKQ
+    GetHit {
+        key: Bytes,
+        value: Bytes,
+        flags: u32,
+        cas: u64,
+    },
+    /// Key not found by a retrieval command
+    Miss,
+    /// Set/Add/Replace/Append/Prepend (and their quiet variants) succeeded
+    Stored {
+        cas: u64,
+    },
+    /// Delete (or DeleteQ) succeeded
+    Deleted,
+    /// Touch succeeded
+    Touched {
+        cas: u64,
+    },
+    /// New value of an incr/decr
+    CounterValue(u64),
+    Version(String),
+    /// One statistic, an empty `key` ends the list
+    Stat {
+        key: Bytes,
+        value: Bytes,
+    },
+    Mechanisms(Vec<String>),
+    AuthContinue(Bytes),
+    AuthSucceeded,
+    /// Quit/Flush/NoOp/Verbosity succeeded
+    Ok,
+    /// Any other status than `NoError`, the server message (if any) is in `message`
+    Error {
+        status: Status,
+        message: Option<String>,
+    },
+}
+
+impl Response {
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        matches!(self, Self::Error { .. })
+    }
+
+    /// Whether successful responses to `opcode` convert to a `Response`
+    #[cfg(feature = "std")]
+    pub(crate) fn has_typed(opcode: Opcode) -> bool {
+        matches!(
+            opcode,
+            Opcode::Get
+                | Opcode::GetQ
+                | Opcode::GetK
+                | Opcode::GetKQ
+                | Opcode::GAT
+                | Opcode::GATQ
+                | Opcode::GATK
+                | Opcode::GATKQ
+                | Opcode::Set
+                | Opcode::SetQ
+                | Opcode::Add
+                | Opcode::AddQ
+                | Opcode::Replace
+                | Opcode::ReplaceQ
+                | Opcode::Append
+                | Opcode::AppendQ
+                | Opcode::Prepend
+                | Opcode::PrependQ
+                | Opcode::Delete
+                | Opcode::DeleteQ
+                | Opcode::Touch
+                | Opcode::Increment
+                | Opcode::IncrementQ
+                | Opcode::Decrement
+                | Opcode::DecrementQ
+                | Opcode::Version
+                | Opcode::Stat
+                | Opcode::SASLListMechs
+                | Opcode::SASLAuth
+                | Opcode::SASLStep
+                | Opcode::Quit
+                | Opcode::QuitQ
+                | Opcode::Flush
+                | Opcode::FlushQ
+                | Opcode::NoOp
+                | Opcode::Verbosity
+        )
+    }
+
+    /// Convert from a response `Packet`;
+    /// fails with `InvalidData` if the packet isn't a well formed response,
+    /// and with `InvalidInput` for opcodes without a typed response.
+    pub fn from_packet(packet: Packet) -> io::Result<Self> {
+        if !packet.is_response() {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let Packet {
+            header,
+            extras,
+            key,
+            val: value,
+            ..
+        } = packet;
+        let status = Status::from(header.vbucket_id_or_status);
+        let opcode = header.opcode;
+        let is_get = matches!(
+            opcode,
+            Opcode::Get
+                | Opcode::GetQ
+                | Opcode::GetK
+                | Opcode::GetKQ
+                | Opcode::GAT
+                | Opcode::GATQ
+                | Opcode::GATK
+                | Opcode::GATKQ
+        );
+        match status {
+            Status::NoError => {}
+            Status::KeyNotFound if is_get => return Ok(Self::Miss),
+            Status::AuthenticationContinue
+                if matches!(opcode, Opcode::SASLAuth | Opcode::SASLStep) =>
+            {
+                return Ok(Self::AuthContinue(value))
+            }
+            status => {
+                let message = if value.is_empty() {
+                    None
+                } else {
+                    Some(String::from_utf8_lossy(&value).into_owned())
+                };
+                return Ok(Self::Error { status, message });
+            }
+        }
+        let cas = header.cas;
+        let response = match opcode {
+            _ if is_get => {
+                let flags = match extras {
+                    Extras::Get { flags } => flags,
+                    Extras::None => 0,
+                    _ => return Err(io::ErrorKind::InvalidData.into()),
+                };
+                Self::GetHit {
+                    key,
+                    value,
+                    flags,
+                    cas,
+                }
+            }
+            Opcode::Set
+            | Opcode::SetQ
+            | Opcode::Add
+            | Opcode::AddQ
+            | Opcode::Replace
+            | Opcode::ReplaceQ
+            | Opcode::Append
+            | Opcode::AppendQ
+            | Opcode::Prepend
+            | Opcode::PrependQ => Self::Stored { cas },
+            Opcode::Delete | Opcode::DeleteQ => Self::Deleted,
+            Opcode::Touch => Self::Touched { cas },
+            Opcode::Increment | Opcode::IncrementQ | Opcode::Decrement | Opcode::DecrementQ => {
+                if value.len() != 8 {
+                    return Err(io::ErrorKind::InvalidData.into());
+                }
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(&value);
+                Self::CounterValue(u64::from_be_bytes(buf))
+            }
+            Opcode::Version => Self::Version(
+                String::from_utf8(value.to_vec()).map_err(|_| io::ErrorKind::InvalidData)?,
+            ),
+            Opcode::Stat => Self::Stat { key, value },
+            Opcode::SASLListMechs => Self::Mechanisms(
+                String::from_utf8_lossy(&value)
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect(),
+            ),
+            Opcode::SASLAuth | Opcode::SASLStep => Self::AuthSucceeded,
+            Opcode::Quit
+            | Opcode::QuitQ
+            | Opcode::Flush
+            | Opcode::FlushQ
+            | Opcode::NoOp
+            | Opcode::Verbosity => Self::Ok,
+            _ => return Err(io::ErrorKind::InvalidInput.into()),
+        };
+        Ok(response)
+    }
+}
+
+impl TryFrom<Packet> for Response {
+    type Error = io::Error;
+
+    fn try_from(packet: Packet) -> io::Result<Self> {
+        Self::from_packet(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(opcode: Opcode, status: Status, extras: Extras, key: &str, val: &[u8]) -> Packet {
+        Packet::response(
+            opcode,
+            status,
+            0,
+            7,
+            extras,
+            Bytes::copy_from_slice(key.as_bytes()),
+            Bytes::copy_from_slice(val),
+        )
+    }
+
+    #[test]
+    fn test_hit_miss_error() {
+        let hit = response(
+            Opcode::GetK,
+            Status::NoError,
+            Extras::Get { flags: 3 },
+            "k",
+            b"v",
+        );
+        assert_eq!(
+            Response::from_packet(hit).unwrap(),
+            Response::GetHit {
+                key: "k".into(),
+                value: "v".into(),
+                flags: 3,
+                cas: 7,
+            }
+        );
+
+        let miss = response(
+            Opcode::Get,
+            Status::KeyNotFound,
+            Extras::None,
+            "",
+            b"Not found",
+        );
+        assert_eq!(Response::from_packet(miss).unwrap(), Response::Miss);
+
+        // only retrievals answer a miss, a missing key of other commands is an error
+        let err = response(
+            Opcode::Delete,
+            Status::KeyNotFound,
+            Extras::None,
+            "",
+            b"Not found",
+        );
+        let err = Response::from_packet(err).unwrap();
+        assert!(err.is_error());
+        assert_eq!(
+            err,
+            Response::Error {
+                status: Status::KeyNotFound,
+                message: Some("Not found".into()),
+            }
+        );
+
+        let stored = response(Opcode::SetQ, Status::NoError, Extras::None, "", b"");
+        assert_eq!(
+            Response::from_packet(stored).unwrap(),
+            Response::Stored { cas: 7 }
+        );
+    }
+
+    #[test]
+    fn test_counter_and_stat() {
+        let counter = response(
+            Opcode::Increment,
+            Status::NoError,
+            Extras::None,
+            "",
+            &42u64.to_be_bytes(),
+        );
+        assert_eq!(
+            Response::from_packet(counter).unwrap(),
+            Response::CounterValue(42)
+        );
+
+        let stat = response(Opcode::Stat, Status::NoError, Extras::None, "pid", b"1");
+        assert_eq!(
+            Response::from_packet(stat).unwrap(),
+            Response::Stat {
+                key: "pid".into(),
+                value: "1".into(),
+            }
+        );
+        let end = response(Opcode::Stat, Status::NoError, Extras::None, "", b"");
+        assert_eq!(
+            Response::from_packet(end).unwrap(),
+            Response::Stat {
+                key: Bytes::new(),
+                value: Bytes::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        let short_counter = response(Opcode::Decrement, Status::NoError, Extras::None, "", b"42");
+        let err = Response::from_packet(short_counter).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let bad_extras = response(
+            Opcode::Get,
+            Status::NoError,
+            Extras::Touch { expiration: 0 },
+            "",
+            b"v",
+        );
+        let err = Response::from_packet(bad_extras).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let bad_version = response(Opcode::Version, Status::NoError, Extras::None, "", b"\xff");
+        let err = Response::from_packet(bad_version).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let request = Packet::request(Opcode::Get, 0, 0, 0, Extras::None, "k".into(), Bytes::new());
+        let err = Response::from_packet(request).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let no_typed = response(Opcode::RGet, Status::NoError, Extras::None, "", b"");
+        let err = Response::from_packet(no_typed).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,43 @@
+//! `Bytes` as base64 strings, for `#[serde(with = "crate::serde_base64")]`
+use bytes::Bytes;
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer>(bytes: &Bytes, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&base64::encode(bytes))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Bytes, D::Error> {
+    let s = String::deserialize(d)?;
+    base64::decode(&s)
+        .map(Bytes::from)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Extras, Opcode, Packet};
+    use bytes::Bytes;
+
+    #[test]
+    fn test_packet_round_trip() {
+        let packet = Packet::request(
+            Opcode::Other(0xf0),
+            0,
+            1,
+            0,
+            Extras::Unknown(Bytes::from_static(&[0xff, 0x00])),
+            Bytes::from_static(b"key"),
+            Bytes::from_static(b"\x00\x01value"),
+        );
+        let json = serde_json::to_value(&packet).unwrap();
+        assert_eq!(json["key"], "a2V5");
+        assert_eq!(json["val"], "AAF2YWx1ZQ==");
+        assert_eq!(json["extras"]["Unknown"], "/wA=");
+
+        let decoded: Packet = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded, packet);
+
+        let bad = serde_json::json!({ "Unknown": "not base64!" });
+        assert!(serde_json::from_value::<Extras>(bad).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+//! TRACE level logging of every encoded/decoded packet, behind the `trace-frames` feature
+use crate::code::Status;
+use crate::packet::PacketRef;
+use std::fmt::Write;
+
+/// Bodies up to this many bytes are also logged in hex
+const MAX_HEX_BODY: usize = 64;
+
+fn hex(packet: &PacketRef<'_>) -> Option<String> {
+    if packet.header.body_len as usize > MAX_HEX_BODY {
+        return None;
+    }
+    let mut buf = Vec::with_capacity(packet.header.body_len as usize);
+    for info in packet.framing_extras {
+        info.put(&mut buf);
+    }
+    packet.extras.encode_to(&mut buf);
+    buf.extend_from_slice(packet.key);
+    buf.extend_from_slice(packet.val);
+    let mut s = String::with_capacity(buf.len() * 2);
+    for b in buf {
+        let _ = write!(s, "{:02x}", b);
+    }
+    Some(s)
+}
+
+/// Log `packet`, `direction` being "encode" or "decode"
+pub(crate) fn frame(direction: &'static str, packet: &PacketRef<'_>) {
+    if !tracing::enabled!(tracing::Level::TRACE) {
+        return;
+    }
+    let header = packet.header;
+    let status = if header.magic.is_response() {
+        Some(Status::from(header.vbucket_id_or_status))
+    } else {
+        None
+    };
+    tracing::trace!(
+        direction,
+        magic = ?header.magic,
+        opcode = ?header.opcode,
+        opaque = header.opaque,
+        status = ?status,
+        key = %String::from_utf8_lossy(packet.key),
+        extras_len = header.extras_len,
+        key_len = header.key_len,
+        body_len = header.body_len,
+        body = ?hex(packet),
+        "memcached frame"
+    );
+}
@@ -0,0 +1,252 @@
+//! [Memcached UDP frame](https://github.com/memcached/memcached/blob/master/doc/protocol.txt)
+use crate::packet::{Packet, SyncOps};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io;
+
+/// Largest datagram payload memcached sends, frame header excluded.
+pub const MAX_DATAGRAM_PAYLOAD: usize = 1400;
+
+// Byte/     0       |       1       |       2       |       3       |
+//    /              |               |               |               |
+//   |0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|0 1 2 3 4 5 6 7|
+//   +---------------+---------------+---------------+---------------+
+//  0| Request id                    | Sequence number               |
+//   +---------------+---------------+---------------+---------------+
+//  4| Total datagrams               | Reserved (0x0000)             |
+//   +---------------+---------------+---------------+---------------+
+//   Total 8 bytes
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct UdpFrameHeader {
+    pub request_id: u16,
+    /// zero-based index of this datagram
+    pub sequence: u16,
+    pub total: u16,
+}
+
+impl UdpFrameHeader {
+    /// Size of `UdpFrameHeader` in bytes
+    pub const fn size() -> usize {
+        2 + 2 + 2 + 2
+    }
+
+    /// Parse from buffer;
+    /// # Panics
+    /// This function panics if there is not enough remaining data in `buf`.
+    pub fn parse(mut buf: &[u8]) -> Self {
+        let header = Self {
+            request_id: buf.get_u16(),
+            sequence: buf.get_u16(),
+            total: buf.get_u16(),
+        };
+        // reserved
+        buf.get_u16();
+        header
+    }
+
+    pub fn write_to<B: BufMut>(&self, buf: &mut B) {
+        buf.put_u16(self.request_id);
+        buf.put_u16(self.sequence);
+        buf.put_u16(self.total);
+        buf.put_u16(0);
+    }
+}
+
+/// Split `payload` into datagrams of at most `max_payload` bytes each (frame header excluded),
+/// every one prefixed with its `UdpFrameHeader`.
+pub fn fragment(request_id: u16, payload: &[u8], max_payload: usize) -> io::Result<Vec<Bytes>> {
+    if max_payload == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "max_payload must be positive",
+        ));
+    }
+    let total = payload.chunks(max_payload).len().max(1);
+    if total > u16::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "payload needs more than 65535 datagrams",
+        ));
+    }
+    let mut datagrams = Vec::with_capacity(total);
+    for sequence in 0..total {
+        let start = sequence * max_payload;
+        let chunk = &payload[start..payload.len().min(start + max_payload)];
+        let mut buf = BytesMut::with_capacity(UdpFrameHeader::size() + chunk.len());
+        UdpFrameHeader {
+            request_id,
+            sequence: sequence as u16,
+            total: total as u16,
+        }
+        .write_to(&mut buf);
+        buf.put_slice(chunk);
+        datagrams.push(buf.freeze());
+    }
+    Ok(datagrams)
+}
+
+/// Encode `packet` and split it into datagrams of at most `MAX_DATAGRAM_PAYLOAD` bytes.
+pub fn fragment_packet(request_id: u16, packet: &Packet) -> io::Result<Vec<Bytes>> {
+    let mut buf = Vec::new();
+    packet.write_to(&mut buf)?;
+    fragment(request_id, &buf, MAX_DATAGRAM_PAYLOAD)
+}
+
+/// Collects the datagrams of one response, which may arrive out of order.
+#[derive(Debug)]
+pub struct Reassembler {
+    request_id: u16,
+    parts: Vec<Option<Bytes>>,
+    received: usize,
+}
+
+impl Reassembler {
+    pub fn new(request_id: u16) -> Self {
+        Self {
+            request_id,
+            parts: Vec::new(),
+            received: 0,
+        }
+    }
+
+    /// Feed one datagram, frame header included;
+    /// returns the whole payload once every datagram of the response has been received.
+    /// Datagrams of other requests and duplicates are ignored.
+    pub fn push(&mut self, datagram: &[u8]) -> io::Result<Option<Bytes>> {
+        if datagram.len() < UdpFrameHeader::size() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let header = UdpFrameHeader::parse(datagram);
+        if header.request_id != self.request_id {
+            return Ok(None);
+        }
+        if header.total == 0 || header.sequence >= header.total {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        if self.parts.is_empty() {
+            self.parts.resize(header.total as usize, None);
+        } else if self.parts.len() != header.total as usize {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let part = &mut self.parts[header.sequence as usize];
+        if part.is_none() {
+            *part = Some(Bytes::copy_from_slice(&datagram[UdpFrameHeader::size()..]));
+            self.received += 1;
+        }
+        if self.received < self.parts.len() {
+            return Ok(None);
+        }
+        let mut payload = BytesMut::new();
+        for part in self.parts.drain(..) {
+            payload.put(part.unwrap());
+        }
+        self.received = 0;
+        Ok(Some(payload.freeze()))
+    }
+
+    /// Feed one datagram and decode every response `Packet` of the payload once it is complete;
+    /// a multi-get answers with several packets in one response.
+    pub fn push_packet(&mut self, datagram: &[u8]) -> io::Result<Option<Vec<Packet>>> {
+        let payload = match self.push(datagram)? {
+            Some(payload) => payload,
+            None => return Ok(None),
+        };
+        let mut rest = payload.bytes();
+        let mut packets = Vec::new();
+        while !rest.is_empty() {
+            packets.push(Packet::read_from(&mut rest)?);
+        }
+        Ok(Some(packets))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fragment, fragment_packet, Reassembler, UdpFrameHeader};
+    use crate::packet::{Packet, SyncOps};
+    use crate::{Opcode, Status};
+    use std::io;
+
+    fn get_hit(value: &'static [u8]) -> Packet {
+        Packet::builder(Opcode::Get)
+            .value(value)
+            .response(Status::NoError)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_fragment_reassemble() {
+        let payload: Vec<u8> = (0..3000u32).map(|i| i as u8).collect();
+        let datagrams = fragment(7, &payload, 1400).unwrap();
+        assert_eq!(datagrams.len(), 3);
+        assert_eq!(
+            UdpFrameHeader::parse(&datagrams[2]),
+            UdpFrameHeader {
+                request_id: 7,
+                sequence: 2,
+                total: 3
+            }
+        );
+
+        let mut reassembler = Reassembler::new(7);
+        assert_eq!(reassembler.push(&datagrams[2]).unwrap(), None);
+        assert_eq!(reassembler.push(&datagrams[0]).unwrap(), None);
+        let whole = reassembler.push(&datagrams[1]).unwrap().unwrap();
+        assert_eq!(&whole[..], &payload[..]);
+    }
+
+    #[test]
+    fn test_fragment_empty_max_payload() {
+        let err = fragment(7, b"payload", 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_duplicate_and_foreign_datagrams() {
+        let payload = [1u8; 20];
+        let datagrams = fragment(7, &payload, 8).unwrap();
+        let foreign = fragment(8, &payload, 8).unwrap();
+        assert_eq!(datagrams.len(), 3);
+
+        let mut reassembler = Reassembler::new(7);
+        assert_eq!(reassembler.push(&datagrams[1]).unwrap(), None);
+        assert_eq!(reassembler.push(&datagrams[1]).unwrap(), None);
+        for datagram in &foreign {
+            assert_eq!(reassembler.push(datagram).unwrap(), None);
+        }
+        assert_eq!(reassembler.push(&datagrams[0]).unwrap(), None);
+        let whole = reassembler.push(&datagrams[2]).unwrap().unwrap();
+        assert_eq!(&whole[..], &payload[..]);
+    }
+
+    #[test]
+    fn test_inconsistent_total() {
+        let mut reassembler = Reassembler::new(7);
+        reassembler.push(&fragment(7, &[0; 20], 8).unwrap()[0]).unwrap();
+        let err = reassembler
+            .push(&fragment(7, &[0; 20], 4).unwrap()[1])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_push_packets() {
+        let mut payload = Vec::new();
+        get_hit(b"a").write_to(&mut payload).unwrap();
+        get_hit(b"b").write_to(&mut payload).unwrap();
+        let datagrams = fragment(7, &payload, 16).unwrap();
+
+        let mut reassembler = Reassembler::new(7);
+        let mut packets = None;
+        for datagram in datagrams.iter().rev() {
+            packets = reassembler.push_packet(datagram).unwrap();
+        }
+        let packets = packets.unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(&packets[1].val[..], b"b");
+
+        let mut reassembler = Reassembler::new(9);
+        let datagrams = fragment_packet(9, &get_hit(b"c")).unwrap();
+        let packets = reassembler.push_packet(&datagrams[0]).unwrap().unwrap();
+        assert_eq!(packets.len(), 1);
+    }
+}
@@ -0,0 +1,194 @@
+//! Conversion of typed values to and from stored bytes and flags, used by `get_as`/`set_value`;
+//! the flags follow `interop::pymemcache`, integers are ASCII decimal so `increment`/`decrement` apply to them.
+use crate::interop::pymemcache;
+use crate::Result;
+use bytes::Bytes;
+use std::borrow::Cow;
+use std::io;
+
+fn invalid(expected: &str, flags: u32) -> crate::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("value with flags {:#x} is not {}", flags, expected),
+    )
+    .into()
+}
+
+/// Flags of the values stored by `set_json`, a bit `interop::pymemcache` leaves unused
+#[cfg(feature = "json")]
+pub const JSON_FLAGS: u32 = 1 << 5;
+
+#[cfg(feature = "json")]
+pub(crate) fn to_json<T: serde::Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(value).map_err(io::Error::from)?)
+}
+
+#[cfg(feature = "json")]
+pub(crate) fn from_json<T: serde::de::DeserializeOwned>(value: &[u8], flags: u32) -> Result<T> {
+    if flags != JSON_FLAGS {
+        return Err(invalid("JSON", flags));
+    }
+    Ok(serde_json::from_slice(value).map_err(io::Error::from)?)
+}
+
+/// A value that can be stored
+pub trait ToMemcachedValue {
+    /// Flags marking the type of the value
+    fn flags(&self) -> u32;
+    fn to_value(&self) -> Cow<'_, [u8]>;
+}
+
+/// A value that can be read back; fails with an `InvalidData` error if `flags` mark another type
+/// or the bytes don't hold a `Self`
+pub trait FromMemcachedValue: Sized {
+    fn from_value(value: Bytes, flags: u32) -> Result<Self>;
+}
+
+impl ToMemcachedValue for [u8] {
+    fn flags(&self) -> u32 {
+        pymemcache::BYTES
+    }
+    fn to_value(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl ToMemcachedValue for Vec<u8> {
+    fn flags(&self) -> u32 {
+        pymemcache::BYTES
+    }
+    fn to_value(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl ToMemcachedValue for Bytes {
+    fn flags(&self) -> u32 {
+        pymemcache::BYTES
+    }
+    fn to_value(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl ToMemcachedValue for str {
+    fn flags(&self) -> u32 {
+        pymemcache::TEXT
+    }
+    fn to_value(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl ToMemcachedValue for String {
+    fn flags(&self) -> u32 {
+        pymemcache::TEXT
+    }
+    fn to_value(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl<T: ToMemcachedValue + ?Sized> ToMemcachedValue for &T {
+    fn flags(&self) -> u32 {
+        (**self).flags()
+    }
+    fn to_value(&self) -> Cow<'_, [u8]> {
+        (**self).to_value()
+    }
+}
+
+/// Any flags, the bytes are returned as stored
+impl FromMemcachedValue for Bytes {
+    fn from_value(value: Bytes, _flags: u32) -> Result<Self> {
+        Ok(value)
+    }
+}
+
+/// Any flags, the bytes are returned as stored
+impl FromMemcachedValue for Vec<u8> {
+    fn from_value(value: Bytes, _flags: u32) -> Result<Self> {
+        Ok(value.to_vec())
+    }
+}
+
+/// Text or bytes holding UTF-8
+impl FromMemcachedValue for String {
+    fn from_value(value: Bytes, flags: u32) -> Result<Self> {
+        match flags {
+            pymemcache::TEXT | pymemcache::BYTES => {
+                String::from_utf8(value.to_vec()).map_err(|_| invalid("UTF-8 text", flags))
+            }
+            _ => Err(invalid("text", flags)),
+        }
+    }
+}
+
+macro_rules! integer_value {
+    ($($t:ty),*) => {
+        $(
+            impl ToMemcachedValue for $t {
+                fn flags(&self) -> u32 {
+                    pymemcache::INTEGER
+                }
+                fn to_value(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(self.to_string().into_bytes())
+                }
+            }
+
+            /// Integers, or bytes holding one as set by `increment`/`decrement`
+            impl FromMemcachedValue for $t {
+                fn from_value(value: Bytes, flags: u32) -> Result<Self> {
+                    match flags {
+                        pymemcache::INTEGER | pymemcache::LONG | pymemcache::BYTES => {
+                            std::str::from_utf8(&value)
+                                .ok()
+                                .and_then(|s| s.trim().parse().ok())
+                                .ok_or_else(|| invalid(stringify!($t), flags))
+                        }
+                        _ => Err(invalid(stringify!($t), flags)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+integer_value!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: ToMemcachedValue + FromMemcachedValue>(v: &T) -> Result<T> {
+        T::from_value(Bytes::copy_from_slice(&v.to_value()), v.flags())
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        assert_eq!(round_trip(&"abc".to_string()).unwrap(), "abc");
+        assert_eq!(round_trip(&b"\xff\x00".to_vec()).unwrap(), b"\xff\x00");
+        assert_eq!(round_trip(&-42i64).unwrap(), -42);
+        assert_eq!(round_trip(&u64::MAX).unwrap(), u64::MAX);
+        assert_eq!(42u32.to_value(), &b"42"[..]);
+        assert_eq!("abc".flags(), pymemcache::TEXT);
+
+        // counters created by increment have no type flag
+        assert_eq!(u64::from_value(Bytes::from_static(b"7"), 0).unwrap(), 7);
+        assert!(u8::from_value(Bytes::from_static(b"256"), pymemcache::INTEGER).is_err());
+        assert!(String::from_value(Bytes::from_static(b"1"), pymemcache::INTEGER).is_err());
+        assert!(String::from_value(Bytes::from_static(b"\xff"), pymemcache::BYTES).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        let value = to_json(&(1, "a")).unwrap();
+        assert_eq!(value, br#"[1,"a"]"#);
+        assert_eq!(
+            from_json::<(u8, String)>(&value, JSON_FLAGS).unwrap(),
+            (1, "a".into())
+        );
+        assert!(from_json::<(u8, String)>(&value, pymemcache::TEXT).is_err());
+    }
+}
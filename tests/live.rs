@@ -0,0 +1,260 @@
+//! Wire compatibility against a real memcached server:
+//!
+//! ```text
+//! MEMCACHED_ADDR=127.0.0.1:11211 MEMCACHED_FLAVOR=1.6 \
+//!     cargo test --features live-tests --test live -- --nocapture
+//! ```
+//!
+//! `MEMCACHED_FLAVOR` is the `major.minor[.patch]` release of the server (default `1.6`),
+//! checks for commands newer than that are skipped. Set `MEMCACHED_ALLOW_FLUSH`
+//! to also run the flush checks, which wipe the server.
+#![cfg(feature = "live-tests")]
+
+use bytes::Bytes;
+use futures_lite::future::block_on;
+use futures_lite::{AsyncRead, AsyncWrite};
+use memcached_proto::client::{BinaryProto, Proto};
+use memcached_proto::{Error, Extras, Opcode, Packet, Status};
+use semver::Version;
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Blocking `TcpStream` as an async stream, the checks run one at a time under `block_on`
+struct Blocking(TcpStream);
+
+impl AsyncRead for Blocking {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().0.read(buf))
+    }
+}
+
+impl AsyncWrite for Blocking {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().0.write(buf))
+    }
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.get_mut().0.flush())
+    }
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.get_mut().0.shutdown(std::net::Shutdown::Write))
+    }
+}
+
+type Client = BinaryProto<Blocking>;
+
+fn connect(addr: &str) -> Client {
+    BinaryProto::new(Blocking(TcpStream::connect(addr).unwrap()))
+}
+
+fn check<T>(result: memcached_proto::Result<T>) -> Result<T, String> {
+    result.map_err(|e| e.to_string())
+}
+
+fn expect_status<T: std::fmt::Debug>(
+    result: memcached_proto::Result<T>,
+    status: Status,
+) -> Result<(), String> {
+    match result {
+        Err(Error::Proto(e)) if e.status() == status => Ok(()),
+        other => Err(format!("expected {:?}, got {:?}", status, other)),
+    }
+}
+
+fn expect_eq<T: PartialEq + std::fmt::Debug>(actual: T, expected: T) -> Result<(), String> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("expected {:?}, got {:?}", expected, actual))
+    }
+}
+
+const KEY: &[u8] = b"test:live:key";
+const COUNTER: &[u8] = b"test:live:counter";
+
+/// (name, first release with the commands, destructive, check)
+type Check<C> = (
+    &'static str,
+    (u64, u64, u64),
+    bool,
+    fn(&mut C) -> Result<(), String>,
+);
+
+/// The checks, through the client traits only
+fn checks<C: Proto + Send>() -> Vec<Check<C>> {
+    vec![
+        ("set/get", (1, 4, 0), false, |c| {
+            check(block_on(c.set(KEY, b"v", 7, 0)))?;
+            let (value, flags) = check(block_on(c.get(KEY)))?;
+            expect_eq((&value[..], flags), (&b"v"[..], 7))
+        }),
+        ("getk", (1, 4, 0), false, |c| {
+            check(block_on(c.set(KEY, b"v", 0, 0)))?;
+            let (key, value, _) = check(block_on(c.getk(KEY)))?;
+            expect_eq((&key[..], &value[..]), (KEY, &b"v"[..]))
+        }),
+        ("get miss", (1, 4, 0), false, |c| {
+            let _ = block_on(c.delete(KEY));
+            expect_status(block_on(c.get(KEY)), Status::KeyNotFound)
+        }),
+        ("add", (1, 4, 0), false, |c| {
+            let _ = block_on(c.delete(KEY));
+            check(block_on(c.add(KEY, b"v", 0, 0)))?;
+            expect_status(block_on(c.add(KEY, b"v", 0, 0)), Status::KeyExits)
+        }),
+        ("replace", (1, 4, 0), false, |c| {
+            let _ = block_on(c.delete(KEY));
+            expect_status(block_on(c.replace(KEY, b"v", 0, 0)), Status::KeyNotFound)?;
+            check(block_on(c.set(KEY, b"v", 0, 0)))?;
+            check(block_on(c.replace(KEY, b"w", 0, 0)))
+        }),
+        ("append/prepend", (1, 4, 0), false, |c| {
+            check(block_on(c.set(KEY, b"b", 0, 0)))?;
+            check(block_on(c.append(KEY, b"c")))?;
+            check(block_on(c.prepend(KEY, b"a")))?;
+            expect_eq(check(block_on(c.get(KEY)))?.0, Bytes::from_static(b"abc"))
+        }),
+        ("cas", (1, 4, 0), false, |c| {
+            check(block_on(c.set(KEY, b"v", 0, 0)))?;
+            let (_, _, cas) = check(block_on(c.get_cas(KEY)))?;
+            expect_status(
+                block_on(c.set_cas(KEY, b"w", 0, 0, cas.wrapping_add(1))),
+                Status::KeyExits,
+            )?;
+            check(block_on(c.set_cas(KEY, b"w", 0, 0, cas))).map(drop)
+        }),
+        ("delete", (1, 4, 0), false, |c| {
+            check(block_on(c.set(KEY, b"v", 0, 0)))?;
+            check(block_on(c.delete(KEY)))?;
+            expect_status(block_on(c.delete(KEY)), Status::KeyNotFound)
+        }),
+        ("increment/decrement", (1, 4, 0), false, |c| {
+            let _ = block_on(c.delete(COUNTER));
+            expect_eq(check(block_on(c.increment(COUNTER, 5, 10, 0)))?, 10)?;
+            expect_eq(check(block_on(c.increment(COUNTER, 5, 10, 0)))?, 15)?;
+            expect_eq(check(block_on(c.decrement(COUNTER, 20, 10, 0)))?, 0)
+        }),
+        ("non-numeric increment", (1, 4, 0), false, |c| {
+            check(block_on(c.set(KEY, b"v", 0, 0)))?;
+            expect_status(
+                block_on(c.increment(KEY, 1, 0, 0)),
+                Status::IncrOrDecrOnNonNumericValue,
+            )
+        }),
+        ("touch", (1, 4, 8), false, |c| {
+            check(block_on(c.set(KEY, b"v", 0, 0)))?;
+            check(block_on(c.touch(KEY, 60)))
+        }),
+        ("gat", (1, 4, 8), false, |c| {
+            check(block_on(c.set(KEY, b"v", 3, 0)))?;
+            let (value, flags) = check(block_on(c.get_and_touch(KEY, 60)))?;
+            expect_eq((&value[..], flags), (&b"v"[..], 3))
+        }),
+        ("quiet set", (1, 4, 0), false, |c| {
+            check(block_on(c.set_noreply(KEY, b"q", 0, 0)))?;
+            expect_eq(check(block_on(c.get(KEY)))?.0, Bytes::from_static(b"q"))
+        }),
+        ("multi get", (1, 4, 0), false, |c| {
+            check(block_on(c.set(KEY, b"v", 0, 0)))?;
+            let _ = block_on(c.delete(COUNTER));
+            let found = check(block_on(c.get_multi(&[KEY, COUNTER])))?;
+            expect_eq(found.len(), 1)?;
+            expect_eq(found.get(KEY).map(|v| v.0.clone()), Some("v".into()))
+        }),
+        ("multi set", (1, 4, 0), false, |c| {
+            let mut kv = BTreeMap::new();
+            kv.insert(KEY, (&b"a"[..], 0, 0));
+            kv.insert(COUNTER, (&b"1"[..], 0, 0));
+            check(block_on(c.set_multi(kv)))?;
+            expect_eq(check(block_on(c.get(COUNTER)))?.0, Bytes::from_static(b"1"))
+        }),
+        ("version", (1, 4, 0), false, |c| {
+            check(block_on(c.version())).map(drop)
+        }),
+        ("stat", (1, 4, 0), false, |c| {
+            let stats = check(block_on(c.stat()))?;
+            if stats.contains_key("pid") {
+                Ok(())
+            } else {
+                Err(format!("no pid in {:?}", stats))
+            }
+        }),
+        ("verbosity", (1, 4, 0), false, |c| {
+            check(block_on(c.verbosity(0)))
+        }),
+        ("noop", (1, 4, 0), false, |c| check(block_on(c.noop()))),
+        ("unknown command", (1, 4, 0), false, |c| {
+            let request = Packet::request(
+                Opcode::RGet,
+                0,
+                0,
+                0,
+                Extras::None,
+                Bytes::from_static(KEY),
+                Bytes::new(),
+            );
+            let response = check(block_on(c.execute_raw(request)))?;
+            expect_eq(response.status(), Status::UnknownCommand)
+        }),
+        ("flush", (1, 4, 0), true, |c| {
+            check(block_on(c.set(KEY, b"v", 0, 0)))?;
+            check(block_on(c.flush(0)))?;
+            expect_status(block_on(c.get(KEY)), Status::KeyNotFound)
+        }),
+    ]
+}
+
+fn flavor() -> Version {
+    let flavor = env::var("MEMCACHED_FLAVOR").unwrap_or_else(|_| "1.6".to_string());
+    let mut parts = flavor.split('.').map(|p| p.parse::<u64>().unwrap());
+    Version::new(
+        parts.next().unwrap(),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    )
+}
+
+#[test]
+fn test_wire_compatibility() {
+    let addr = env::var("MEMCACHED_ADDR").unwrap_or_else(|_| "127.0.0.1:11211".to_string());
+    let flavor = flavor();
+    let allow_flush = env::var_os("MEMCACHED_ALLOW_FLUSH").is_some();
+    let mut client = connect(&addr);
+
+    println!(
+        "wire compatibility report for {} (memcached {})",
+        addr, flavor
+    );
+    let mut failures = 0;
+    for (name, (major, minor, patch), destructive, check) in checks::<Client>() {
+        let since = Version::new(major, minor, patch);
+        let outcome = if since > flavor {
+            format!("skipped, needs {}", since)
+        } else if destructive && !allow_flush {
+            "skipped, set MEMCACHED_ALLOW_FLUSH".to_string()
+        } else {
+            match check(&mut client) {
+                Ok(()) => "ok".to_string(),
+                Err(e) => {
+                    failures += 1;
+                    // the stream may be out of sync now
+                    client = connect(&addr);
+                    format!("FAILED: {}", e)
+                }
+            }
+        };
+        println!("  {:<24} {}", name, outcome);
+    }
+    assert_eq!(failures, 0, "{} compatibility checks failed", failures);
+}